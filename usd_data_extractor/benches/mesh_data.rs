@@ -0,0 +1,75 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use usd_data_extractor::mock::{MockGeomSubset, MockMesh, MockPrim, MockStage};
+use usd_data_extractor::{SceneDiffItem, UsdSceneExtractor};
+
+// XY平面上にGRID_SIZE x GRID_SIZE個の四角形を並べたmesh。
+// 行ごとに帯状にSUBSET_COUNT個のgeomSubsetに分け、それぞれ別のmaterialを割り当てる
+const GRID_SIZE: u32 = 512;
+const SUBSET_COUNT: u32 = 16;
+
+fn high_poly_mesh() -> MockMesh {
+    let row = GRID_SIZE + 1;
+    let points = (0..row * row)
+        .flat_map(|i| [(i % row) as f32, (i / row) as f32, 0.0])
+        .collect();
+    let face_vertex_indices = (0..GRID_SIZE * GRID_SIZE)
+        .flat_map(|face| {
+            let i = face / GRID_SIZE * row + face % GRID_SIZE;
+            [i, i + 1, i + row + 1, i + row]
+        })
+        .collect();
+
+    let rows_per_subset = GRID_SIZE / SUBSET_COUNT;
+    let geom_subsets = (0..SUBSET_COUNT)
+        .map(|subset| {
+            let first_face = subset * rows_per_subset * GRID_SIZE;
+            let geom_subset = MockGeomSubset {
+                indices_type: "typeFaceSet".to_string(),
+                indices: (first_face..first_face + rows_per_subset * GRID_SIZE).collect(),
+                material_path: Some(format!("/Materials/Material{subset}")),
+            };
+            (format!("subset{subset}"), geom_subset)
+        })
+        .collect::<HashMap<_, _>>();
+
+    MockMesh {
+        points: Some(points),
+        face_vertex_indices: Some(face_vertex_indices),
+        face_vertex_counts: Some(vec![4; (GRID_SIZE * GRID_SIZE) as usize]),
+        geom_subsets,
+        ..Default::default()
+    }
+}
+
+fn extract_high_poly_mesh(c: &mut Criterion) {
+    let mut stage = MockStage::new(0.0, 0.0);
+    stage.set_prims(
+        0.0,
+        [("/Mesh".to_string(), MockPrim::Mesh(high_poly_mesh()))],
+    );
+
+    // 全てのfaceがgeomSubsetに振り分けられていることを確認しておく
+    let diff = UsdSceneExtractor::from_mock_stage(stage.clone()).extract(0.0);
+    let Some(SceneDiffItem::MeshCreated(_, _, mesh)) = diff.items.first() else {
+        panic!("mesh was not extracted: {:?}", diff.warnings);
+    };
+    assert_eq!(mesh.sub_meshes.len(), SUBSET_COUNT as usize);
+
+    let mut group = c.benchmark_group("mesh_data");
+    group.sample_size(10);
+    group.measurement_time(Duration::from_secs(10));
+    group.bench_function("extract_high_poly_mesh_with_subsets", |b| {
+        b.iter_batched(
+            || UsdSceneExtractor::from_mock_stage(stage.clone()),
+            |mut extractor| black_box(extractor.extract(0.0)),
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, extract_high_poly_mesh);
+criterion_main!(benches);
@@ -1,41 +1,11 @@
 use std::env;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
-fn build_windows(manifest_dir: &str, usd_dir: &str, usd_dst: &str, target_dir: PathBuf) {
-    // get python path
-    let python = which::which("python").unwrap();
-    let python = python.to_str().unwrap();
-
-    // build OpenUSD
-    let output = Command::new("cmd")
-        .env("PYTHONUTF8", "1")
-        .arg("/c")
-        .args([
-            r#"cd /d C:\Program Files\Microsoft Visual Studio\2022\Community\Common7\Tools"#,
-            "&&",
-            "VsDevCmd.bat",
-            "-arch=x64",
-            "-host_arch=x64",
-            "&&",
-            python,
-            &format!(r#"{usd_dir}\build_scripts\build_usd.py"#),
-            "--no-python",
-            usd_dst,
-        ])
-        .output()
-        .expect("failed to execute build process");
-    if !output.status.success() {
-        println!("status: {}", output.status);
-        println!("stdout: {}", String::from_utf8_lossy(&output.stdout));
-        println!("stderr: {}", String::from_utf8_lossy(&output.stderr));
-        panic!("failed to build OpenUSD");
-    }
-
-    // collect cpp files
+fn collect_cpp_files(manifest_dir: &str) -> Vec<String> {
     let mut cpp_files = vec![];
-    for f in fs::read_dir("cpp").unwrap() {
+    for f in fs::read_dir(format!("{manifest_dir}/cpp")).unwrap() {
         let f = f.unwrap();
         let f = f.file_name();
         let f = f.to_str().unwrap();
@@ -43,9 +13,226 @@ fn build_windows(manifest_dir: &str, usd_dir: &str, usd_dst: &str, target_dir: P
             cpp_files.push(format!("{manifest_dir}/cpp/{f}"));
         }
     }
+    cpp_files
+}
+
+// OpenUSD libraries in dependency order, dependents first.
+// static libraries have to be passed to the linker in this order,
+// since it resolves symbols in a single pass.
+const USD_LIBRARIES: &[&str] = &[
+    "usdAppUtils",
+    "usdImagingGL",
+    "usdProcImaging",
+    "usdRiPxrImaging",
+    "usdRiImaging",
+    "usdSkelImaging",
+    "usdVolImaging",
+    "usdImaging",
+    "hdx",
+    "hdSt",
+    "hdMtlx",
+    "hdsi",
+    "hdGp",
+    "hdar",
+    "hd",
+    "hgiInterop",
+    "hgiGL",
+    "hgi",
+    "glf",
+    "geomUtil",
+    "pxOsd",
+    "cameraUtil",
+    "hio",
+    "hf",
+    "garch",
+    "usdMtlx",
+    "usdPhysics",
+    "usdUtils",
+    "usdUI",
+    "usdSkel",
+    "usdRi",
+    "usdHydra",
+    "usdRender",
+    "usdProc",
+    "usdLux",
+    "usdShade",
+    "usdMedia",
+    "usdVol",
+    "usdGeom",
+    "usd",
+    "pcp",
+    "sdr",
+    "ndr",
+    "sdf",
+    "kind",
+    "ar",
+    "ts",
+    "vt",
+    "plug",
+    "work",
+    "trace",
+    "js",
+    "gf",
+    "tf",
+    "arch",
+];
+
+// name of the OpenUSD library `name` to pass to the linker,
+// with or without the `usd_` prefix depending on how OpenUSD was built
+fn usd_library_name(lib_dir: &Path, name: &str, extension: &str) -> Option<String> {
+    ["usd_", ""]
+        .iter()
+        .map(|usd_prefix| format!("{usd_prefix}{name}"))
+        .find(|name| lib_dir.join(format!("lib{name}.{extension}")).exists())
+}
+
+fn copy_usd_plugin_resources(usd_dst: &Path, profile_dir: &Path) {
+    let usd_path = usd_dst.to_str().unwrap();
+    for pattern in ["json", "usda"] {
+        let pattern = format!("{usd_path}/lib/usd/**/*.{pattern}");
+        for path in glob::glob(&pattern).unwrap().flatten() {
+            let relative_path = path.strip_prefix(usd_dst.join("lib")).unwrap();
+            if let Some(parent) = relative_path.parent() {
+                fs::create_dir_all(profile_dir.join(parent)).unwrap();
+            }
+            fs::copy(&path, profile_dir.join(relative_path)).unwrap();
+        }
+    }
+}
+
+// find a prebuilt OpenUSD install prefix
+// looks at USD_ROOT, pxr_DIR and CMAKE_PREFIX_PATH in this order.
+// if nothing is found, the OpenUSD submodule is built instead.
+fn find_prebuilt_usd() -> Option<PathBuf> {
+    println!("cargo:rerun-if-env-changed=USD_ROOT");
+    println!("cargo:rerun-if-env-changed=pxr_DIR");
+    println!("cargo:rerun-if-env-changed=CMAKE_PREFIX_PATH");
+
+    // if USD_ROOT is set explicitly, don't look anywhere else
+    if let Some(usd_root) = env::var_os("USD_ROOT") {
+        return Some(PathBuf::from(usd_root));
+    }
+
+    // pxr_DIR points to the directory containing pxrConfig.cmake
+    if let Some(pxr_dir) = env::var_os("pxr_DIR") {
+        let pxr_dir = PathBuf::from(pxr_dir);
+        if pxr_dir.join("pxrConfig.cmake").exists() {
+            return Some(pxr_dir);
+        }
+    }
+
+    if let Some(prefix_paths) = env::var_os("CMAKE_PREFIX_PATH") {
+        for prefix in env::split_paths(&prefix_paths) {
+            if prefix.join("pxrConfig.cmake").exists() || prefix.join("include/pxr/pxr.h").exists()
+            {
+                return Some(prefix);
+            }
+        }
+    }
+
+    None
+}
+
+fn has_usd_library(lib_dir: &Path, name: &str) -> bool {
+    let extensions: &[&str] = if cfg!(target_os = "windows") {
+        &["lib"]
+    } else {
+        &["so", "a"]
+    };
+    let lib_prefix = if cfg!(target_os = "windows") {
+        ""
+    } else {
+        "lib"
+    };
+    ["usd_", ""].iter().any(|usd_prefix| {
+        extensions.iter().any(|ext| {
+            lib_dir
+                .join(format!("{lib_prefix}{usd_prefix}{name}.{ext}"))
+                .exists()
+        })
+    })
+}
+
+// check that the OpenUSD install has the headers and libraries this crate needs
+// and fail the build naming the first missing one
+fn validate_usd_install(usd_root: &Path) {
+    let include_dir = usd_root.join("include");
+    for header in [
+        "pxr/pxr.h",
+        "pxr/usd/usd/stage.h",
+        "pxr/imaging/hd/sceneIndexObserver.h",
+        "pxr/usdImaging/usdImaging/sceneIndices.h",
+        "pxr/usd/usdSkel/cache.h",
+    ] {
+        if !include_dir.join(header).exists() {
+            panic!(
+                "OpenUSD install at {} is missing header `{header}` \
+                 (OpenUSD 23.11 or later is required)",
+                usd_root.display()
+            );
+        }
+    }
+
+    let lib_dir = usd_root.join("lib");
+    if has_usd_library(&lib_dir, "ms") {
+        // monolithic build
+        return;
+    }
+    for library in ["tf", "sdf", "usd", "usdGeom", "usdSkel", "hd", "usdImaging"] {
+        if !has_usd_library(&lib_dir, library) {
+            panic!(
+                "OpenUSD install at {} is missing library `{library}` in {}",
+                usd_root.display(),
+                lib_dir.display()
+            );
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn build_windows(
+    manifest_dir: &str,
+    usd_dir: &str,
+    usd_dst: &str,
+    target_dir: PathBuf,
+    prebuilt: bool,
+) {
+    // build OpenUSD
+    // skip the build if a prebuilt OpenUSD install has been found
+    if !prebuilt {
+        // get python path
+        let python = which::which("python").unwrap();
+        let python = python.to_str().unwrap();
+
+        let output = Command::new("cmd")
+            .env("PYTHONUTF8", "1")
+            .arg("/c")
+            .args([
+                r#"cd /d C:\Program Files\Microsoft Visual Studio\2022\Community\Common7\Tools"#,
+                "&&",
+                "VsDevCmd.bat",
+                "-arch=x64",
+                "-host_arch=x64",
+                "&&",
+                python,
+                &format!(r#"{usd_dir}\build_scripts\build_usd.py"#),
+                "--no-python",
+                usd_dst,
+            ])
+            .output()
+            .expect("failed to execute build process");
+        if !output.status.success() {
+            println!("status: {}", output.status);
+            println!("stdout: {}", String::from_utf8_lossy(&output.stdout));
+            println!("stderr: {}", String::from_utf8_lossy(&output.stderr));
+            panic!("failed to build OpenUSD");
+        }
+    }
 
-    // build CXX bridge
     let usd_dst = PathBuf::from(usd_dst);
+    validate_usd_install(&usd_dst);
+
+    // build CXX bridge
     let mut vcvars = vcvars::Vcvars::new();
     let vcvars_include = vcvars.get_cached("INCLUDE").unwrap();
     cxx_build::bridge("src/bridge.rs")
@@ -59,8 +246,12 @@ fn build_windows(manifest_dir: &str, usd_dir: &str, usd_dst: &str, target_dir: P
         .flag_if_supported("/utf-8")
         .includes(env::split_paths(&*vcvars_include))
         .include(usd_dst.join("include"))
-        .include(usd_dst.join("include/boost-1_78"))
-        .files(cpp_files)
+        .includes(
+            glob::glob(&format!("{}/include/boost-*", usd_dst.display()))
+                .unwrap()
+                .flatten(),
+        )
+        .files(collect_cpp_files(manifest_dir))
         .compile("usd-data-extractor-cpp");
 
     println!("cargo:rerun-if-changed={manifest_dir}/src/bridge.rs");
@@ -93,27 +284,121 @@ fn build_windows(manifest_dir: &str, usd_dir: &str, usd_dst: &str, target_dir: P
             fs::copy(&path, profile_dir.join(path.file_name().unwrap())).unwrap();
         }
     }
-    for entry in glob::glob(&format!("{usd_path}/lib/usd/**/*.json")).unwrap() {
-        if let Ok(path) = entry {
-            let relative_path = path.strip_prefix(&usd_dst.join("lib")).unwrap();
-            if let Some(parent) = relative_path.parent() {
-                fs::create_dir_all(profile_dir.join(parent)).unwrap();
-            }
-            fs::copy(&path, profile_dir.join(relative_path)).unwrap();
+    copy_usd_plugin_resources(&usd_dst, &profile_dir);
+}
+
+#[cfg(target_os = "linux")]
+fn build_linux(
+    manifest_dir: &str,
+    usd_dir: &str,
+    usd_dst: &str,
+    target_dir: PathBuf,
+    prebuilt: bool,
+) {
+    let usd_dst = PathBuf::from(usd_dst);
+
+    // build OpenUSD
+    // skip the build if a prebuilt OpenUSD install has been found
+    // or OpenUSD has already been installed to target/OpenUSD
+    if !prebuilt && !usd_dst.join("include/pxr/pxr.h").exists() {
+        // get python path
+        let python = which::which("python3")
+            .or_else(|_| which::which("python"))
+            .unwrap();
+        let python = python.to_str().unwrap();
+
+        let output = Command::new(python)
+            .env("PYTHONUTF8", "1")
+            .arg(format!("{usd_dir}/build_scripts/build_usd.py"))
+            .arg("--no-python")
+            .arg(&usd_dst)
+            .output()
+            .expect("failed to execute build process");
+        if !output.status.success() {
+            println!("status: {}", output.status);
+            println!("stdout: {}", String::from_utf8_lossy(&output.stdout));
+            println!("stderr: {}", String::from_utf8_lossy(&output.stderr));
+            panic!("failed to build OpenUSD");
         }
     }
-    for entry in glob::glob(&format!("{usd_path}/lib/usd/**/*.usda")).unwrap() {
-        if let Ok(path) = entry {
-            let relative_path = path.strip_prefix(&usd_dst.join("lib")).unwrap();
-            if let Some(parent) = relative_path.parent() {
-                fs::create_dir_all(profile_dir.join(parent)).unwrap();
-            }
-            fs::copy(&path, profile_dir.join(relative_path)).unwrap();
-        }
+    validate_usd_install(&usd_dst);
+
+    // build CXX bridge
+    cxx_build::bridge("src/bridge.rs")
+        .cpp(true)
+        .debug(false)
+        .warnings(false)
+        .flag("-w")
+        .flag_if_supported("-std=c++20")
+        .include(usd_dst.join("include"))
+        .files(collect_cpp_files(manifest_dir))
+        .compile("usd-data-extractor-cpp");
+
+    println!("cargo:rerun-if-changed={manifest_dir}/src/bridge.rs");
+    println!("cargo:rerun-if-changed={manifest_dir}/cpp/");
+
+    // link OpenUSD libraries
+    // only the OpenUSD libraries and tbb are linked, since a prebuilt install prefix
+    // such as /usr also contains many unrelated libraries.
+    // prefer shared libraries and fall back to static libraries
+    let usd_lib = usd_dst.join("lib");
+    println!("cargo:rustc-link-search={}", usd_lib.display());
+    let shared = ["ms"]
+        .iter()
+        .chain(USD_LIBRARIES)
+        .any(|name| usd_library_name(&usd_lib, name, "so").is_some());
+    let (kind, extension) = if shared {
+        ("dylib", "so")
+    } else {
+        ("static", "a")
+    };
+    let names = match usd_library_name(&usd_lib, "ms", extension) {
+        // monolithic build
+        Some(name) => vec![name],
+        // static libraries have to be linked in dependency order
+        None => USD_LIBRARIES
+            .iter()
+            .filter_map(|name| usd_library_name(&usd_lib, name, extension))
+            .collect(),
+    };
+    for name in names {
+        println!("cargo:rustc-link-lib={kind}={name}");
+    }
+    if usd_lib.join(format!("libtbb.{extension}")).exists() {
+        println!("cargo:rustc-link-lib={kind}=tbb");
+    }
+    if shared {
+        // look up shared libraries next to the binary or in the install dir at runtime
+        println!("cargo:rustc-link-arg=-Wl,-rpath,$ORIGIN");
+        println!("cargo:rustc-link-arg=-Wl,-rpath,{}", usd_lib.display());
     }
+
+    // copy shared libraries, usd plugin json and usda files to profile dir
+    // only for the bundled build in target/OpenUSD.
+    // a prebuilt install is used in place through the rpath above
+    if prebuilt {
+        return;
+    }
+    let profile = env::var("PROFILE").unwrap();
+    let profile_dir = target_dir.join(profile);
+    let usd_path = usd_dst.to_str().unwrap();
+    for path in glob::glob(&format!("{usd_path}/lib/*.so*"))
+        .unwrap()
+        .flatten()
+    {
+        fs::copy(&path, profile_dir.join(path.file_name().unwrap())).unwrap();
+    }
+    copy_usd_plugin_resources(&usd_dst, &profile_dir);
 }
 
 fn main() {
+    // the mock backend doesn't use OpenUSD, so there is nothing to build.
+    // this only helps if the build script itself compiles without OpenUSD's toolchain,
+    // so keep platform specific dependencies such as vcvars behind #[cfg]
+    if env::var_os("CARGO_FEATURE_MOCK").is_some() {
+        return;
+    }
+
     let manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
     let usd_dir = PathBuf::from(&manifest_dir)
         .parent()
@@ -124,12 +409,16 @@ fn main() {
         .unwrap()
         .join("target");
     let usd_dir = usd_dir.to_str().unwrap();
-    let usd_dst = PathBuf::from(&target_dir).join("OpenUSD");
+    let prebuilt_usd = find_prebuilt_usd();
+    let prebuilt = prebuilt_usd.is_some();
+    let usd_dst = prebuilt_usd.unwrap_or_else(|| PathBuf::from(&target_dir).join("OpenUSD"));
     let usd_dst_str = usd_dst.to_str().unwrap();
 
-    if cfg!(target_os = "windows") {
-        build_windows(&manifest_dir, &usd_dir, &usd_dst_str, target_dir);
-    } else {
-        panic!("Unsupported platform");
-    }
+    // vcvars is only available on windows, so each platform's build is compiled only there
+    #[cfg(target_os = "windows")]
+    build_windows(&manifest_dir, &usd_dir, &usd_dst_str, target_dir, prebuilt);
+    #[cfg(target_os = "linux")]
+    build_linux(&manifest_dir, usd_dir, usd_dst_str, target_dir, prebuilt);
+    #[cfg(not(any(target_os = "windows", target_os = "linux")))]
+    panic!("Unsupported platform");
 }
@@ -0,0 +1,489 @@
+#include "instancerObserver.h"
+#include "pxr/base/gf/matrix4d.h"
+#include "pxr/base/gf/quatd.h"
+#include "pxr/base/gf/vec3d.h"
+#include "pxr/base/vt/types.h"
+#include "pxr/imaging/hd/dataSourceTypeDefs.h"
+#include "usd_data_extractor/src/bridge.rs.h"
+
+// instanceごとのtransformのprimvarの名前。
+// OpenUSDのバージョンによって"hydra:"のprefixが付いていない場合がある
+static const std::vector<std::string> _InstanceTransformNames = {
+  "instanceTransforms",
+  "instanceTranslations",
+  "instanceRotations",
+  "instanceScales",
+};
+
+static bool
+_IsInstanceTransformName(const TfToken& name)
+{
+  for (const auto& transformName : _InstanceTransformNames) {
+    if (name.GetString() == transformName ||
+        name.GetString() == "hydra:" + transformName) {
+      return true;
+    }
+  }
+  return false;
+}
+
+// indexedなprimvarの値をindicesで展開する。Tの配列でなければfalseを返す
+template<typename T>
+static bool
+_Flatten(VtValue& value, const VtIntArray& indices)
+{
+  if (!value.IsHolding<VtArray<T>>()) {
+    return false;
+  }
+  const auto& values = value.UncheckedGet<VtArray<T>>();
+  VtArray<T> flattened(indices.size());
+  for (size_t i = 0; i < indices.size(); i++) {
+    auto index = indices[i];
+    if (index >= 0 && static_cast<size_t>(index) < values.size()) {
+      flattened[i] = values[index];
+    }
+  }
+  value = VtValue(flattened);
+  return true;
+}
+
+// primvarの値を取得する。indexedなprimvarの場合は展開した値を返す
+static VtValue
+_GetPrimvarValue(const HdSceneIndexBase& sceneIndex,
+                 const SdfPath& path,
+                 const HdDataSourceLocator& primvarLocator)
+{
+  auto valueSource = HdSampledDataSource::Cast(sceneIndex.GetDataSource(
+    path, primvarLocator.Append(TfToken("primvarValue"))));
+  if (valueSource) {
+    return valueSource->GetValue(0);
+  }
+
+  auto indexedValueSource = HdSampledDataSource::Cast(sceneIndex.GetDataSource(
+    path, primvarLocator.Append(TfToken("indexedPrimvarValue"))));
+  if (!indexedValueSource) {
+    return VtValue();
+  }
+  auto value = indexedValueSource->GetValue(0);
+  auto indicesSource = HdSampledDataSource::Cast(sceneIndex.GetDataSource(
+    path, primvarLocator.Append(TfToken("indices"))));
+  if (!indicesSource) {
+    return value;
+  }
+  auto indices = indicesSource->GetValue(0).Get<VtIntArray>();
+  if (indices.empty()) {
+    return value;
+  }
+  if (_Flatten<float>(value, indices) || _Flatten<GfVec2f>(value, indices) ||
+      _Flatten<GfVec3f>(value, indices) || _Flatten<GfVec4f>(value, indices) ||
+      _Flatten<GfQuath>(value, indices) || _Flatten<GfQuatf>(value, indices) ||
+      _Flatten<GfMatrix4d>(value, indices)) {
+    return value;
+  }
+  return VtValue();
+}
+
+// instanceごとのtransformのprimvarの値を取得する。
+// "hydra:"のprefixが付いた名前を優先する
+static VtValue
+_GetInstanceTransformValue(const HdSceneIndexBase& sceneIndex,
+                           const SdfPath& path,
+                           const std::string& name)
+{
+  auto primvarsLocator = InstancerObserver::PrimvarsLocator;
+  auto value = _GetPrimvarValue(
+    sceneIndex, path, primvarsLocator.Append(TfToken("hydra:" + name)));
+  if (!value.IsEmpty()) {
+    return value;
+  }
+  return _GetPrimvarValue(
+    sceneIndex, path, primvarsLocator.Append(TfToken(name)));
+}
+
+InstancerObserver::InstancerObserver() {}
+
+InstancerObserver::~InstancerObserver() {}
+
+void
+InstancerObserver::PrimsAdded(
+  const HdSceneIndexBase& sender,
+  const HdSceneIndexObserver::AddedPrimEntries& entries)
+{
+  for (const auto entry : entries) {
+    auto primType = entry.primType;
+
+    if (primType != TypeToken) {
+      continue;
+    }
+
+    // stageに追加されたInstancerを記録する
+    _instancerPaths.insert(entry.primPath);
+
+    if (_removed.find(entry.primPath) != _removed.end()) {
+      // このDiff中ですでにremovedされているDiffがある場合、
+      // removedを取り消してaddedとして扱う
+      _removed.erase(entry.primPath);
+      _added.emplace(entry.primPath);
+    } else if (_dirtied.find(entry.primPath) != _dirtied.end()) {
+      // このDiff中ですでにdirtiedされているDiffがある場合、
+      // dirtiedを取り消してaddedとして扱う
+      _dirtied.erase(entry.primPath);
+      _added.emplace(entry.primPath);
+    } else {
+      // _addedされたInstancerとしてdiffに登録する
+      _added.emplace(entry.primPath);
+    }
+  }
+}
+
+void
+InstancerObserver::PrimsRemoved(
+  const HdSceneIndexBase& sender,
+  const HdSceneIndexObserver::RemovedPrimEntries& entries)
+{
+  for (const auto entry : entries) {
+    // _instancerPathsに記録されていない場合は無視する
+    if (_instancerPaths.find(entry.primPath) == _instancerPaths.end()) {
+      continue;
+    }
+
+    // stageから削除されたInstancerを記録から削除する
+    _instancerPaths.erase(entry.primPath);
+
+    if (_added.find(entry.primPath) != _added.end()) {
+      // このDiff中ですでにaddedされているDiffがある場合、
+      // addedを取り消して差分はなかったことにする
+      _added.erase(entry.primPath);
+    } else if (_dirtied.find(entry.primPath) != _dirtied.end()) {
+      // このDiff中ですでにdirtiedされているDiffがある場合、
+      // そのdirtiedは削除されるので取り消してremovedだけを記録する
+      _dirtied.erase(entry.primPath);
+      _removed.emplace(entry.primPath);
+    } else {
+      // _removedされたInstancerとしてdiffに登録する
+      _removed.emplace(entry.primPath);
+    }
+  }
+}
+
+void
+InstancerObserver::PrimsDirtied(
+  const HdSceneIndexBase& sender,
+  const HdSceneIndexObserver::DirtiedPrimEntries& entries)
+{
+  for (const auto entry : entries) {
+    // _instancerPathsに記録されていない場合は無視する
+    if (_instancerPaths.find(entry.primPath) == _instancerPaths.end()) {
+      continue;
+    }
+
+    // このフレーム中でaddedな場合は、addedですべての情報を送るので追加で差分を送る必要はない
+    // そのため、addedされたInstancerの場合はdirtiedを無視する
+    if (_added.find(entry.primPath) != _added.end()) {
+      continue;
+    }
+
+    // dirtiedされたらdiffに記録する
+    _dirtied.emplace(entry.primPath);
+  }
+}
+
+void
+InstancerObserver::PrimsRenamed(
+  const HdSceneIndexBase& sender,
+  const HdSceneIndexObserver::RenamedPrimEntries& entries)
+{
+  for (const auto entry : entries) {
+    // _instancerPathsに記録されていない場合は無視する
+    if (_instancerPaths.find(entry.oldPrimPath) == _instancerPaths.end()) {
+      continue;
+    }
+
+    // stageからrenameされたInstancerを記録から削除し、新しい名前で記録する
+    _instancerPaths.erase(entry.oldPrimPath);
+    _instancerPaths.insert(entry.newPrimPath);
+
+    // oldPathをremoveする
+    {
+      if (_added.find(entry.oldPrimPath) != _added.end()) {
+        // このDiff中ですでにaddedされているDiffがある場合、
+        // addedを取り消して差分はなかったことにする
+        _added.erase(entry.oldPrimPath);
+      } else if (_dirtied.find(entry.oldPrimPath) != _dirtied.end()) {
+        // このDiff中ですでにdirtiedされているDiffがある場合、
+        // そのdirtiedは削除されるので取り消す
+        _dirtied.erase(entry.oldPrimPath);
+        _removed.emplace(entry.oldPrimPath);
+      } else {
+        // _removedされたInstancerとしてdiffに登録する
+        _removed.emplace(entry.oldPrimPath);
+      }
+    }
+
+    // newPathをaddする
+    {
+      if (_removed.find(entry.newPrimPath) != _removed.end()) {
+        // このDiff中ですでにremovedされているDiffがある場合、
+        // removedを取り消してaddedとして扱う
+        _removed.erase(entry.newPrimPath);
+        _added.emplace(entry.newPrimPath);
+      } else if (_dirtied.find(entry.newPrimPath) != _dirtied.end()) {
+        // このDiff中ですでにdirtiedされているDiffがある場合、
+        // dirtiedを取り消してaddedとして扱う
+        _dirtied.erase(entry.newPrimPath);
+        _added.emplace(entry.newPrimPath);
+      } else {
+        // _addedされたInstancerとしてdiffに登録する
+        _added.emplace(entry.newPrimPath);
+      }
+    }
+  }
+}
+
+void
+InstancerObserver::ClearDiff()
+{
+  // 各種diffの記録をクリアする
+  _added.clear();
+  _removed.clear();
+  _dirtied.clear();
+}
+
+std::vector<float>
+InstancerObserver::_GetInstanceTransforms(const HdSceneIndexBase& sceneIndex,
+                                          const SdfPath& path,
+                                          size_t instanceCount) const
+{
+  auto transformsValue =
+    _GetInstanceTransformValue(sceneIndex, path, "instanceTransforms");
+  auto translationsValue =
+    _GetInstanceTransformValue(sceneIndex, path, "instanceTranslations");
+  auto rotationsValue =
+    _GetInstanceTransformValue(sceneIndex, path, "instanceRotations");
+  auto scalesValue =
+    _GetInstanceTransformValue(sceneIndex, path, "instanceScales");
+
+  auto transforms = transformsValue.GetWithDefault<VtMatrix4dArray>();
+  auto translations = translationsValue.GetWithDefault<VtVec3fArray>();
+  auto scales = scalesValue.GetWithDefault<VtVec3fArray>();
+  // rotationはhalfかfloatのquaternionで、どちらもdoubleにして扱う
+  std::vector<GfQuatd> rotations;
+  if (rotationsValue.IsHolding<VtQuathArray>()) {
+    for (const auto& rotation : rotationsValue.UncheckedGet<VtQuathArray>()) {
+      rotations.push_back(GfQuatd(rotation));
+    }
+  } else if (rotationsValue.IsHolding<VtQuatfArray>()) {
+    for (const auto& rotation : rotationsValue.UncheckedGet<VtQuatfArray>()) {
+      rotations.push_back(GfQuatd(rotation));
+    }
+  }
+
+  // instanceの数は、prototypeが使うinstanceの数とtransformの配列の長さの最大
+  instanceCount = std::max({ instanceCount,
+                             transforms.size(),
+                             translations.size(),
+                             rotations.size(),
+                             scales.size() });
+
+  // Hydraと同じく、instanceTransforms, scale, rotation, translationの順に適用する
+  std::vector<float> data;
+  data.reserve(instanceCount * 16);
+  for (size_t i = 0; i < instanceCount; i++) {
+    GfMatrix4d matrix(1.0);
+    if (i < transforms.size()) {
+      matrix = transforms[i];
+    }
+    if (i < scales.size()) {
+      matrix *= GfMatrix4d().SetScale(GfVec3d(scales[i]));
+    }
+    if (i < rotations.size()) {
+      matrix *= GfMatrix4d().SetRotate(rotations[i]);
+    }
+    if (i < translations.size()) {
+      matrix *= GfMatrix4d().SetTranslate(GfVec3d(translations[i]));
+    }
+    auto matrixArray = matrix.GetArray();
+    for (int j = 0; j < 16; j++) {
+      data.push_back(matrixArray[j]);
+    }
+  }
+  return data;
+}
+
+void
+InstancerObserver::_GetInstancePrimvars(const HdSceneIndexBase& sceneIndex,
+                                        const SdfPath& path,
+                                        size_t instanceCount,
+                                        UsdDataDiff& diff) const
+{
+  auto primvarsSource = HdContainerDataSource::Cast(
+    sceneIndex.GetDataSource(path, PrimvarsLocator));
+  if (!primvarsSource) {
+    return;
+  }
+
+  auto pathString = rust::String(path.GetText());
+  for (const auto& name : primvarsSource->GetNames()) {
+    // instanceごとのtransformは専用のデータとして渡しているので除外する
+    if (_IsInstanceTransformName(name)) {
+      continue;
+    }
+    auto primvarLocator = PrimvarsLocator.Append(name);
+
+    auto interpolationSource =
+      HdSampledDataSource::Cast(sceneIndex.GetDataSource(
+        path, primvarLocator.Append(TfToken("interpolation"))));
+    if (!interpolationSource ||
+        interpolationSource->GetValue(0).Get<TfToken>() !=
+          TfToken("instance")) {
+      continue;
+    }
+
+    auto value = _GetPrimvarValue(sceneIndex, path, primvarLocator);
+
+    // float系の配列のみ対応し、それ以外の型のprimvarは無視する
+    PrimvarType elementType;
+    const float* data;
+    size_t count;
+    size_t componentCount;
+    if (value.IsHolding<VtFloatArray>()) {
+      auto& array = value.UncheckedGet<VtFloatArray>();
+      elementType = PrimvarType::Float;
+      data = array.cdata();
+      count = array.size();
+      componentCount = 1;
+    } else if (value.IsHolding<VtVec2fArray>()) {
+      auto& array = value.UncheckedGet<VtVec2fArray>();
+      elementType = PrimvarType::Float2;
+      data = reinterpret_cast<const float*>(array.cdata());
+      count = array.size();
+      componentCount = 2;
+    } else if (value.IsHolding<VtVec3fArray>()) {
+      auto& array = value.UncheckedGet<VtVec3fArray>();
+      elementType = PrimvarType::Float3;
+      data = reinterpret_cast<const float*>(array.cdata());
+      count = array.size();
+      componentCount = 3;
+    } else if (value.IsHolding<VtVec4fArray>()) {
+      auto& array = value.UncheckedGet<VtVec4fArray>();
+      elementType = PrimvarType::Float4;
+      data = reinterpret_cast<const float*>(array.cdata());
+      count = array.size();
+      componentCount = 4;
+    } else {
+      continue;
+    }
+    // instanceの数と長さが合わないprimvarは使えないので無視する
+    if (count != instanceCount) {
+      continue;
+    }
+    diff.add_or_update_instancer_primvar(
+      pathString,
+      rust::String(name.GetText()),
+      elementType,
+      rust::Slice<const float>(data, count * componentCount));
+  }
+}
+
+void
+InstancerObserver::_UpdateDiff(const HdSceneIndexBase& sceneIndex,
+                               UsdDataDiff& diff,
+                               const SdfPath path) const
+{
+  auto pathString = rust::String(path.GetText());
+
+  diff.add_or_update_instancer(pathString);
+
+  auto transformMatrixSource =
+    sceneIndex.GetDataSource(path, TransformMatrixLocator);
+  if (transformMatrixSource) {
+    auto sampledTransformMatrixSource =
+      HdSampledDataSource::Cast(transformMatrixSource);
+    auto value = sampledTransformMatrixSource->GetValue(0);
+    auto matrix = value.Get<GfMatrix4d>();
+    auto matrixArray = matrix.GetArray();
+    std::array<float, 16> matrixData;
+    for (int i = 0; i < 16; i++) {
+      matrixData[i] = matrixArray[i];
+    }
+    auto data = rust::Slice<const float>(matrixData.data(), 16);
+    diff.add_or_update_instancer_transform_matrix(pathString, data);
+  }
+
+  // maskでfalseになっているinstanceは描画しない
+  VtBoolArray mask;
+  auto maskSource =
+    HdSampledDataSource::Cast(sceneIndex.GetDataSource(path, MaskLocator));
+  if (maskSource) {
+    mask = maskSource->GetValue(0).GetWithDefault<VtBoolArray>();
+  }
+
+  // prototypeごとに、そのprototypeを使うinstanceのindexを取得する
+  VtArray<SdfPath> prototypes;
+  auto prototypesSource = HdSampledDataSource::Cast(
+    sceneIndex.GetDataSource(path, PrototypesLocator));
+  if (prototypesSource) {
+    prototypes =
+      prototypesSource->GetValue(0).GetWithDefault<VtArray<SdfPath>>();
+  }
+  auto instanceIndicesSource = HdVectorDataSource::Cast(
+    sceneIndex.GetDataSource(path, InstanceIndicesLocator));
+  std::vector<std::vector<uint32_t>> prototypeInstanceIndices;
+  size_t instanceCount = 0;
+  for (size_t i = 0; i < prototypes.size(); i++) {
+    std::vector<uint32_t> indices;
+    if (instanceIndicesSource && i < instanceIndicesSource->GetNumElements()) {
+      auto indicesSource =
+        HdSampledDataSource::Cast(instanceIndicesSource->GetElement(i));
+      if (indicesSource) {
+        auto values = indicesSource->GetValue(0).Get<VtIntArray>();
+        for (auto index : values) {
+          if (index < 0) {
+            continue;
+          }
+          instanceCount = std::max(instanceCount, size_t(index) + 1);
+          if (size_t(index) < mask.size() && !mask[index]) {
+            continue;
+          }
+          indices.push_back(index);
+        }
+      }
+    }
+    prototypeInstanceIndices.push_back(indices);
+  }
+
+  auto transforms = _GetInstanceTransforms(sceneIndex, path, instanceCount);
+  diff.add_or_update_instancer_instance_transforms(
+    pathString, rust::Slice<const float>(transforms.data(), transforms.size()));
+
+  for (size_t i = 0; i < prototypes.size(); i++) {
+    const auto& indices = prototypeInstanceIndices[i];
+    diff.add_or_update_instancer_prototype(
+      pathString,
+      rust::String(prototypes[i].GetText()),
+      rust::Slice<const uint32_t>(indices.data(), indices.size()));
+  }
+
+  _GetInstancePrimvars(sceneIndex, path, transforms.size() / 16, diff);
+}
+
+void
+InstancerObserver::GetDiff(const HdSceneIndexBase& sceneIndex,
+                           UsdDataDiff& diff)
+{
+  // addedされたInstancerの情報をdiffに登録する
+  for (const auto& path : _added) {
+    _UpdateDiff(sceneIndex, diff, path);
+  }
+
+  // removedされたInstancerの情報をdiffに登録する
+  for (const auto& path : _removed) {
+    auto pathString = rust::String(path.GetText());
+    diff.destroy_instancer(pathString);
+  }
+
+  // dirtiedされたInstancerの情報をdiffに登録する
+  for (const auto& path : _dirtied) {
+    _UpdateDiff(sceneIndex, diff, path);
+  }
+}
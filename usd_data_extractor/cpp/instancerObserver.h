@@ -0,0 +1,89 @@
+#ifndef INSTANCER_OBSERVER_H
+#define INSTANCER_OBSERVER_H
+
+#include "pxr/imaging/hd/dataSource.h"
+#include "pxr/imaging/hd/sceneIndexObserver.h"
+#include "pxr/pxr.h"
+#include "pxr/usd/sdf/path.h"
+#include "usdDataDiff.h"
+#include <algorithm>
+#include <array>
+#include <iostream>
+#include <set>
+#include <vector>
+
+using namespace pxr;
+
+// primTypeがInstancerの情報を処理してRustにdiffを受け渡すためのクラス。
+// PointInstancerとinstanceableなprimは、どちらもscene indexではInstancerとして流れてくる。
+// prototypeのMeshはMeshObserverから通常のMeshとして渡し、
+// Instancerからはprototypeごとのinstanceの一覧とinstanceごとのtransformを渡す。
+class InstancerObserver
+{
+
+public:
+  InstancerObserver();
+  virtual ~InstancerObserver();
+
+  inline static const TfToken TypeToken = TfToken("instancer");
+
+  inline static const HdDataSourceLocator TransformMatrixLocator =
+    HdDataSourceLocator(TfToken("xform"), TfToken("matrix"));
+  inline static const HdDataSourceLocator PrototypesLocator =
+    HdDataSourceLocator(TfToken("instancerTopology"), TfToken("prototypes"));
+  inline static const HdDataSourceLocator InstanceIndicesLocator =
+    HdDataSourceLocator(TfToken("instancerTopology"),
+                        TfToken("instanceIndices"));
+  inline static const HdDataSourceLocator MaskLocator =
+    HdDataSourceLocator(TfToken("instancerTopology"), TfToken("mask"));
+  inline static const HdDataSourceLocator PrimvarsLocator =
+    HdDataSourceLocator(TfToken("primvars"));
+
+  void PrimsAdded(const HdSceneIndexBase& sender,
+                  const HdSceneIndexObserver::AddedPrimEntries& entries);
+
+  void PrimsRemoved(const HdSceneIndexBase& sender,
+                    const HdSceneIndexObserver::RemovedPrimEntries& entries);
+
+  void PrimsDirtied(const HdSceneIndexBase& sender,
+                    const HdSceneIndexObserver::DirtiedPrimEntries& entries);
+
+  void PrimsRenamed(const HdSceneIndexBase& sender,
+                    const HdSceneIndexObserver::RenamedPrimEntries& entries);
+
+  void ClearDiff();
+
+  void GetDiff(const HdSceneIndexBase& sceneIndex, UsdDataDiff& diff);
+
+private:
+  // stageに存在するInstancerのPathを記録する
+  std::set<SdfPath> _instancerPaths;
+
+  // 前回GetDiffしてClearしてから追加されたInstancerの差分のPathを記録する
+  std::set<SdfPath> _added;
+  // 前回GetDiffしてClearしてから削除されたInstancerのPathを記録する
+  std::set<SdfPath> _removed;
+  // 前回までにGetDiffで追加されたInstancerを記録する
+  std::set<SdfPath> _dirtied;
+
+  // instanceごとのtransformのprimvarを読み、instanceの数だけのtransformを返す
+  std::vector<float> _GetInstanceTransforms(const HdSceneIndexBase& sceneIndex,
+                                            const SdfPath& path,
+                                            size_t instanceCount) const;
+
+  // transform以外のinterpolationがinstanceのprimvarをdiffに登録する
+  void _GetInstancePrimvars(const HdSceneIndexBase& sceneIndex,
+                            const SdfPath& path,
+                            size_t instanceCount,
+                            UsdDataDiff& diff) const;
+
+  void _UpdateDiff(const HdSceneIndexBase& sceneIndex,
+                   UsdDataDiff& diff,
+                   const SdfPath path) const;
+
+  // This class does not support copying.
+  InstancerObserver(const InstancerObserver&) = delete;
+  InstancerObserver& operator=(const InstancerObserver&) = delete;
+};
+
+#endif
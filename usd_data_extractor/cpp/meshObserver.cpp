@@ -1,6 +1,66 @@
 #include "meshObserver.h"
 #include "usd_data_extractor/src/bridge.rs.h"
 
+// Hydraのinterpolationのtokenを変換する
+static std::optional<Interpolation>
+_ToInterpolation(const TfToken& interpolation)
+{
+  if (interpolation == TfToken("constant")) {
+    return Interpolation::Constant;
+  } else if (interpolation == TfToken("uniform")) {
+    return Interpolation::Uniform;
+  } else if (interpolation == TfToken("varying")) {
+    return Interpolation::Varying;
+  } else if (interpolation == TfToken("vertex")) {
+    return Interpolation::Vertex;
+  } else if (interpolation == TfToken("faceVarying")) {
+    return Interpolation::FaceVarying;
+  } else if (interpolation == TfToken("instance")) {
+    return Interpolation::Instance;
+  }
+  return std::nullopt;
+}
+
+// subdivisionTagsの配列の値を取得する。authorされていない場合は空の配列を返す
+template<typename T>
+static VtArray<T>
+_GetSubdivisionTag(const HdSceneIndexBase& sceneIndex,
+                   const SdfPath& path,
+                   const HdDataSourceLocator& locator,
+                   const TfToken& name)
+{
+  auto source = HdSampledDataSource::Cast(
+    sceneIndex.GetDataSource(path, locator.Append(name)));
+  if (!source) {
+    return VtArray<T>();
+  }
+  return source->GetValue(0).GetWithDefault<VtArray<T>>();
+}
+
+// extentのminとmaxを並べた6つの値を取得する。authorされていない場合はnulloptを返す
+static std::optional<std::array<float, 6>>
+_GetExtent(const HdSceneIndexBase& sceneIndex, const SdfPath& path)
+{
+  auto minSource = HdSampledDataSource::Cast(
+    sceneIndex.GetDataSource(path, MeshObserver::ExtentMinLocator));
+  auto maxSource = HdSampledDataSource::Cast(
+    sceneIndex.GetDataSource(path, MeshObserver::ExtentMaxLocator));
+  if (!minSource || !maxSource) {
+    return std::nullopt;
+  }
+  auto minValue = minSource->GetValue(0);
+  auto maxValue = maxSource->GetValue(0);
+  if (!minValue.IsHolding<GfVec3d>() || !maxValue.IsHolding<GfVec3d>()) {
+    return std::nullopt;
+  }
+  auto min = minValue.UncheckedGet<GfVec3d>();
+  auto max = maxValue.UncheckedGet<GfVec3d>();
+  return std::array<float, 6>{
+    float(min[0]), float(min[1]), float(min[2]),
+    float(max[0]), float(max[1]), float(max[2]),
+  };
+}
+
 MeshObserver::MeshObserver() {}
 
 MeshObserver::~MeshObserver() {}
@@ -127,9 +187,19 @@ MeshObserver::PrimsDirtied(
         if (locator.HasPrefix(TransforLocator)) {
           // xformについて差分がある場合、transformのmatrixを再取得する
           _dirtied[primPath].insert(DiffType::TransformMatrix);
+        } else if (locator.HasPrefix(VisibilityLocator)) {
+          // visibilityについて差分がある場合、表示状態を再確認する
+          _dirtied[primPath].insert(DiffType::Visibility);
+        } else if (locator.HasPrefix(PointsLocator) ||
+                   locator.HasPrefix(NormalsLocator) ||
+                   locator.HasPrefix(ExtentLocator)) {
+          // points, normals, extentだけについて差分がある場合、
+          // 頂点位置と法線とextentだけを再取得する
+          _dirtied[primPath].insert(DiffType::Points);
         } else if (locator.HasPrefix(PrimvarsLocator) ||
                    locator.HasPrefix(MaterialBindingsLocator) ||
-                   locator.HasPrefix(MeshLocator)) {
+                   locator.HasPrefix(MeshLocator) ||
+                   locator.HasPrefix(DisplayStyleLocator)) {
           // primvars, materialBindings, meshのいずれかについて差分がある場合、
           // meshの全データを再取得する
           _dirtied[primPath].insert(DiffType::MeshData);
@@ -233,9 +303,317 @@ MeshObserver::ClearDiff()
   _dirtied.clear();
 }
 
+bool
+MeshObserver::_IsVisible(const HdSceneIndexBase& sceneIndex,
+                         const SdfPath& path) const
+{
+  // visibilityのデータがない場合は表示されているものとして扱う
+  auto visibilitySource =
+    sceneIndex.GetDataSource(path, VisibilityDataLocator);
+  auto sampledVisibilitySource = HdSampledDataSource::Cast(visibilitySource);
+  if (!sampledVisibilitySource) {
+    return true;
+  }
+  auto value = sampledVisibilitySource->GetValue(0);
+  return value.GetWithDefault<bool>(true);
+}
+
+void
+MeshObserver::_GetPrimvars(const HdSceneIndexBase& sceneIndex,
+                           const SdfPath& path,
+                           bool created,
+                           UsdDataDiff& diff) const
+{
+  auto primvarsSource = HdContainerDataSource::Cast(
+    sceneIndex.GetDataSource(path, PrimvarsLocator));
+  if (!primvarsSource) {
+    return;
+  }
+
+  auto pathString = rust::String(path.GetText());
+  for (const auto& name : primvarsSource->GetNames()) {
+    // points, normals, stは専用のデータとして渡しているので除外する
+    if (name == TfToken("points") || name == TfToken("normals") ||
+        name == TfToken("st")) {
+      continue;
+    }
+    // skel:jointIndicesなどのskinningの情報はSkelObserverから渡している
+    if (TfStringStartsWith(name.GetString(), "skel:")) {
+      continue;
+    }
+    auto primvarLocator = PrimvarsLocator.Append(name);
+
+    auto interpolationSource =
+      HdSampledDataSource::Cast(sceneIndex.GetDataSource(
+        path, primvarLocator.Append(TfToken("interpolation"))));
+    if (!interpolationSource) {
+      continue;
+    }
+    auto interpolation =
+      _ToInterpolation(interpolationSource->GetValue(0).Get<TfToken>());
+    if (!interpolation) {
+      continue;
+    }
+
+    auto valueSource = HdSampledDataSource::Cast(sceneIndex.GetDataSource(
+      path, primvarLocator.Append(TfToken("indexedPrimvarValue"))));
+    if (!valueSource) {
+      continue;
+    }
+    auto value = valueSource->GetValue(0);
+
+    // float系の配列のみ対応し、それ以外の型のprimvarは無視する
+    PrimvarType elementType;
+    const float* data;
+    size_t size;
+    if (value.IsHolding<VtFloatArray>()) {
+      auto& array = value.UncheckedGet<VtFloatArray>();
+      elementType = PrimvarType::Float;
+      data = array.cdata();
+      size = array.size();
+    } else if (value.IsHolding<VtVec2fArray>()) {
+      auto& array = value.UncheckedGet<VtVec2fArray>();
+      elementType = PrimvarType::Float2;
+      data = reinterpret_cast<const float*>(array.cdata());
+      size = array.size() * 2;
+    } else if (value.IsHolding<VtVec3fArray>()) {
+      auto& array = value.UncheckedGet<VtVec3fArray>();
+      elementType = PrimvarType::Float3;
+      data = reinterpret_cast<const float*>(array.cdata());
+      size = array.size() * 3;
+    } else if (value.IsHolding<VtVec4fArray>()) {
+      auto& array = value.UncheckedGet<VtVec4fArray>();
+      elementType = PrimvarType::Float4;
+      data = reinterpret_cast<const float*>(array.cdata());
+      size = array.size() * 4;
+    } else {
+      continue;
+    }
+    auto nameString = rust::String(name.GetText());
+    auto valuesData = rust::Slice<const float>(data, size);
+    if (created) {
+      diff.create_mesh_primvar(
+        pathString, nameString, elementType, *interpolation, valuesData);
+    } else {
+      diff.diff_mesh_data_primvar(
+        pathString, nameString, elementType, *interpolation, valuesData);
+    }
+
+    auto indicesSource = HdSampledDataSource::Cast(sceneIndex.GetDataSource(
+      path, primvarLocator.Append(TfToken("indices"))));
+    if (indicesSource) {
+      auto indices = indicesSource->GetValue(0).Get<VtIntArray>();
+      std::vector<uint32_t> indicesData(indices.begin(), indices.end());
+      auto indicesSlice =
+        rust::Slice<const uint32_t>(indicesData.data(), indicesData.size());
+      if (created) {
+        diff.create_mesh_primvar_indices(pathString, nameString, indicesSlice);
+      } else {
+        diff.diff_mesh_data_primvar_indices(
+          pathString, nameString, indicesSlice);
+      }
+    }
+  }
+}
+
+void
+MeshObserver::_GetSubdivision(const HdSceneIndexBase& sceneIndex,
+                              const SdfPath& path,
+                              bool created,
+                              UsdDataDiff& diff) const
+{
+  auto pathString = rust::String(path.GetText());
+
+  auto schemeSource = HdSampledDataSource::Cast(
+    sceneIndex.GetDataSource(path, SubdivisionSchemeLocator));
+  if (schemeSource) {
+    auto scheme = schemeSource->GetValue(0).Get<TfToken>();
+    std::optional<SubdivisionScheme> value;
+    if (scheme == TfToken("none")) {
+      value = SubdivisionScheme::None;
+    } else if (scheme == TfToken("catmullClark")) {
+      value = SubdivisionScheme::CatmullClark;
+    } else if (scheme == TfToken("loop")) {
+      value = SubdivisionScheme::Loop;
+    } else if (scheme == TfToken("bilinear")) {
+      value = SubdivisionScheme::Bilinear;
+    }
+    if (value) {
+      if (created) {
+        diff.create_mesh_subdivision_scheme(pathString, *value);
+      } else {
+        diff.diff_mesh_data_subdivision_scheme(pathString, *value);
+      }
+    }
+  }
+
+  auto interpolateBoundarySource =
+    HdSampledDataSource::Cast(sceneIndex.GetDataSource(
+      path, SubdivisionTagsLocator.Append(TfToken("interpolateBoundary"))));
+  if (interpolateBoundarySource) {
+    auto interpolateBoundary =
+      interpolateBoundarySource->GetValue(0).Get<TfToken>();
+    std::optional<BoundaryInterpolation> value;
+    if (interpolateBoundary == TfToken("none")) {
+      value = BoundaryInterpolation::None;
+    } else if (interpolateBoundary == TfToken("edgeOnly")) {
+      value = BoundaryInterpolation::EdgeOnly;
+    } else if (interpolateBoundary == TfToken("edgeAndCorner")) {
+      value = BoundaryInterpolation::EdgeAndCorner;
+    }
+    if (value) {
+      if (created) {
+        diff.create_mesh_interpolate_boundary(pathString, *value);
+      } else {
+        diff.diff_mesh_data_interpolate_boundary(pathString, *value);
+      }
+    }
+  }
+
+  auto faceVaryingSource = HdSampledDataSource::Cast(sceneIndex.GetDataSource(
+    path,
+    SubdivisionTagsLocator.Append(TfToken("faceVaryingLinearInterpolation"))));
+  if (faceVaryingSource) {
+    auto faceVarying = faceVaryingSource->GetValue(0).Get<TfToken>();
+    std::optional<FaceVaryingLinearInterpolation> value;
+    if (faceVarying == TfToken("none")) {
+      value = FaceVaryingLinearInterpolation::None;
+    } else if (faceVarying == TfToken("cornersOnly")) {
+      value = FaceVaryingLinearInterpolation::CornersOnly;
+    } else if (faceVarying == TfToken("cornersPlus1")) {
+      value = FaceVaryingLinearInterpolation::CornersPlus1;
+    } else if (faceVarying == TfToken("cornersPlus2")) {
+      value = FaceVaryingLinearInterpolation::CornersPlus2;
+    } else if (faceVarying == TfToken("boundaries")) {
+      value = FaceVaryingLinearInterpolation::Boundaries;
+    } else if (faceVarying == TfToken("all")) {
+      value = FaceVaryingLinearInterpolation::All;
+    }
+    if (value) {
+      if (created) {
+        diff.create_mesh_face_varying_linear_interpolation(pathString, *value);
+      } else {
+        diff.diff_mesh_data_face_varying_linear_interpolation(pathString,
+                                                              *value);
+      }
+    }
+  }
+
+  auto creaseIndices = _GetSubdivisionTag<int>(
+    sceneIndex, path, SubdivisionTagsLocator, TfToken("creaseIndices"));
+  auto creaseLengths = _GetSubdivisionTag<int>(
+    sceneIndex, path, SubdivisionTagsLocator, TfToken("creaseLengths"));
+  auto creaseSharpnesses = _GetSubdivisionTag<float>(
+    sceneIndex, path, SubdivisionTagsLocator, TfToken("creaseSharpnesses"));
+  if (!creaseIndices.empty()) {
+    std::vector<uint32_t> indices(creaseIndices.begin(), creaseIndices.end());
+    std::vector<uint32_t> lengths(creaseLengths.begin(), creaseLengths.end());
+    auto indicesData =
+      rust::Slice<const uint32_t>(indices.data(), indices.size());
+    auto lengthsData =
+      rust::Slice<const uint32_t>(lengths.data(), lengths.size());
+    auto sharpnessesData = rust::Slice<const float>(creaseSharpnesses.cdata(),
+                                                    creaseSharpnesses.size());
+    if (created) {
+      diff.create_mesh_creases(
+        pathString, indicesData, lengthsData, sharpnessesData);
+    } else {
+      diff.diff_mesh_data_creases(
+        pathString, indicesData, lengthsData, sharpnessesData);
+    }
+  }
+
+  auto cornerIndices = _GetSubdivisionTag<int>(
+    sceneIndex, path, SubdivisionTagsLocator, TfToken("cornerIndices"));
+  auto cornerSharpnesses = _GetSubdivisionTag<float>(
+    sceneIndex, path, SubdivisionTagsLocator, TfToken("cornerSharpnesses"));
+  if (!cornerIndices.empty()) {
+    std::vector<uint32_t> indices(cornerIndices.begin(), cornerIndices.end());
+    auto indicesData =
+      rust::Slice<const uint32_t>(indices.data(), indices.size());
+    auto sharpnessesData = rust::Slice<const float>(cornerSharpnesses.cdata(),
+                                                    cornerSharpnesses.size());
+    if (created) {
+      diff.create_mesh_corners(pathString, indicesData, sharpnessesData);
+    } else {
+      diff.diff_mesh_data_corners(pathString, indicesData, sharpnessesData);
+    }
+  }
+
+  // Hydraのprimごとのrefine level。0の場合はExtractorの設定に従う
+  auto refineLevelSource = HdSampledDataSource::Cast(
+    sceneIndex.GetDataSource(path, RefineLevelLocator));
+  if (refineLevelSource) {
+    auto refineLevel = refineLevelSource->GetValue(0).GetWithDefault<int>(0);
+    if (refineLevel > 0) {
+      if (created) {
+        diff.create_mesh_refine_level(pathString, refineLevel);
+      } else {
+        diff.diff_mesh_data_refine_level(pathString, refineLevel);
+      }
+    }
+  }
+}
+
+void
+MeshObserver::_ApplyVisibility(const HdSceneIndexBase& sceneIndex)
+{
+  // 非表示のMeshはRust側に送っていないので、削除されても差分を送らない
+  for (auto it = _removed.begin(); it != _removed.end();) {
+    if (_hiddenMeshPaths.erase(*it) > 0) {
+      it = _removed.erase(it);
+    } else {
+      ++it;
+    }
+  }
+
+  // visibilityが変わったMeshは、表示されたらadded、非表示になったらremovedとして扱う
+  for (auto it = _dirtied.begin(); it != _dirtied.end();) {
+    const auto& path = it->first;
+    auto& diffTypes = it->second;
+    auto isHidden = _hiddenMeshPaths.find(path) != _hiddenMeshPaths.end();
+
+    if (diffTypes.erase(DiffType::Visibility) > 0) {
+      auto isVisible = _IsVisible(sceneIndex, path);
+      if (isVisible && isHidden) {
+        _hiddenMeshPaths.erase(path);
+        _added.emplace(path);
+        it = _dirtied.erase(it);
+        continue;
+      } else if (!isVisible && !isHidden) {
+        _hiddenMeshPaths.insert(path);
+        _removed.emplace(path);
+        it = _dirtied.erase(it);
+        continue;
+      }
+    }
+
+    // 非表示のMeshの差分はRust側に送らない
+    if (isHidden || diffTypes.empty()) {
+      it = _dirtied.erase(it);
+    } else {
+      ++it;
+    }
+  }
+
+  // 追加されたMeshのうち非表示のものはRust側に送らずに記録だけする
+  for (auto it = _added.begin(); it != _added.end();) {
+    if (!_IsVisible(sceneIndex, *it)) {
+      _hiddenMeshPaths.insert(*it);
+      it = _added.erase(it);
+    } else {
+      _hiddenMeshPaths.erase(*it);
+      ++it;
+    }
+  }
+}
+
 void
 MeshObserver::GetDiff(const HdSceneIndexBase& sceneIndex, UsdDataDiff& diff)
 {
+  // 表示状態に応じてadded, removed, dirtiedを振り分ける
+  _ApplyVisibility(sceneIndex);
+
   // addedされたMeshの情報をdiffに登録する
   for (const auto& path : _added) {
     auto pathString = rust::String(path.GetText());
@@ -281,6 +659,12 @@ MeshObserver::GetDiff(const HdSceneIndexBase& sceneIndex, UsdDataDiff& diff)
       diff.create_mesh_points(pathString, pointsData);
     }
 
+    auto extent = _GetExtent(sceneIndex, path);
+    if (extent) {
+      auto extentData = rust::Slice<const float>(extent->data(), 6);
+      diff.create_mesh_extent(pathString, extentData);
+    }
+
     auto normalsSource = sceneIndex.GetDataSource(path, NormalsDataLocator);
     if (normalsSource) {
       auto sampledNormalsSource = HdSampledDataSource::Cast(normalsSource);
@@ -292,6 +676,23 @@ MeshObserver::GetDiff(const HdSceneIndexBase& sceneIndex, UsdDataDiff& diff)
       diff.create_mesh_normals(pathString, normalsData);
     }
 
+    auto normalsIndicesSource =
+      sceneIndex.GetDataSource(path, NormalsIndicesDataLocator);
+    if (normalsIndicesSource) {
+      auto sampledNormalsIndicesSource =
+        HdSampledDataSource::Cast(normalsIndicesSource);
+      auto value = sampledNormalsIndicesSource->GetValue(0);
+      auto normalsIndices = value.Get<VtIntArray>();
+      auto size = normalsIndices.size();
+      std::vector<uint32_t> data;
+      data.reserve(size);
+      for (int i = 0; i < size; i++) {
+        data.push_back(normalsIndices[i]);
+      }
+      auto normalsIndicesData = rust::Slice<const uint32_t>(data.data(), size);
+      diff.create_mesh_normals_indices(pathString, normalsIndicesData);
+    }
+
     auto normalsInterpolationSource =
       sceneIndex.GetDataSource(path, NormalsInterpolationDataLocator);
     if (normalsInterpolationSource) {
@@ -407,6 +808,28 @@ MeshObserver::GetDiff(const HdSceneIndexBase& sceneIndex, UsdDataDiff& diff)
       diff.create_mesh_face_vertex_counts(pathString, faceVertexCountsData);
     }
 
+    auto holeIndicesSource = sceneIndex.GetDataSource(path, HoleIndicesLocator);
+    if (holeIndicesSource) {
+      auto sampledHoleIndicesSource =
+        HdSampledDataSource::Cast(holeIndicesSource);
+      auto value = sampledHoleIndicesSource->GetValue(0);
+      auto holeIndices = value.Get<VtIntArray>();
+      auto size = holeIndices.size();
+      std::vector<uint32_t> data;
+      data.reserve(size);
+      for (int i = 0; i < size; i++) {
+        data.push_back(holeIndices[i]);
+      }
+      auto holeIndicesData = rust::Slice<const uint32_t>(data.data(), size);
+      diff.create_mesh_hole_indices(pathString, holeIndicesData);
+    }
+
+    // points, normals, st以外のprimvarの情報をdiffに登録する
+    _GetPrimvars(sceneIndex, path, true, diff);
+
+    // subdivisionの情報をdiffに登録する
+    _GetSubdivision(sceneIndex, path, true, diff);
+
     // meshに関係するgeomSubsetの情報をdiffに登録する
     for (const auto& geomSubsetPath : _geomSubsetPaths) {
       if (geomSubsetPath.GetParentPath() == path) {
@@ -519,6 +942,12 @@ MeshObserver::GetDiff(const HdSceneIndexBase& sceneIndex, UsdDataDiff& diff)
           diff.diff_mesh_data_points(pathString, pointsData);
         }
 
+        auto extent = _GetExtent(sceneIndex, path);
+        if (extent) {
+          auto extentData = rust::Slice<const float>(extent->data(), 6);
+          diff.diff_mesh_data_extent(pathString, extentData);
+        }
+
         auto normalsSource = sceneIndex.GetDataSource(path, NormalsDataLocator);
         if (normalsSource) {
           auto sampledNormalsSource = HdSampledDataSource::Cast(normalsSource);
@@ -530,6 +959,24 @@ MeshObserver::GetDiff(const HdSceneIndexBase& sceneIndex, UsdDataDiff& diff)
           diff.diff_mesh_data_normals(pathString, normalsData);
         }
 
+        auto normalsIndicesSource =
+          sceneIndex.GetDataSource(path, NormalsIndicesDataLocator);
+        if (normalsIndicesSource) {
+          auto sampledNormalsIndicesSource =
+            HdSampledDataSource::Cast(normalsIndicesSource);
+          auto value = sampledNormalsIndicesSource->GetValue(0);
+          auto normalsIndices = value.Get<VtIntArray>();
+          auto size = normalsIndices.size();
+          std::vector<uint32_t> data;
+          data.reserve(size);
+          for (int i = 0; i < size; i++) {
+            data.push_back(normalsIndices[i]);
+          }
+          auto normalsIndicesData =
+            rust::Slice<const uint32_t>(data.data(), size);
+          diff.diff_mesh_data_normals_indices(pathString, normalsIndicesData);
+        }
+
         auto normalsInterpolationSource =
           sceneIndex.GetDataSource(path, NormalsInterpolationDataLocator);
         if (normalsInterpolationSource) {
@@ -652,6 +1099,30 @@ MeshObserver::GetDiff(const HdSceneIndexBase& sceneIndex, UsdDataDiff& diff)
                                                  faceVertexCountsData);
         }
 
+        auto holeIndicesSource =
+          sceneIndex.GetDataSource(path, HoleIndicesLocator);
+        if (holeIndicesSource) {
+          auto sampledHoleIndicesSource =
+            HdSampledDataSource::Cast(holeIndicesSource);
+          auto value = sampledHoleIndicesSource->GetValue(0);
+          auto holeIndices = value.Get<VtIntArray>();
+          auto size = holeIndices.size();
+          std::vector<uint32_t> data;
+          data.reserve(size);
+          for (int i = 0; i < size; i++) {
+            data.push_back(holeIndices[i]);
+          }
+          auto holeIndicesData =
+            rust::Slice<const uint32_t>(data.data(), size);
+          diff.diff_mesh_data_hole_indices(pathString, holeIndicesData);
+        }
+
+        // points, normals, st以外のprimvarの情報をdiffに登録する
+        _GetPrimvars(sceneIndex, path, false, diff);
+
+        // subdivisionの情報をdiffに登録する
+        _GetSubdivision(sceneIndex, path, false, diff);
+
         // meshに関係するgeomSubsetの情報をdiffに登録する
         for (const auto& geomSubsetPath : _geomSubsetPaths) {
           if (geomSubsetPath.GetParentPath() == path) {
@@ -705,6 +1176,60 @@ MeshObserver::GetDiff(const HdSceneIndexBase& sceneIndex, UsdDataDiff& diff)
           auto materialPathString = rust::String(materialPath.GetText());
           diff.diff_mesh_material_binding(pathString, materialPathString);
         }
+      } else if (diffType == DiffType::Points &&
+                 diffTypes.find(DiffType::MeshData) == diffTypes.end()) {
+        // topologyは変わっていないので、pointsとnormalsだけを再取得する。
+        // MeshDataの差分もある場合は、そちらで一通りのデータを再取得している
+        auto pointsSource = HdSampledDataSource::Cast(
+          sceneIndex.GetDataSource(path, PointsDataLocator));
+        if (!pointsSource) {
+          continue;
+        }
+        auto points = pointsSource->GetValue(0).Get<VtVec3fArray>();
+        auto pointsData = rust::Slice<const float>(
+          reinterpret_cast<const float*>(points.cdata()), points.size() * 3);
+        diff.diff_mesh_points(pathString, pointsData);
+
+        auto extent = _GetExtent(sceneIndex, path);
+        if (extent) {
+          auto extentData = rust::Slice<const float>(extent->data(), 6);
+          diff.diff_mesh_points_extent(pathString, extentData);
+        }
+
+        // normalsは値だけでなくindicesとinterpolationも含めて全て送る
+        auto normalsSource = HdSampledDataSource::Cast(
+          sceneIndex.GetDataSource(path, NormalsDataLocator));
+        if (normalsSource) {
+          auto normals = normalsSource->GetValue(0).Get<VtVec3fArray>();
+          auto normalsData = rust::Slice<const float>(
+            reinterpret_cast<const float*>(normals.cdata()),
+            normals.size() * 3);
+          diff.diff_mesh_points_normals(pathString, normalsData);
+        }
+
+        auto normalsIndicesSource = HdSampledDataSource::Cast(
+          sceneIndex.GetDataSource(path, NormalsIndicesDataLocator));
+        if (normalsIndicesSource) {
+          auto normalsIndices =
+            normalsIndicesSource->GetValue(0).Get<VtIntArray>();
+          std::vector<uint32_t> data(normalsIndices.begin(),
+                                     normalsIndices.end());
+          auto normalsIndicesData =
+            rust::Slice<const uint32_t>(data.data(), data.size());
+          diff.diff_mesh_points_normals_indices(pathString,
+                                                normalsIndicesData);
+        }
+
+        auto normalsInterpolationSource = HdSampledDataSource::Cast(
+          sceneIndex.GetDataSource(path, NormalsInterpolationDataLocator));
+        if (normalsInterpolationSource) {
+          auto interpolation = _ToInterpolation(
+            normalsInterpolationSource->GetValue(0).Get<TfToken>());
+          if (interpolation) {
+            diff.diff_mesh_points_normals_interpolation(pathString,
+                                                        *interpolation);
+          }
+        }
       }
     }
   }
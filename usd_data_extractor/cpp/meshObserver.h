@@ -1,6 +1,7 @@
 #ifndef MESH_OBSERVER_H
 #define MESH_OBSERVER_H
 
+#include "pxr/base/tf/stringUtils.h"
 #include "pxr/imaging/hd/dataSource.h"
 #include "pxr/imaging/hd/sceneIndexObserver.h"
 #include "pxr/pxr.h"
@@ -8,19 +9,25 @@
 #include "usdDataDiff.h"
 #include <iostream>
 #include <map>
+#include <optional>
 #include <set>
 
 using namespace pxr;
 
 // Locatorは細かいprimvar単位などで変更通知を受け取れるが、
 // Rust側にはMeshDataの一部に変更があったらMeshDataの情報全体を渡しているので、
-// Rustとの同期の単位はTransformMatrixかMeshDataかの二択。
+// Rustとの同期の単位は基本的にTransformMatrixかMeshDataかの二択。
+// Pointsはtopologyが変わらずにpointsとnormals、extentだけが変わった場合のためのもので、
+// アニメーションするMeshではRust側で頂点位置と法線だけを作り直せるようにする。
+// Visibilityは非表示になったMeshをRust側では削除されたものとして扱うためのもの。
 // MeshDataを全部一括で渡すのは、Rust側でメッシュの頂点のduplicate処理とかをして
 // 頂点バッファを構築し直すのに一通りの情報が必要なため。
 enum class DiffType
 {
   TransformMatrix,
   MeshData,
+  Points,
+  Visibility,
 };
 
 // primTypeがMeshの情報を処理してRustにdiffを受け渡すためのクラス。
@@ -36,12 +43,25 @@ public:
   inline static const HdDataSourceLocator TransforLocator =
     HdDataSourceLocator(TfToken("xform"));
   inline static const HdDataSourceLocator PrimvarsLocator =
-    HdDataSourceLocator(TfToken("privars"));
+    HdDataSourceLocator(TfToken("primvars"));
   inline static const HdDataSourceLocator MeshLocator =
     HdDataSourceLocator(TfToken("mesh"));
+  inline static const HdDataSourceLocator VisibilityLocator =
+    HdDataSourceLocator(TfToken("visibility"));
+  inline static const HdDataSourceLocator DisplayStyleLocator =
+    HdDataSourceLocator(TfToken("displayStyle"));
+
+  inline static const HdDataSourceLocator PointsLocator =
+    HdDataSourceLocator(TfToken("primvars"), TfToken("points"));
+  inline static const HdDataSourceLocator NormalsLocator =
+    HdDataSourceLocator(TfToken("primvars"), TfToken("normals"));
+  inline static const HdDataSourceLocator ExtentLocator =
+    HdDataSourceLocator(TfToken("extent"));
 
   inline static const HdDataSourceLocator TransformMatrixLocator =
     HdDataSourceLocator(TfToken("xform"), TfToken("matrix"));
+  inline static const HdDataSourceLocator VisibilityDataLocator =
+    HdDataSourceLocator(TfToken("visibility"), TfToken("visibility"));
   inline static const HdDataSourceLocator LeftHandedDataLocator =
     HdDataSourceLocator(TfToken("mesh"),
                         TfToken("topology"),
@@ -53,11 +73,19 @@ public:
   inline static const HdDataSourceLocator NormalsDataLocator =
     HdDataSourceLocator(TfToken("primvars"),
                         TfToken("normals"),
-                        TfToken("primvarValue"));
+                        TfToken("indexedPrimvarValue"));
+  inline static const HdDataSourceLocator NormalsIndicesDataLocator =
+    HdDataSourceLocator(TfToken("primvars"),
+                        TfToken("normals"),
+                        TfToken("indices"));
   inline static const HdDataSourceLocator NormalsInterpolationDataLocator =
     HdDataSourceLocator(TfToken("primvars"),
                         TfToken("normals"),
                         TfToken("interpolation"));
+  inline static const HdDataSourceLocator ExtentMinLocator =
+    HdDataSourceLocator(TfToken("extent"), TfToken("min"));
+  inline static const HdDataSourceLocator ExtentMaxLocator =
+    HdDataSourceLocator(TfToken("extent"), TfToken("max"));
   inline static const HdDataSourceLocator UVsDataLocator =
     HdDataSourceLocator(TfToken("primvars"),
                         TfToken("st"),
@@ -76,6 +104,16 @@ public:
     HdDataSourceLocator(TfToken("mesh"),
                         TfToken("topology"),
                         TfToken("faceVertexCounts"));
+  inline static const HdDataSourceLocator HoleIndicesLocator =
+    HdDataSourceLocator(TfToken("mesh"),
+                        TfToken("topology"),
+                        TfToken("holeIndices"));
+  inline static const HdDataSourceLocator SubdivisionSchemeLocator =
+    HdDataSourceLocator(TfToken("mesh"), TfToken("subdivisionScheme"));
+  inline static const HdDataSourceLocator SubdivisionTagsLocator =
+    HdDataSourceLocator(TfToken("mesh"), TfToken("subdivisionTags"));
+  inline static const HdDataSourceLocator RefineLevelLocator =
+    HdDataSourceLocator(TfToken("displayStyle"), TfToken("refineLevel"));
   inline static const HdDataSourceLocator GeomSubsetLocator =
     HdDataSourceLocator(TfToken("mesh"), TfToken("geomSubsets"));
 
@@ -116,6 +154,8 @@ private:
   std::set<SdfPath> _meshPaths;
   // stageに存在するGeomSubsetのPathを記録する
   std::set<SdfPath> _geomSubsetPaths;
+  // stageに存在するが非表示のため、Rust側に送っていないMeshのPathを記録する
+  std::set<SdfPath> _hiddenMeshPaths;
 
   // 前回GetDiffしてClearしてから追加されたMeshの差分のPathを記録する
   std::set<SdfPath> _added;
@@ -124,6 +164,18 @@ private:
   // 前回までにGetDiffで追加されたものの情報の更新の場合を記録する
   std::map<SdfPath, std::set<DiffType>> _dirtied;
 
+  bool _IsVisible(const HdSceneIndexBase& sceneIndex,
+                  const SdfPath& path) const;
+  void _GetPrimvars(const HdSceneIndexBase& sceneIndex,
+                    const SdfPath& path,
+                    bool created,
+                    UsdDataDiff& diff) const;
+  void _GetSubdivision(const HdSceneIndexBase& sceneIndex,
+                       const SdfPath& path,
+                       bool created,
+                       UsdDataDiff& diff) const;
+  void _ApplyVisibility(const HdSceneIndexBase& sceneIndex);
+
   // This class does not support copying.
   MeshObserver(const MeshObserver&) = delete;
   MeshObserver& operator=(const MeshObserver&) = delete;
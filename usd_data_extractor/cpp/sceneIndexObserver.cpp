@@ -17,6 +17,7 @@ HdBridgeSceneIndexObserver::PrimsAdded(const HdSceneIndexBase& sender,
   _sphereLightObserver.PrimsAdded(sender, entries);
   _distantLightObserver.PrimsAdded(sender, entries);
   _cameraObserver.PrimsAdded(sender, entries);
+  _instancerObserver.PrimsAdded(sender, entries);
   _materialObserver.PrimsAdded(sender, entries);
 }
 
@@ -29,6 +30,7 @@ HdBridgeSceneIndexObserver::PrimsRemoved(const HdSceneIndexBase& sender,
   _sphereLightObserver.PrimsRemoved(sender, entries);
   _distantLightObserver.PrimsRemoved(sender, entries);
   _cameraObserver.PrimsRemoved(sender, entries);
+  _instancerObserver.PrimsRemoved(sender, entries);
   _materialObserver.PrimsRemoved(sender, entries);
 }
 
@@ -41,6 +43,7 @@ HdBridgeSceneIndexObserver::PrimsDirtied(const HdSceneIndexBase& sender,
   _sphereLightObserver.PrimsDirtied(sender, entries);
   _distantLightObserver.PrimsDirtied(sender, entries);
   _cameraObserver.PrimsDirtied(sender, entries);
+  _instancerObserver.PrimsDirtied(sender, entries);
   _materialObserver.PrimsDirtied(sender, entries);
 }
 
@@ -53,6 +56,7 @@ HdBridgeSceneIndexObserver::PrimsRenamed(const HdSceneIndexBase& sender,
   _sphereLightObserver.PrimsRenamed(sender, entries);
   _distantLightObserver.PrimsRenamed(sender, entries);
   _cameraObserver.PrimsRenamed(sender, entries);
+  _instancerObserver.PrimsRenamed(sender, entries);
   _materialObserver.PrimsRenamed(sender, entries);
 }
 
@@ -64,6 +68,7 @@ HdBridgeSceneIndexObserver::ClearDiff()
   _sphereLightObserver.ClearDiff();
   _distantLightObserver.ClearDiff();
   _cameraObserver.ClearDiff();
+  _instancerObserver.ClearDiff();
   _materialObserver.ClearDiff();
 }
 
@@ -76,5 +81,6 @@ HdBridgeSceneIndexObserver::GetDiff(const HdSceneIndexBase& sender,
   _sphereLightObserver.GetDiff(sender, diff);
   _distantLightObserver.GetDiff(sender, diff);
   _cameraObserver.GetDiff(sender, diff);
+  _instancerObserver.GetDiff(sender, diff);
   _materialObserver.GetDiff(sender, diff);
 }
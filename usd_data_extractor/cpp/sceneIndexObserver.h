@@ -3,6 +3,7 @@
 
 #include "cameraObserver.h"
 #include "distantLightObserver.h"
+#include "instancerObserver.h"
 #include "materialObserver.h"
 #include "meshObserver.h"
 #include "pxr/imaging/hd/dataSource.h"
@@ -44,6 +45,7 @@ private:
   SphereLightObserver _sphereLightObserver;
   DistantLightObserver _distantLightObserver;
   CameraObserver _cameraObserver;
+  InstancerObserver _instancerObserver;
   MaterialObserver _materialObserver;
 
   // This class does not support copying.
@@ -0,0 +1,397 @@
+#include "skelObserver.h"
+#include "pxr/usd/usdGeom/mesh.h"
+#include "pxr/usd/usdGeom/xformCache.h"
+#include "pxr/usd/usdSkel/animQuery.h"
+#include "pxr/usd/usdSkel/binding.h"
+#include "pxr/usd/usdSkel/blendShape.h"
+#include "pxr/usd/usdSkel/root.h"
+#include "pxr/usd/usdSkel/utils.h"
+#include "usd_data_extractor/src/bridge.rs.h"
+
+// GPUのskinningで扱いやすいように、1頂点に影響するjointの数をこの数に揃える
+static const int InfluencesPerVertex = 4;
+
+// matrixの16個の値をfloatにしてdataの末尾に追加する
+static void
+_AppendMatrix(const GfMatrix4d& matrix, std::vector<float>& data)
+{
+  auto array = matrix.GetArray();
+  for (int i = 0; i < 16; i++) {
+    data.push_back(array[i]);
+  }
+}
+
+static rust::Slice<const float>
+_ToSlice(const VtVec3fArray& values)
+{
+  return rust::Slice<const float>(
+    reinterpret_cast<const float*>(values.cdata()), values.size() * 3);
+}
+
+static std::vector<float>
+_ToFloats(const VtMatrix4dArray& matrices)
+{
+  std::vector<float> data;
+  data.reserve(matrices.size() * 16);
+  for (const auto& matrix : matrices) {
+    _AppendMatrix(matrix, data);
+  }
+  return data;
+}
+
+bool
+SkelObserver::SkeletonRecord::operator==(const SkeletonRecord& other) const
+{
+  return joints == other.joints && parents == other.parents &&
+         bindTransforms == other.bindTransforms &&
+         restTransforms == other.restTransforms;
+}
+
+bool
+SkelObserver::SkinBindingRecord::operator==(
+  const SkinBindingRecord& other) const
+{
+  return skeletonPath == other.skeletonPath && rigid == other.rigid &&
+         jointIndices == other.jointIndices &&
+         jointWeights == other.jointWeights &&
+         geomBindTransform == other.geomBindTransform;
+}
+
+bool
+SkelObserver::InbetweenRecord::operator==(const InbetweenRecord& other) const
+{
+  return weight == other.weight && offsets == other.offsets &&
+         normalOffsets == other.normalOffsets;
+}
+
+bool
+SkelObserver::BlendShapeRecord::operator==(const BlendShapeRecord& other) const
+{
+  return name == other.name && offsets == other.offsets &&
+         normalOffsets == other.normalOffsets &&
+         pointIndices == other.pointIndices && inbetweens == other.inbetweens;
+}
+
+SkelObserver::SkelObserver() {}
+
+SkelObserver::~SkelObserver()
+{
+  TfNotice::Revoke(_objectsChangedKey);
+}
+
+void
+SkelObserver::SetStage(UsdStageRefPtr stage)
+{
+  TfNotice::Revoke(_objectsChangedKey);
+  _stage = stage;
+  _objectsChangedKey = TfNotice::Register(TfCreateWeakPtr(this),
+                                          &SkelObserver::_OnObjectsChanged,
+                                          UsdStageWeakPtr(_stage));
+  _needsPopulate = true;
+}
+
+void
+SkelObserver::_OnObjectsChanged(const UsdNotice::ObjectsChanged& notice,
+                                const UsdStageWeakPtr& sender)
+{
+  // どの編集がSkeletonやskinningに関係するかを調べるより、
+  // 次のextractでまとめて取得し直して前回送った内容と比べる方が単純
+  _needsPopulate = true;
+}
+
+std::vector<SkelObserver::BlendShapeRecord>
+SkelObserver::_GetBlendShapes(const UsdSkelSkinningQuery& skinningQuery) const
+{
+  // blendShapesとblendShapeTargetsは同じ順に並んでいて、weightもこの順になる
+  VtTokenArray names;
+  skinningQuery.GetBlendShapeOrder(&names);
+  SdfPathVector targets;
+  skinningQuery.GetBlendShapeTargetsRel().GetTargets(&targets);
+
+  std::vector<BlendShapeRecord> blendShapes;
+  for (size_t i = 0; i < names.size() && i < targets.size(); i++) {
+    // targetが見つからない場合も、weightの並びを揃えるためにoffsetのない
+    // blend shapeとして送る
+    BlendShapeRecord record;
+    record.name = names[i];
+    UsdSkelBlendShape blendShape(_stage->GetPrimAtPath(targets[i]));
+    if (blendShape) {
+      blendShape.GetOffsetsAttr().Get(&record.offsets);
+      blendShape.GetNormalOffsetsAttr().Get(&record.normalOffsets);
+      blendShape.GetPointIndicesAttr().Get(&record.pointIndices);
+      for (const auto& inbetween : blendShape.GetInbetweens()) {
+        InbetweenRecord inbetweenRecord;
+        if (!inbetween.GetWeight(&inbetweenRecord.weight)) {
+          continue;
+        }
+        inbetween.GetOffsets(&inbetweenRecord.offsets);
+        inbetween.GetNormalOffsets(&inbetweenRecord.normalOffsets);
+        record.inbetweens.push_back(inbetweenRecord);
+      }
+    }
+    blendShapes.push_back(record);
+  }
+  return blendShapes;
+}
+
+void
+SkelObserver::_Populate(UsdDataDiff& diff)
+{
+  _skelCache.Clear();
+  _skeletonQueries.clear();
+  _blendShapeQueries.clear();
+
+  std::map<SdfPath, SkeletonRecord> skeletons;
+  std::map<SdfPath, SkinBindingRecord> skinBindings;
+  std::map<SdfPath, std::vector<BlendShapeRecord>> blendShapes;
+
+  for (const auto& prim : _stage->Traverse()) {
+    if (!prim.IsA<UsdSkelRoot>()) {
+      continue;
+    }
+    UsdSkelRoot skelRoot(prim);
+    _skelCache.Populate(skelRoot, UsdPrimDefaultPredicate);
+
+    std::vector<UsdSkelBinding> bindings;
+    _skelCache.ComputeSkelBindings(
+      skelRoot, &bindings, UsdPrimDefaultPredicate);
+    for (const auto& binding : bindings) {
+      auto skelQuery = _skelCache.GetSkelQuery(binding.GetSkeleton());
+      if (!skelQuery.IsValid()) {
+        continue;
+      }
+      auto skeletonPath = skelQuery.GetPrim().GetPath();
+      _skeletonQueries[skeletonPath] = skelQuery;
+
+      SkeletonRecord skeleton;
+      skeleton.joints = skelQuery.GetJointOrder();
+      skeleton.parents = skelQuery.GetTopology().GetParentIndices();
+      skelQuery.GetJointWorldBindTransforms(&skeleton.bindTransforms);
+      skelQuery.GetSkeleton().GetRestTransformsAttr().Get(
+        &skeleton.restTransforms);
+      skeletons[skeletonPath] = skeleton;
+
+      // skeletonのjointの名前からjoint indexを引けるようにする
+      std::map<TfToken, int> skeletonJointIndices;
+      for (size_t i = 0; i < skeleton.joints.size(); i++) {
+        skeletonJointIndices[skeleton.joints[i]] = i;
+      }
+
+      for (const auto& skinningQuery : binding.GetSkinningTargets()) {
+        auto meshPrim = skinningQuery.GetPrim();
+        if (!meshPrim.IsA<UsdGeomMesh>()) {
+          continue;
+        }
+        if (skinningQuery.HasBlendShapes()) {
+          auto meshBlendShapes = _GetBlendShapes(skinningQuery);
+          if (!meshBlendShapes.empty()) {
+            blendShapes[meshPrim.GetPath()] = meshBlendShapes;
+            _blendShapeQueries[meshPrim.GetPath()] = { skeletonPath,
+                                                       skinningQuery };
+          }
+        }
+
+        VtIntArray indices;
+        VtFloatArray weights;
+        if (!skinningQuery.ComputeJointInfluences(&indices, &weights)) {
+          continue;
+        }
+
+        // 影響の大きい順に並べてからjointの数を揃え、重みの合計を1にする
+        int influences = skinningQuery.GetNumInfluencesPerComponent();
+        UsdSkelSortInfluences(indices, weights, influences);
+        UsdSkelResizeInfluences(&indices, influences, InfluencesPerVertex);
+        UsdSkelResizeInfluences(&weights, influences, InfluencesPerVertex);
+        UsdSkelNormalizeWeights(weights, InfluencesPerVertex);
+
+        // meshにskel:jointsがある場合、indicesはmesh固有のjointの並びなので
+        // skeletonのjointの並びに変換する。skeletonにないjointの重みは0にし、
+        // 残りのjointの重みの合計が1になるように正規化し直す
+        VtTokenArray meshJoints;
+        bool hasMeshJoints = skinningQuery.GetJointOrder(&meshJoints);
+
+        SkinBindingRecord skinBinding;
+        skinBinding.skeletonPath = skeletonPath;
+        skinBinding.rigid = skinningQuery.IsRigidlyDeformed();
+        skinBinding.jointIndices.reserve(indices.size());
+        for (size_t i = 0; i < indices.size(); i++) {
+          int index = indices[i];
+          if (hasMeshJoints) {
+            auto found = index >= 0 && size_t(index) < meshJoints.size()
+                           ? skeletonJointIndices.find(meshJoints[index])
+                           : skeletonJointIndices.end();
+            index = found != skeletonJointIndices.end() ? found->second : -1;
+          }
+          if (index < 0 || size_t(index) >= skeleton.joints.size()) {
+            index = 0;
+            weights[i] = 0.0f;
+          }
+          skinBinding.jointIndices.push_back(index);
+        }
+        UsdSkelNormalizeWeights(weights, InfluencesPerVertex);
+        skinBinding.jointWeights = weights;
+        skinBinding.geomBindTransform = skinningQuery.GetGeomBindTransform();
+        skinBindings[meshPrim.GetPath()] = skinBinding;
+      }
+    }
+  }
+
+  // 前回送った内容と比べて、追加、変更、削除されたものをdiffに登録する
+  for (const auto& [path, skeleton] : skeletons) {
+    auto prev = _skeletons.find(path);
+    if (prev != _skeletons.end() && prev->second == skeleton) {
+      continue;
+    }
+    rust::Vec<rust::String> joints;
+    for (const auto& joint : skeleton.joints) {
+      joints.push_back(rust::String(joint.GetText()));
+    }
+    std::vector<int32_t> parents(skeleton.parents.begin(),
+                                 skeleton.parents.end());
+    auto bindTransforms = _ToFloats(skeleton.bindTransforms);
+    auto restTransforms = _ToFloats(skeleton.restTransforms);
+    diff.add_or_update_skeleton(
+      rust::String(path.GetText()),
+      rust::Slice<const rust::String>(joints.data(), joints.size()),
+      rust::Slice<const int32_t>(parents.data(), parents.size()),
+      rust::Slice<const float>(bindTransforms.data(), bindTransforms.size()),
+      rust::Slice<const float>(restTransforms.data(), restTransforms.size()));
+    // 構造が変わったSkeletonのポーズは送り直す
+    _poses.erase(path);
+  }
+  for (const auto& [path, skeleton] : _skeletons) {
+    if (skeletons.find(path) == skeletons.end()) {
+      diff.destroy_skeleton(rust::String(path.GetText()));
+      _poses.erase(path);
+    }
+  }
+
+  for (const auto& [path, skinBinding] : skinBindings) {
+    auto prev = _skinBindings.find(path);
+    if (prev != _skinBindings.end() && prev->second == skinBinding) {
+      continue;
+    }
+    std::vector<float> geomBindTransform;
+    _AppendMatrix(skinBinding.geomBindTransform, geomBindTransform);
+    diff.add_or_update_mesh_skin_binding(
+      rust::String(path.GetText()),
+      rust::String(skinBinding.skeletonPath.GetText()),
+      skinBinding.rigid ? Interpolation::Constant : Interpolation::Vertex,
+      rust::Slice<const uint32_t>(skinBinding.jointIndices.data(),
+                                  skinBinding.jointIndices.size()),
+      rust::Slice<const float>(skinBinding.jointWeights.cdata(),
+                               skinBinding.jointWeights.size()),
+      rust::Slice<const float>(geomBindTransform.data(), 16));
+  }
+  for (const auto& [path, skinBinding] : _skinBindings) {
+    if (skinBindings.find(path) == skinBindings.end()) {
+      diff.destroy_mesh_skin_binding(rust::String(path.GetText()));
+    }
+  }
+
+  for (const auto& [path, meshBlendShapes] : blendShapes) {
+    auto prev = _blendShapes.find(path);
+    if (prev != _blendShapes.end() && prev->second == meshBlendShapes) {
+      continue;
+    }
+    for (const auto& blendShape : meshBlendShapes) {
+      std::vector<uint32_t> pointIndices(blendShape.pointIndices.begin(),
+                                         blendShape.pointIndices.end());
+      diff.add_mesh_blend_shape(
+        rust::String(path.GetText()),
+        rust::String(blendShape.name.GetText()),
+        _ToSlice(blendShape.offsets),
+        _ToSlice(blendShape.normalOffsets),
+        rust::Slice<const uint32_t>(pointIndices.data(), pointIndices.size()));
+      for (const auto& inbetween : blendShape.inbetweens) {
+        diff.add_mesh_blend_shape_inbetween(rust::String(path.GetText()),
+                                            inbetween.weight,
+                                            _ToSlice(inbetween.offsets),
+                                            _ToSlice(inbetween.normalOffsets));
+      }
+    }
+    // blend shapeが変わったMeshのweightは送り直す
+    _blendShapeWeights.erase(path);
+  }
+  for (const auto& [path, meshBlendShapes] : _blendShapes) {
+    if (blendShapes.find(path) == blendShapes.end()) {
+      diff.destroy_mesh_blend_shapes(rust::String(path.GetText()));
+      _blendShapeWeights.erase(path);
+    }
+  }
+
+  _skeletons = std::move(skeletons);
+  _skinBindings = std::move(skinBindings);
+  _blendShapes = std::move(blendShapes);
+}
+
+void
+SkelObserver::GetDiff(double timeCode, UsdDataDiff& diff)
+{
+  if (!_stage) {
+    return;
+  }
+  if (_needsPopulate) {
+    _Populate(diff);
+    _needsPopulate = false;
+  }
+
+  // Skeletonのポーズを取得し、前回送ったものから変わっていれば送る
+  UsdGeomXformCache xformCache(timeCode);
+  for (const auto& [path, skelQuery] : _skeletonQueries) {
+    PoseRecord pose;
+    pose.transformMatrix =
+      xformCache.GetLocalToWorldTransform(skelQuery.GetPrim());
+    if (!skelQuery.ComputeSkinningTransforms(&pose.skinningTransforms,
+                                             timeCode)) {
+      continue;
+    }
+
+    auto prev = _poses.find(path);
+    if (prev != _poses.end() &&
+        prev->second.transformMatrix == pose.transformMatrix &&
+        prev->second.skinningTransforms == pose.skinningTransforms) {
+      continue;
+    }
+    std::vector<float> transformMatrix;
+    _AppendMatrix(pose.transformMatrix, transformMatrix);
+    auto skinningTransforms = _ToFloats(pose.skinningTransforms);
+    diff.update_skeleton_pose(
+      rust::String(path.GetText()),
+      rust::Slice<const float>(transformMatrix.data(), 16),
+      rust::Slice<const float>(skinningTransforms.data(),
+                               skinningTransforms.size()));
+    _poses[path] = pose;
+  }
+
+  // blend shapeのweightをSkelAnimationから取得し、Meshのblend shapeの並びにする
+  for (const auto& [path, query] : _blendShapeQueries) {
+    auto skelQuery = _skeletonQueries.find(query.skeletonPath);
+    if (skelQuery == _skeletonQueries.end()) {
+      continue;
+    }
+    VtFloatArray animWeights;
+    auto animQuery = skelQuery->second.GetAnimQuery();
+    if (animQuery.IsValid()) {
+      animQuery.ComputeBlendShapeWeights(&animWeights, timeCode);
+    }
+    VtFloatArray weights;
+    const auto& mapper = query.skinningQuery.GetBlendShapeMapper();
+    if (mapper) {
+      mapper->Remap(animWeights, &weights);
+    } else {
+      weights = animWeights;
+    }
+    // SkelAnimationにweightがないblend shapeのweightは0にする
+    weights.resize(_blendShapes[path].size(), 0.0f);
+
+    auto prev = _blendShapeWeights.find(path);
+    if (prev != _blendShapeWeights.end() && prev->second == weights) {
+      continue;
+    }
+    diff.update_mesh_blend_shape_weights(
+      rust::String(path.GetText()),
+      rust::Slice<const float>(weights.cdata(), weights.size()));
+    _blendShapeWeights[path] = weights;
+  }
+}
@@ -0,0 +1,123 @@
+#ifndef SKEL_OBSERVER_H
+#define SKEL_OBSERVER_H
+
+#include "pxr/base/tf/notice.h"
+#include "pxr/base/tf/weakBase.h"
+#include "pxr/pxr.h"
+#include "pxr/usd/sdf/path.h"
+#include "pxr/usd/usd/notice.h"
+#include "pxr/usd/usd/stage.h"
+#include "pxr/usd/usdSkel/cache.h"
+#include "pxr/usd/usdSkel/skeletonQuery.h"
+#include "pxr/usd/usdSkel/skinningQuery.h"
+#include "usdDataDiff.h"
+#include <iostream>
+#include <map>
+#include <set>
+#include <vector>
+
+using namespace pxr;
+
+// SkelRoot以下のSkeletonと、Skeletonに束縛されたMeshのskinningやblend shapeの情報を
+// 処理してRustにdiffを受け渡すためのクラス。
+// OpenUSDのバージョンによってはHydraのscene indexにSkeletonの情報が流れてこないため、
+// 他のObserverと違いUsdSkelのAPIでstageを直接問い合わせる。
+// Skeletonの構造とskinningの束縛はstageが編集されたときだけ取得し直し、
+// Skeletonのポーズとblend shapeのweightはextractのたびに取得して変わっていれば送る。
+class SkelObserver : public TfWeakBase
+{
+
+public:
+  SkelObserver();
+  virtual ~SkelObserver();
+
+  void SetStage(UsdStageRefPtr stage);
+
+  void GetDiff(double timeCode, UsdDataDiff& diff);
+
+private:
+  // 前回Rustに送ったSkeletonの構造
+  struct SkeletonRecord
+  {
+    VtTokenArray joints;
+    VtIntArray parents;
+    VtMatrix4dArray bindTransforms;
+    VtMatrix4dArray restTransforms;
+
+    bool operator==(const SkeletonRecord& other) const;
+  };
+
+  // 前回Rustに送ったMeshのskinningの束縛
+  struct SkinBindingRecord
+  {
+    SdfPath skeletonPath;
+    bool rigid;
+    std::vector<uint32_t> jointIndices;
+    VtFloatArray jointWeights;
+    GfMatrix4d geomBindTransform;
+
+    bool operator==(const SkinBindingRecord& other) const;
+  };
+
+  // 前回Rustに送ったblend shapeのinbetween
+  struct InbetweenRecord
+  {
+    float weight;
+    VtVec3fArray offsets;
+    VtVec3fArray normalOffsets;
+
+    bool operator==(const InbetweenRecord& other) const;
+  };
+
+  // 前回Rustに送ったMeshのblend shape
+  struct BlendShapeRecord
+  {
+    TfToken name;
+    VtVec3fArray offsets;
+    VtVec3fArray normalOffsets;
+    VtIntArray pointIndices;
+    std::vector<InbetweenRecord> inbetweens;
+
+    bool operator==(const BlendShapeRecord& other) const;
+  };
+
+  // blend shapeのweightを計算するための、Meshが束縛されたSkeletonとskinningの情報
+  struct BlendShapeQuery
+  {
+    SdfPath skeletonPath;
+    UsdSkelSkinningQuery skinningQuery;
+  };
+
+  // 前回Rustに送ったSkeletonのポーズ
+  struct PoseRecord
+  {
+    GfMatrix4d transformMatrix;
+    VtMatrix4dArray skinningTransforms;
+  };
+
+  void _OnObjectsChanged(const UsdNotice::ObjectsChanged& notice,
+                         const UsdStageWeakPtr& sender);
+  void _Populate(UsdDataDiff& diff);
+  std::vector<BlendShapeRecord> _GetBlendShapes(
+    const UsdSkelSkinningQuery& skinningQuery) const;
+
+  UsdStageRefPtr _stage;
+  TfNotice::Key _objectsChangedKey;
+  // stageが編集されてSkeletonの構造やskinningの束縛を取得し直す必要があるか
+  bool _needsPopulate = true;
+
+  UsdSkelCache _skelCache;
+  std::map<SdfPath, UsdSkelSkeletonQuery> _skeletonQueries;
+  std::map<SdfPath, SkeletonRecord> _skeletons;
+  std::map<SdfPath, SkinBindingRecord> _skinBindings;
+  std::map<SdfPath, PoseRecord> _poses;
+  std::map<SdfPath, std::vector<BlendShapeRecord>> _blendShapes;
+  std::map<SdfPath, BlendShapeQuery> _blendShapeQueries;
+  std::map<SdfPath, VtFloatArray> _blendShapeWeights;
+
+  // This class does not support copying.
+  SkelObserver(const SkelObserver&) = delete;
+  SkelObserver& operator=(const SkelObserver&) = delete;
+};
+
+#endif
@@ -1,13 +1,26 @@
 #include "usdDataExtractor.h"
 #include "usd_data_extractor/src/bridge.rs.h"
 
-BridgeUsdDataExtractor::BridgeUsdDataExtractor(std::string openPath)
-  : _openPath(openPath)
+// prim pathのリストからUsdStagePopulationMaskを作る
+static UsdStagePopulationMask
+_CreatePopulationMask(rust::Slice<const rust::String> populationMask)
 {
-  _stage = UsdStage::Open(_openPath);
-  if (!_stage) {
-    throw std::runtime_error("Failed to open stage");
+  UsdStagePopulationMask mask;
+  for (const auto& path : populationMask) {
+    auto pathString = std::string(path);
+    if (!SdfPath::IsValidPathString(pathString)) {
+      throw std::runtime_error("Invalid population mask path: " + pathString);
+    }
+    mask.Add(SdfPath(pathString));
   }
+  return mask;
+}
+
+BridgeUsdDataExtractor::BridgeUsdDataExtractor(UsdStageRefPtr stage)
+  : _stage(stage)
+{
+  // stageの編集はすべてsession layerに書き込む
+  _stage->SetEditTarget(_stage->GetSessionLayer());
 
   _startTimeCode = _stage->GetStartTimeCode();
   _endTimeCode = _stage->GetEndTimeCode();
@@ -22,6 +35,7 @@ BridgeUsdDataExtractor::BridgeUsdDataExtractor(std::string openPath)
   _sceneIndex->AddObserver(HdSceneIndexObserverPtr(&_observer));
 
   _stageSceneIndex->SetStage(_stage);
+  _skelObserver.SetStage(_stage);
 }
 
 BridgeUsdDataExtractor::~BridgeUsdDataExtractor()
@@ -41,16 +55,499 @@ BridgeUsdDataExtractor::extract(double timeCode, UsdDataDiff& diff)
   // debug
   // HdUtils::PrintSceneIndex(std::cout, _sceneIndex);
 
+  // 前回のextractからのstageの編集をscene indexに反映する
+  _stageSceneIndex->ApplyPendingUpdates();
   _stageSceneIndex->SetTime(timeCode);
   _observer.GetDiff(*_sceneIndex, diff);
+  _skelObserver.GetDiff(timeCode, diff);
 
   if (_isFirstExtract) {
     _isFirstExtract = false;
   }
 }
 
+rust::String
+BridgeUsdDataExtractor::up_axis() const
+{
+  return rust::String(UsdGeomGetStageUpAxis(_stage).GetString());
+}
+
+double
+BridgeUsdDataExtractor::meters_per_unit() const
+{
+  return UsdGeomGetStageMetersPerUnit(_stage);
+}
+
+double
+BridgeUsdDataExtractor::time_codes_per_second() const
+{
+  return _stage->GetTimeCodesPerSecond();
+}
+
+double
+BridgeUsdDataExtractor::frames_per_second() const
+{
+  return _stage->GetFramesPerSecond();
+}
+
+rust::String
+BridgeUsdDataExtractor::default_prim() const
+{
+  // defaultPrimが設定されていない場合は空文字列を返す
+  auto prim = _stage->GetDefaultPrim();
+  if (!prim) {
+    return rust::String();
+  }
+  return rust::String(prim.GetPath().GetText());
+}
+
+UsdPrim
+BridgeUsdDataExtractor::_GetPrim(rust::Str path) const
+{
+  auto pathString = std::string(path);
+  if (!SdfPath::IsValidPathString(pathString)) {
+    throw std::runtime_error("Invalid prim path");
+  }
+  auto prim = _stage->GetPrimAtPath(SdfPath(pathString));
+  if (!prim) {
+    throw std::runtime_error("Prim not found");
+  }
+  return prim;
+}
+
+UsdAttribute
+BridgeUsdDataExtractor::_GetOrCreateAttribute(
+  rust::Str path,
+  rust::Str name,
+  const SdfValueTypeName& typeName) const
+{
+  auto prim = _GetPrim(path);
+  auto nameToken = TfToken(std::string(name));
+  auto attr = prim.GetAttribute(nameToken);
+  if (attr) {
+    return attr;
+  }
+  // 属性が存在しない場合はsession layerに作る
+  attr = prim.CreateAttribute(nameToken, typeName);
+  if (!attr) {
+    throw std::runtime_error("Failed to create attribute");
+  }
+  return attr;
+}
+
+void
+BridgeUsdDataExtractor::_SetAttribute(rust::Str path,
+                                      rust::Str name,
+                                      rust::Str typeName,
+                                      const VtValue& value) const
+{
+  auto sdfTypeName =
+    SdfSchema::GetInstance().FindType(std::string(typeName));
+  if (!sdfTypeName) {
+    throw std::runtime_error("Unknown attribute type");
+  }
+  auto attr = _GetOrCreateAttribute(path, name, sdfTypeName);
+  if (!attr.Set(value)) {
+    throw std::runtime_error("Failed to set attribute value");
+  }
+}
+
+// 要素数がtypeNameのtuple次元の倍数のスカラー列をVtValueに変換する
+template<typename T, typename V2, typename V3, typename V4>
+static VtValue
+_ToValue(const SdfValueTypeName& typeName, rust::Slice<const T> values)
+{
+  // matrixなどの2次元のtupleには未対応
+  auto dimensions = typeName.GetDimensions();
+  if (dimensions.size > 1) {
+    throw std::runtime_error("Unsupported attribute type");
+  }
+  size_t size = dimensions.size == 0 ? 1 : dimensions.d[0];
+  if (size == 0 || values.size() % size != 0) {
+    throw std::runtime_error("Value length does not match attribute type");
+  }
+  auto count = values.size() / size;
+  auto data = values.data();
+
+  if (typeName.IsArray()) {
+    if (size == 1) {
+      return VtValue(VtArray<T>(data, data + count));
+    }
+    if (size == 2) {
+      VtArray<V2> array(count);
+      for (size_t i = 0; i < count; i++) {
+        array[i] = V2(data + i * 2);
+      }
+      return VtValue(array);
+    }
+    if (size == 3) {
+      VtArray<V3> array(count);
+      for (size_t i = 0; i < count; i++) {
+        array[i] = V3(data + i * 3);
+      }
+      return VtValue(array);
+    }
+    if (size == 4) {
+      VtArray<V4> array(count);
+      for (size_t i = 0; i < count; i++) {
+        array[i] = V4(data + i * 4);
+      }
+      return VtValue(array);
+    }
+  } else if (count == 1) {
+    if (size == 1) {
+      return VtValue(data[0]);
+    }
+    if (size == 2) {
+      return VtValue(V2(data));
+    }
+    if (size == 3) {
+      return VtValue(V3(data));
+    }
+    if (size == 4) {
+      return VtValue(V4(data));
+    }
+  }
+  throw std::runtime_error("Value length does not match attribute type");
+}
+
+void
+BridgeUsdDataExtractor::set_attribute_bool(rust::Str path,
+                                           rust::Str name,
+                                           bool value)
+{
+  _SetAttribute(path, name, "bool", VtValue(value));
+}
+
+void
+BridgeUsdDataExtractor::set_attribute_int(rust::Str path,
+                                          rust::Str name,
+                                          rust::Str typeName,
+                                          rust::Slice<const int32_t> values)
+{
+  auto sdfTypeName =
+    SdfSchema::GetInstance().FindType(std::string(typeName));
+  if (!sdfTypeName) {
+    throw std::runtime_error("Unknown attribute type");
+  }
+  if (sdfTypeName.IsArray()) {
+    auto data = values.data();
+    _SetAttribute(
+      path, name, typeName, VtValue(VtIntArray(data, data + values.size())));
+  } else if (values.size() == 1) {
+    _SetAttribute(path, name, typeName, VtValue(int(values[0])));
+  } else {
+    throw std::runtime_error("Value length does not match attribute type");
+  }
+}
+
+void
+BridgeUsdDataExtractor::set_attribute_float(rust::Str path,
+                                            rust::Str name,
+                                            rust::Str typeName,
+                                            rust::Slice<const float> values)
+{
+  auto sdfTypeName =
+    SdfSchema::GetInstance().FindType(std::string(typeName));
+  if (!sdfTypeName) {
+    throw std::runtime_error("Unknown attribute type");
+  }
+  auto value = _ToValue<float, GfVec2f, GfVec3f, GfVec4f>(sdfTypeName, values);
+  _SetAttribute(path, name, typeName, value);
+}
+
+void
+BridgeUsdDataExtractor::set_attribute_double(rust::Str path,
+                                             rust::Str name,
+                                             rust::Str typeName,
+                                             rust::Slice<const double> values)
+{
+  auto sdfTypeName =
+    SdfSchema::GetInstance().FindType(std::string(typeName));
+  if (!sdfTypeName) {
+    throw std::runtime_error("Unknown attribute type");
+  }
+  auto value =
+    _ToValue<double, GfVec2d, GfVec3d, GfVec4d>(sdfTypeName, values);
+  _SetAttribute(path, name, typeName, value);
+}
+
+void
+BridgeUsdDataExtractor::set_attribute_string(rust::Str path,
+                                             rust::Str name,
+                                             rust::Str typeName,
+                                             rust::Str value)
+{
+  auto typeNameString = std::string(typeName);
+  auto valueString = std::string(value);
+  if (typeNameString == "string") {
+    _SetAttribute(path, name, typeName, VtValue(valueString));
+  } else if (typeNameString == "token") {
+    _SetAttribute(path, name, typeName, VtValue(TfToken(valueString)));
+  } else if (typeNameString == "asset") {
+    _SetAttribute(path, name, typeName, VtValue(SdfAssetPath(valueString)));
+  } else {
+    throw std::runtime_error("Unknown attribute type");
+  }
+}
+
+void
+BridgeUsdDataExtractor::set_transform(rust::Str path,
+                                      rust::Slice<const float> matrix)
+{
+  if (matrix.size() != 16) {
+    throw std::runtime_error("Transform matrix must have 16 elements");
+  }
+  auto xformable = UsdGeomXformable(_GetPrim(path));
+  if (!xformable) {
+    throw std::runtime_error("Prim is not xformable");
+  }
+  // Rust側のmatrixはcolumn-majorなので、USDのrow-majorの配列と同じ並びになる
+  GfMatrix4d value;
+  auto data = value.GetArray();
+  for (int i = 0; i < 16; i++) {
+    data[i] = matrix[i];
+  }
+  // xformOpOrderを単一のtransform opに置き換えてmatrixを設定する
+  auto op = xformable.MakeMatrixXform();
+  if (!op || !op.Set(value)) {
+    throw std::runtime_error("Failed to set transform");
+  }
+}
+
+void
+BridgeUsdDataExtractor::set_active(rust::Str path, bool active)
+{
+  // 非activeなprimはGetPrimAtPathで取得できるのでそのまま編集できる
+  auto prim = _GetPrim(path);
+  if (!prim.SetActive(active)) {
+    throw std::runtime_error("Failed to set active");
+  }
+}
+
+void
+BridgeUsdDataExtractor::set_visibility(rust::Str path, bool visible)
+{
+  auto imageable = UsdGeomImageable(_GetPrim(path));
+  if (!imageable) {
+    throw std::runtime_error("Prim is not imageable");
+  }
+  auto value = visible ? UsdGeomTokens->inherited : UsdGeomTokens->invisible;
+  if (!imageable.CreateVisibilityAttr().Set(value)) {
+    throw std::runtime_error("Failed to set visibility");
+  }
+}
+
+void
+BridgeUsdDataExtractor::set_variant_selection(rust::Str path,
+                                              rust::Str variantSet,
+                                              rust::Str variant)
+{
+  auto prim = _GetPrim(path);
+  auto variantSets = prim.GetVariantSets();
+  auto variantSetName = std::string(variantSet);
+  if (!variantSets.HasVariantSet(variantSetName)) {
+    throw std::runtime_error("Variant set not found");
+  }
+  if (!variantSets.SetSelection(variantSetName, std::string(variant))) {
+    throw std::runtime_error("Failed to set variant selection");
+  }
+}
+
+void
+BridgeUsdDataExtractor::define_prim(rust::Str path, rust::Str typeName)
+{
+  auto pathString = std::string(path);
+  if (!SdfPath::IsValidPathString(pathString)) {
+    throw std::runtime_error("Invalid prim path");
+  }
+  auto prim =
+    _stage->DefinePrim(SdfPath(pathString), TfToken(std::string(typeName)));
+  if (!prim) {
+    throw std::runtime_error("Failed to define prim");
+  }
+}
+
+void
+BridgeUsdDataExtractor::remove_prim(rust::Str path)
+{
+  // root layerで定義されているprimはsession layerからは削除できないので、
+  // specは削除せず、どのlayerで定義されたprimも非activeにして取り除く。
+  // session layerのspecを残すので、set_activeで元に戻せる
+  auto prim = _GetPrim(path);
+  if (!prim.SetActive(false)) {
+    throw std::runtime_error("Failed to remove prim");
+  }
+}
+
+void
+BridgeUsdDataExtractor::load_payloads(rust::Str path)
+{
+  // pathとその子孫のpayloadをloadする
+  auto prim = _GetPrim(path);
+  _stage->Load(prim.GetPath());
+}
+
+void
+BridgeUsdDataExtractor::unload_payloads(rust::Str path)
+{
+  // pathとその子孫のpayloadをunloadする
+  auto prim = _GetPrim(path);
+  _stage->Unload(prim.GetPath());
+}
+
+void
+BridgeUsdDataExtractor::set_population_mask(
+  rust::Slice<const rust::String> populationMask,
+  bool useMask)
+{
+  if (useMask) {
+    _stage->SetPopulationMask(_CreatePopulationMask(populationMask));
+  } else {
+    _stage->SetPopulationMask(UsdStagePopulationMask::All());
+  }
+}
+
+rust::Vec<rust::String>
+BridgeUsdDataExtractor::variant_set_prim_paths() const
+{
+  // variant setを持つprimのパスをstage全体から集める
+  rust::Vec<rust::String> paths;
+  for (const auto& prim : _stage->Traverse()) {
+    if (prim.HasVariantSets()) {
+      paths.push_back(rust::String(prim.GetPath().GetText()));
+    }
+  }
+  return paths;
+}
+
+rust::Vec<rust::String>
+BridgeUsdDataExtractor::variant_set_names(rust::Str path) const
+{
+  auto prim = _GetPrim(path);
+  rust::Vec<rust::String> names;
+  for (const auto& name : prim.GetVariantSets().GetNames()) {
+    names.push_back(rust::String(name));
+  }
+  return names;
+}
+
+rust::Vec<rust::String>
+BridgeUsdDataExtractor::variant_names(rust::Str path,
+                                      rust::Str variantSet) const
+{
+  auto prim = _GetPrim(path);
+  auto variantSetName = std::string(variantSet);
+  if (!prim.HasVariantSets() ||
+      !prim.GetVariantSets().HasVariantSet(variantSetName)) {
+    throw std::runtime_error("Variant set not found");
+  }
+  auto variantSetObject = prim.GetVariantSet(variantSetName);
+  rust::Vec<rust::String> names;
+  for (const auto& name : variantSetObject.GetVariantNames()) {
+    names.push_back(rust::String(name));
+  }
+  return names;
+}
+
+rust::String
+BridgeUsdDataExtractor::variant_selection(rust::Str path,
+                                          rust::Str variantSet) const
+{
+  auto prim = _GetPrim(path);
+  auto variantSetName = std::string(variantSet);
+  if (!prim.HasVariantSets() ||
+      !prim.GetVariantSets().HasVariantSet(variantSetName)) {
+    throw std::runtime_error("Variant set not found");
+  }
+  // selectionがない場合は空文字列になる
+  return rust::String(
+    prim.GetVariantSet(variantSetName).GetVariantSelection());
+}
+
+// USDA文字列からanonymous layerを作る
+static SdfLayerRefPtr
+_CreateAnonymousLayer(rust::Str usda, const std::string& tag)
+{
+  SdfLayerRefPtr layer = SdfLayer::CreateAnonymous(tag + ".usda");
+  // 空文字列の場合は空のlayerとして扱う
+  if (usda.empty()) {
+    return layer;
+  }
+  if (!layer->ImportFromString(std::string(usda))) {
+    throw std::runtime_error("Failed to parse " + tag + " layer");
+  }
+  return layer;
+}
+
 std::unique_ptr<BridgeUsdDataExtractor>
-new_usd_data_extractor(rust::Str openPath)
+new_usd_data_extractor(rust::Str openPath,
+                       rust::Slice<const rust::String> populationMask,
+                       bool useMask,
+                       bool loadPayloads)
 {
-  return std::make_unique<BridgeUsdDataExtractor>(std::string(openPath));
+  auto load = loadPayloads ? UsdStage::LoadAll : UsdStage::LoadNone;
+  UsdStageRefPtr stage;
+  if (useMask) {
+    stage = UsdStage::OpenMasked(
+      std::string(openPath), _CreatePopulationMask(populationMask), load);
+  } else {
+    stage = UsdStage::Open(std::string(openPath), load);
+  }
+  if (!stage) {
+    throw std::runtime_error("Failed to open stage");
+  }
+  return std::make_unique<BridgeUsdDataExtractor>(stage);
+}
+
+std::unique_ptr<BridgeUsdDataExtractor>
+new_usd_data_extractor_from_usda_str(rust::Str usda)
+{
+  SdfLayerRefPtr rootLayer = _CreateAnonymousLayer(usda, "root");
+  UsdStageRefPtr stage = UsdStage::Open(rootLayer);
+  if (!stage) {
+    throw std::runtime_error("Failed to open stage");
+  }
+  return std::make_unique<BridgeUsdDataExtractor>(stage);
+}
+
+// identifierのlayerを開く。既に開かれているlayerやanonymous layerはそのまま使う
+static SdfLayerRefPtr
+_FindOrOpenLayer(rust::Str identifier, const std::string& tag)
+{
+  SdfLayerRefPtr layer = SdfLayer::FindOrOpen(std::string(identifier));
+  if (!layer) {
+    throw std::runtime_error("Failed to open " + tag + " layer " +
+                             std::string(identifier));
+  }
+  return layer;
+}
+
+std::unique_ptr<BridgeUsdDataExtractor>
+new_usd_data_extractor_from_layers(rust::Str rootIdentifier,
+                                   rust::Str sessionIdentifier)
+{
+  SdfLayerRefPtr rootLayer = _FindOrOpenLayer(rootIdentifier, "root");
+  // 空文字列の場合は空のsession layerを使う
+  SdfLayerRefPtr sessionLayer =
+    sessionIdentifier.empty() ? SdfLayer::CreateAnonymous("session.usda")
+                              : _FindOrOpenLayer(sessionIdentifier, "session");
+  UsdStageRefPtr stage = UsdStage::Open(rootLayer, sessionLayer);
+  if (!stage) {
+    throw std::runtime_error("Failed to open stage");
+  }
+  return std::make_unique<BridgeUsdDataExtractor>(stage);
+}
+
+std::unique_ptr<BridgeUsdDataExtractor>
+new_usd_data_extractor_from_usda_layers(rust::Str rootUsda,
+                                        rust::Str sessionUsda)
+{
+  SdfLayerRefPtr rootLayer = _CreateAnonymousLayer(rootUsda, "root");
+  SdfLayerRefPtr sessionLayer = _CreateAnonymousLayer(sessionUsda, "session");
+  UsdStageRefPtr stage = UsdStage::Open(rootLayer, sessionLayer);
+  if (!stage) {
+    throw std::runtime_error("Failed to open stage");
+  }
+  return std::make_unique<BridgeUsdDataExtractor>(stage);
 }
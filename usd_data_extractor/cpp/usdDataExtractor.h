@@ -4,12 +4,19 @@
 #include "pxr/imaging/hd/tokens.h"
 #include "pxr/imaging/hd/utils.h"
 #include "pxr/pxr.h"
+#include "pxr/usd/sdf/layer.h"
 #include "pxr/usd/sdf/path.h"
+#include "pxr/usd/sdf/schema.h"
 #include "pxr/usd/usd/stage.h"
+#include "pxr/usd/usd/variantSets.h"
+#include "pxr/usd/usdGeom/imageable.h"
+#include "pxr/usd/usdGeom/metrics.h"
+#include "pxr/usd/usdGeom/xformable.h"
 #include "pxr/usdImaging/usdImaging/sceneIndices.h"
 #include "pxr/usdImaging/usdImaging/stageSceneIndex.h"
 #include "rust/cxx.h"
 #include "sceneIndexObserver.h"
+#include "skelObserver.h"
 #include "usdDataDiff.h"
 #include <iostream>
 #include <memory>
@@ -19,16 +26,74 @@ using namespace pxr;
 class BridgeUsdDataExtractor
 {
 public:
-  BridgeUsdDataExtractor(std::string openPath);
+  BridgeUsdDataExtractor(UsdStageRefPtr stage);
   virtual ~BridgeUsdDataExtractor();
 
   double start_time_code() const { return _startTimeCode; }
   double end_time_code() const { return _endTimeCode; }
 
+  // stageのlayer metadataを取得する関数
+  rust::String up_axis() const;
+  double meters_per_unit() const;
+  double time_codes_per_second() const;
+  double frames_per_second() const;
+  rust::String default_prim() const;
+
   void extract(double timeCode, UsdDataDiff& diff);
 
+  // session layerにstageの編集を書き込む関数
+  // 編集は次のextractでdiffとして通知される
+  void set_attribute_bool(rust::Str path, rust::Str name, bool value);
+  void set_attribute_int(rust::Str path,
+                         rust::Str name,
+                         rust::Str typeName,
+                         rust::Slice<const int32_t> values);
+  void set_attribute_float(rust::Str path,
+                           rust::Str name,
+                           rust::Str typeName,
+                           rust::Slice<const float> values);
+  void set_attribute_double(rust::Str path,
+                            rust::Str name,
+                            rust::Str typeName,
+                            rust::Slice<const double> values);
+  void set_attribute_string(rust::Str path,
+                            rust::Str name,
+                            rust::Str typeName,
+                            rust::Str value);
+  void set_transform(rust::Str path, rust::Slice<const float> matrix);
+  void set_active(rust::Str path, bool active);
+  void set_visibility(rust::Str path, bool visible);
+  void set_variant_selection(rust::Str path,
+                             rust::Str variantSet,
+                             rust::Str variant);
+  void define_prim(rust::Str path, rust::Str typeName);
+  void remove_prim(rust::Str path);
+
+  // payloadのload/unloadとpopulation maskの変更を行う関数
+  // 変更によってシーンに現れた/消えたprimは次のextractでdiffとして通知される
+  void load_payloads(rust::Str path);
+  void unload_payloads(rust::Str path);
+  void set_population_mask(rust::Slice<const rust::String> populationMask,
+                           bool useMask);
+
+  // stageのvariant setの情報を取得する関数
+  rust::Vec<rust::String> variant_set_prim_paths() const;
+  rust::Vec<rust::String> variant_set_names(rust::Str path) const;
+  rust::Vec<rust::String> variant_names(rust::Str path,
+                                        rust::Str variantSet) const;
+  rust::String variant_selection(rust::Str path, rust::Str variantSet) const;
+
 private:
-  std::string _openPath;
+  UsdPrim _GetPrim(rust::Str path) const;
+  UsdAttribute _GetOrCreateAttribute(rust::Str path,
+                                     rust::Str name,
+                                     const SdfValueTypeName& typeName) const;
+  void _SetAttribute(rust::Str path,
+                     rust::Str name,
+                     rust::Str typeName,
+                     const VtValue& value) const;
+
+
   UsdStageRefPtr _stage;
   double _startTimeCode;
   double _endTimeCode;
@@ -36,11 +101,26 @@ private:
   bool _isFirstExtract = true;
 
   HdBridgeSceneIndexObserver _observer;
+  SkelObserver _skelObserver;
   UsdImagingStageSceneIndexRefPtr _stageSceneIndex;
   HdSceneIndexBaseRefPtr _sceneIndex;
 };
 
 std::unique_ptr<BridgeUsdDataExtractor>
-new_usd_data_extractor(rust::Str openPath);
+new_usd_data_extractor(rust::Str openPath,
+                       rust::Slice<const rust::String> populationMask,
+                       bool useMask,
+                       bool loadPayloads);
+
+std::unique_ptr<BridgeUsdDataExtractor>
+new_usd_data_extractor_from_usda_str(rust::Str usda);
+
+std::unique_ptr<BridgeUsdDataExtractor>
+new_usd_data_extractor_from_layers(rust::Str rootIdentifier,
+                                   rust::Str sessionIdentifier);
+
+std::unique_ptr<BridgeUsdDataExtractor>
+new_usd_data_extractor_from_usda_layers(rust::Str rootUsda,
+                                        rust::Str sessionUsda);
 
 #endif
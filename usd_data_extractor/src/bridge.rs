@@ -1,5 +1,9 @@
 use std::collections::HashMap;
 
+#[cfg(feature = "mock")]
+pub mod mock;
+
+#[cfg(not(feature = "mock"))]
 #[cxx::bridge]
 pub mod ffi {
     #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
@@ -12,6 +16,39 @@ pub mod ffi {
         Instance,
     }
 
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+    pub enum PrimvarType {
+        Float,
+        Float2,
+        Float3,
+        Float4,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+    pub enum SubdivisionScheme {
+        None,
+        CatmullClark,
+        Loop,
+        Bilinear,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+    pub enum BoundaryInterpolation {
+        None,
+        EdgeOnly,
+        EdgeAndCorner,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+    pub enum FaceVaryingLinearInterpolation {
+        None,
+        CornersOnly,
+        CornersPlus1,
+        CornersPlus2,
+        Boundaries,
+        All,
+    }
+
     extern "Rust" {
         type UsdDataDiff;
 
@@ -20,13 +57,41 @@ pub mod ffi {
         fn create_mesh_transform_matrix(&mut self, path: String, matrix: &[f32]);
         fn create_mesh_left_handed(&mut self, path: String, left_handed: bool);
         fn create_mesh_points(&mut self, path: String, data: &[f32]);
+        fn create_mesh_extent(&mut self, path: String, extent: &[f32]);
         fn create_mesh_normals(&mut self, path: String, data: &[f32]);
+        fn create_mesh_normals_indices(&mut self, path: String, data: &[u32]);
         fn create_mesh_normals_interpolation(&mut self, path: String, interpolation: Interpolation);
         fn create_mesh_uvs(&mut self, path: String, data: &[f32]);
         fn create_mesh_uvs_indices(&mut self, path: String, data: &[u32]);
         fn create_mesh_uvs_interpolation(&mut self, path: String, interpolation: Interpolation);
+        fn create_mesh_primvar(
+            &mut self,
+            path: String,
+            name: String,
+            element_type: PrimvarType,
+            interpolation: Interpolation,
+            data: &[f32],
+        );
+        fn create_mesh_primvar_indices(&mut self, path: String, name: String, data: &[u32]);
         fn create_mesh_face_vertex_indices(&mut self, path: String, data: &[u32]);
         fn create_mesh_face_vertex_counts(&mut self, path: String, data: &[u32]);
+        fn create_mesh_hole_indices(&mut self, path: String, data: &[u32]);
+        fn create_mesh_subdivision_scheme(&mut self, path: String, scheme: SubdivisionScheme);
+        fn create_mesh_interpolate_boundary(&mut self, path: String, value: BoundaryInterpolation);
+        fn create_mesh_face_varying_linear_interpolation(
+            &mut self,
+            path: String,
+            value: FaceVaryingLinearInterpolation,
+        );
+        fn create_mesh_creases(
+            &mut self,
+            path: String,
+            indices: &[u32],
+            lengths: &[u32],
+            sharpnesses: &[f32],
+        );
+        fn create_mesh_corners(&mut self, path: String, indices: &[u32], sharpnesses: &[f32]);
+        fn create_mesh_refine_level(&mut self, path: String, level: u32);
         fn create_mesh_geom_subset(
             &mut self,
             path: String,
@@ -52,7 +117,9 @@ pub mod ffi {
         fn diff_mesh_data(&mut self, path: String);
         fn diff_mesh_data_left_handed(&mut self, path: String, left_handed: bool);
         fn diff_mesh_data_points(&mut self, path: String, data: &[f32]);
+        fn diff_mesh_data_extent(&mut self, path: String, extent: &[f32]);
         fn diff_mesh_data_normals(&mut self, path: String, data: &[f32]);
+        fn diff_mesh_data_normals_indices(&mut self, path: String, data: &[u32]);
         fn diff_mesh_data_normals_interpolation(
             &mut self,
             path: String,
@@ -61,8 +128,38 @@ pub mod ffi {
         fn diff_mesh_data_uvs(&mut self, path: String, data: &[f32]);
         fn diff_mesh_data_uvs_indices(&mut self, path: String, data: &[u32]);
         fn diff_mesh_data_uvs_interpolation(&mut self, path: String, interpolation: Interpolation);
+        fn diff_mesh_data_primvar(
+            &mut self,
+            path: String,
+            name: String,
+            element_type: PrimvarType,
+            interpolation: Interpolation,
+            data: &[f32],
+        );
+        fn diff_mesh_data_primvar_indices(&mut self, path: String, name: String, data: &[u32]);
         fn diff_mesh_data_face_vertex_indices(&mut self, path: String, data: &[u32]);
         fn diff_mesh_data_face_vertex_counts(&mut self, path: String, data: &[u32]);
+        fn diff_mesh_data_hole_indices(&mut self, path: String, data: &[u32]);
+        fn diff_mesh_data_subdivision_scheme(&mut self, path: String, scheme: SubdivisionScheme);
+        fn diff_mesh_data_interpolate_boundary(
+            &mut self,
+            path: String,
+            value: BoundaryInterpolation,
+        );
+        fn diff_mesh_data_face_varying_linear_interpolation(
+            &mut self,
+            path: String,
+            value: FaceVaryingLinearInterpolation,
+        );
+        fn diff_mesh_data_creases(
+            &mut self,
+            path: String,
+            indices: &[u32],
+            lengths: &[u32],
+            sharpnesses: &[f32],
+        );
+        fn diff_mesh_data_corners(&mut self, path: String, indices: &[u32], sharpnesses: &[f32]);
+        fn diff_mesh_data_refine_level(&mut self, path: String, level: u32);
         fn diff_mesh_data_geom_subset(
             &mut self,
             path: String,
@@ -78,6 +175,17 @@ pub mod ffi {
         );
         fn diff_mesh_material_binding(&mut self, path: String, material_path: String);
 
+        // meshのtopologyは変わらずにpointsとnormals、extentだけが編集されたdiffの記録とそのデータを設定する関数
+        fn diff_mesh_points(&mut self, path: String, data: &[f32]);
+        fn diff_mesh_points_extent(&mut self, path: String, extent: &[f32]);
+        fn diff_mesh_points_normals(&mut self, path: String, data: &[f32]);
+        fn diff_mesh_points_normals_indices(&mut self, path: String, data: &[u32]);
+        fn diff_mesh_points_normals_interpolation(
+            &mut self,
+            path: String,
+            interpolation: Interpolation,
+        );
+
         // sphere lightが生成/更新されたdiffの記録とそのデータを設定する関数
         fn add_or_update_sphere_light(&mut self, path: String);
         fn add_or_update_sphere_light_transform_matrix(&mut self, path: String, matrix: &[f32]);
@@ -135,76 +243,375 @@ pub mod ffi {
 
         // materialが削除されたdiffを記録する関数
         fn destroy_material(&mut self, path: String);
+
+        // skeletonが生成/更新されたdiffの記録とそのデータを設定する関数
+        fn add_or_update_skeleton(
+            &mut self,
+            path: String,
+            joints: &[String],
+            parents: &[i32],
+            bind_transforms: &[f32],
+            rest_transforms: &[f32],
+        );
+
+        // skeletonが削除されたdiffを記録する関数
+        fn destroy_skeleton(&mut self, path: String);
+
+        // skeletonのポーズが変わったdiffを記録する関数
+        fn update_skeleton_pose(
+            &mut self,
+            path: String,
+            transform_matrix: &[f32],
+            skinning_transforms: &[f32],
+        );
+
+        // meshのskinningの束縛が生成/更新/削除されたdiffを記録する関数
+        fn add_or_update_mesh_skin_binding(
+            &mut self,
+            path: String,
+            skeleton_path: String,
+            interpolation: Interpolation,
+            joint_indices: &[u32],
+            joint_weights: &[f32],
+            geom_bind_transform: &[f32],
+        );
+        fn destroy_mesh_skin_binding(&mut self, path: String);
+
+        // meshのblend shapeが生成/更新されたdiffを記録する関数
+        // add_mesh_blend_shapeをmeshのblendShapesの順に呼び、
+        // inbetweenはその直前に追加したblend shapeに追加する。
+        // normal_offsetsとpoint_indicesが空の場合はauthorされていない
+        fn add_mesh_blend_shape(
+            &mut self,
+            path: String,
+            name: String,
+            offsets: &[f32],
+            normal_offsets: &[f32],
+            point_indices: &[u32],
+        );
+        fn add_mesh_blend_shape_inbetween(
+            &mut self,
+            path: String,
+            weight: f32,
+            offsets: &[f32],
+            normal_offsets: &[f32],
+        );
+        fn destroy_mesh_blend_shapes(&mut self, path: String);
+
+        // meshのblend shapeのweightが変わったdiffを記録する関数
+        fn update_mesh_blend_shape_weights(&mut self, path: String, weights: &[f32]);
+
+        // instancerが生成/更新されたdiffの記録とそのデータを設定する関数
+        // instance_transformsはinstanceごとに16個の値を並べたもので、
+        // instance_indicesはprototypeが使うinstanceのindex
+        fn add_or_update_instancer(&mut self, path: String);
+        fn add_or_update_instancer_transform_matrix(&mut self, path: String, matrix: &[f32]);
+        fn add_or_update_instancer_instance_transforms(&mut self, path: String, data: &[f32]);
+        fn add_or_update_instancer_prototype(
+            &mut self,
+            path: String,
+            prototype_path: String,
+            instance_indices: &[u32],
+        );
+        fn add_or_update_instancer_primvar(
+            &mut self,
+            path: String,
+            name: String,
+            element_type: PrimvarType,
+            values: &[f32],
+        );
+
+        // instancerが削除されたdiffを記録する関数
+        fn destroy_instancer(&mut self, path: String);
     }
     unsafe extern "C++" {
         include!("usd_data_extractor/cpp/usdDataExtractor.h");
 
         type BridgeUsdDataExtractor;
-        fn new_usd_data_extractor(open_path: &str) -> Result<UniquePtr<BridgeUsdDataExtractor>>;
+        fn new_usd_data_extractor(
+            open_path: &str,
+            population_mask: &[String],
+            use_mask: bool,
+            load_payloads: bool,
+        ) -> Result<UniquePtr<BridgeUsdDataExtractor>>;
+        fn new_usd_data_extractor_from_usda_str(
+            usda: &str,
+        ) -> Result<UniquePtr<BridgeUsdDataExtractor>>;
+        fn new_usd_data_extractor_from_layers(
+            root_identifier: &str,
+            session_identifier: &str,
+        ) -> Result<UniquePtr<BridgeUsdDataExtractor>>;
+        fn new_usd_data_extractor_from_usda_layers(
+            root_usda: &str,
+            session_usda: &str,
+        ) -> Result<UniquePtr<BridgeUsdDataExtractor>>;
         fn start_time_code(self: &BridgeUsdDataExtractor) -> f64;
         fn end_time_code(self: &BridgeUsdDataExtractor) -> f64;
+        fn up_axis(self: &BridgeUsdDataExtractor) -> String;
+        fn meters_per_unit(self: &BridgeUsdDataExtractor) -> f64;
+        fn time_codes_per_second(self: &BridgeUsdDataExtractor) -> f64;
+        fn frames_per_second(self: &BridgeUsdDataExtractor) -> f64;
+        fn default_prim(self: &BridgeUsdDataExtractor) -> String;
         fn extract(
             self: Pin<&mut BridgeUsdDataExtractor>,
             time_code: f64,
             scene_diff: Pin<&mut UsdDataDiff>,
         );
+
+        // session layerにstageの編集を書き込む関数
+        fn set_attribute_bool(
+            self: Pin<&mut BridgeUsdDataExtractor>,
+            path: &str,
+            name: &str,
+            value: bool,
+        ) -> Result<()>;
+        fn set_attribute_int(
+            self: Pin<&mut BridgeUsdDataExtractor>,
+            path: &str,
+            name: &str,
+            type_name: &str,
+            values: &[i32],
+        ) -> Result<()>;
+        fn set_attribute_float(
+            self: Pin<&mut BridgeUsdDataExtractor>,
+            path: &str,
+            name: &str,
+            type_name: &str,
+            values: &[f32],
+        ) -> Result<()>;
+        fn set_attribute_double(
+            self: Pin<&mut BridgeUsdDataExtractor>,
+            path: &str,
+            name: &str,
+            type_name: &str,
+            values: &[f64],
+        ) -> Result<()>;
+        fn set_attribute_string(
+            self: Pin<&mut BridgeUsdDataExtractor>,
+            path: &str,
+            name: &str,
+            type_name: &str,
+            value: &str,
+        ) -> Result<()>;
+        fn set_transform(
+            self: Pin<&mut BridgeUsdDataExtractor>,
+            path: &str,
+            matrix: &[f32],
+        ) -> Result<()>;
+        fn set_active(
+            self: Pin<&mut BridgeUsdDataExtractor>,
+            path: &str,
+            active: bool,
+        ) -> Result<()>;
+        fn set_visibility(
+            self: Pin<&mut BridgeUsdDataExtractor>,
+            path: &str,
+            visible: bool,
+        ) -> Result<()>;
+        fn set_variant_selection(
+            self: Pin<&mut BridgeUsdDataExtractor>,
+            path: &str,
+            variant_set: &str,
+            variant: &str,
+        ) -> Result<()>;
+        fn define_prim(
+            self: Pin<&mut BridgeUsdDataExtractor>,
+            path: &str,
+            type_name: &str,
+        ) -> Result<()>;
+        fn remove_prim(self: Pin<&mut BridgeUsdDataExtractor>, path: &str) -> Result<()>;
+
+        // payloadのload/unloadとpopulation maskの変更を行う関数
+        fn load_payloads(self: Pin<&mut BridgeUsdDataExtractor>, path: &str) -> Result<()>;
+        fn unload_payloads(self: Pin<&mut BridgeUsdDataExtractor>, path: &str) -> Result<()>;
+        fn set_population_mask(
+            self: Pin<&mut BridgeUsdDataExtractor>,
+            population_mask: &[String],
+            use_mask: bool,
+        ) -> Result<()>;
+
+        // stageのvariant setの情報を取得する関数
+        fn variant_set_prim_paths(self: &BridgeUsdDataExtractor) -> Vec<String>;
+        fn variant_set_names(self: &BridgeUsdDataExtractor, path: &str) -> Result<Vec<String>>;
+        fn variant_names(
+            self: &BridgeUsdDataExtractor,
+            path: &str,
+            variant_set: &str,
+        ) -> Result<Vec<String>>;
+        fn variant_selection(
+            self: &BridgeUsdDataExtractor,
+            path: &str,
+            variant_set: &str,
+        ) -> Result<String>;
     }
 }
 
-pub use ffi::Interpolation;
+// mock backendではcxxのbridgeを使わないので、shared enumをRust側で定義する
+#[cfg(feature = "mock")]
+pub mod ffi {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+    pub enum Interpolation {
+        Constant,
+        Uniform,
+        Varying,
+        Vertex,
+        FaceVarying,
+        Instance,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+    pub enum PrimvarType {
+        Float,
+        Float2,
+        Float3,
+        Float4,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+    pub enum SubdivisionScheme {
+        None,
+        CatmullClark,
+        Loop,
+        Bilinear,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+    pub enum BoundaryInterpolation {
+        None,
+        EdgeOnly,
+        EdgeAndCorner,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+    pub enum FaceVaryingLinearInterpolation {
+        None,
+        CornersOnly,
+        CornersPlus1,
+        CornersPlus2,
+        Boundaries,
+        All,
+    }
+}
 
-#[derive(Debug, Default, Hash, PartialEq, Eq)]
+pub use ffi::{
+    BoundaryInterpolation, FaceVaryingLinearInterpolation, Interpolation, PrimvarType,
+    SubdivisionScheme,
+};
+impl PrimvarType {
+    /// 1要素あたりのfloatの数
+    pub fn component_count(&self) -> usize {
+        match *self {
+            PrimvarType::Float => 1,
+            PrimvarType::Float2 => 2,
+            PrimvarType::Float3 => 3,
+            _ => 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Hash, PartialEq, Eq)]
 pub struct SdfPath(String);
+impl SdfPath {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
 impl Into<String> for SdfPath {
     fn into(self) -> String {
         self.0
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Clone, Default)]
 pub struct SubMeshData {
     pub indices_type: String,
     pub indices: Vec<u32>,
     pub material_path: Option<String>,
 }
 
+#[derive(Debug, Clone)]
+pub struct PrimvarData {
+    pub element_type: PrimvarType,
+    pub interpolation: Interpolation,
+    pub values: Vec<f32>,
+    pub indices: Option<Vec<u32>>,
+}
+
+/// subdivision surfaceのcreaseとcornerなどの設定
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SubdivisionTags {
+    pub interpolate_boundary: Option<BoundaryInterpolation>,
+    pub face_varying_linear_interpolation: Option<FaceVaryingLinearInterpolation>,
+    pub crease_indices: Vec<u32>,
+    pub crease_lengths: Vec<u32>,
+    pub crease_sharpnesses: Vec<f32>,
+    pub corner_indices: Vec<u32>,
+    pub corner_sharpnesses: Vec<f32>,
+}
+
 #[derive(Debug, Default)]
 pub struct MeshCreate {
     pub transform_matrix: Option<[f32; 16]>,
     pub left_handed: Option<bool>,
     pub points: Option<Vec<f32>>,
+    pub extent: Option<[f32; 6]>,
     pub normals: Option<Vec<f32>>,
+    pub normals_indices: Option<Vec<u32>>,
     pub normals_interpolation: Option<Interpolation>,
     pub uvs: Option<Vec<f32>>,
     pub uvs_indices: Option<Vec<u32>>,
     pub uvs_interpolation: Option<Interpolation>,
+    pub primvars: HashMap<String, PrimvarData>,
     pub face_vertex_indices: Option<Vec<u32>>,
     pub face_vertex_counts: Option<Vec<u32>>,
+    pub hole_indices: Option<Vec<u32>>,
+    pub subdivision_scheme: Option<SubdivisionScheme>,
+    pub subdivision_tags: SubdivisionTags,
+    pub refine_level: Option<u32>,
     pub geom_subsets: HashMap<String, SubMeshData>,
     pub material_path: Option<String>,
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Clone, Default)]
 pub struct MeshDataDiff {
     pub left_handed: Option<bool>,
     pub points: Option<Vec<f32>>,
+    pub extent: Option<[f32; 6]>,
     pub normals: Option<Vec<f32>>,
+    pub normals_indices: Option<Vec<u32>>,
     pub normals_interpolation: Option<Interpolation>,
     pub uvs: Option<Vec<f32>>,
     pub uvs_indices: Option<Vec<u32>>,
     pub uvs_interpolation: Option<Interpolation>,
+    pub primvars: HashMap<String, PrimvarData>,
     pub face_vertex_indices: Option<Vec<u32>>,
     pub face_vertex_counts: Option<Vec<u32>>,
+    pub hole_indices: Option<Vec<u32>>,
+    pub subdivision_scheme: Option<SubdivisionScheme>,
+    pub subdivision_tags: SubdivisionTags,
+    pub refine_level: Option<u32>,
     pub geom_subsets: HashMap<String, SubMeshData>,
     pub material_path: Option<String>,
 }
 
+/// topologyが変わらないmeshのpointsとnormals、extentの差分。
+/// normalsはauthorされていればindicesとInterpolationも含めて全て入る
+#[derive(Debug, Default)]
+pub struct MeshPointsDiff {
+    pub points: Vec<f32>,
+    pub extent: Option<[f32; 6]>,
+    pub normals: Option<Vec<f32>>,
+    pub normals_indices: Option<Vec<u32>>,
+    pub normals_interpolation: Option<Interpolation>,
+}
+
 #[derive(Debug, Default)]
 pub struct MeshesDiff {
     pub create: HashMap<SdfPath, MeshCreate>,
     pub destroy: Vec<SdfPath>,
     pub diff_transform_matrix: HashMap<SdfPath, [f32; 16]>,
     pub diff_mesh_data: HashMap<SdfPath, MeshDataDiff>,
+    pub diff_points: HashMap<SdfPath, MeshPointsDiff>,
 }
 
 #[derive(Debug, Default)]
@@ -285,6 +692,81 @@ pub struct MaterialsDiff {
     pub destroy: Vec<SdfPath>,
 }
 
+/// skeletonのjointの構造とbind pose。
+/// transformはjointごとに16個の値を並べたもの
+#[derive(Debug, Default)]
+pub struct SkeletonData {
+    pub joints: Vec<String>,
+    pub parents: Vec<i32>,
+    pub bind_transforms: Vec<f32>,
+    pub rest_transforms: Vec<f32>,
+}
+
+/// skeletonのワールド座標系へのtransform matrixと、jointごとのskinning transform
+#[derive(Debug, Default)]
+pub struct SkeletonPoseData {
+    pub transform_matrix: [f32; 16],
+    pub skinning_transforms: Vec<f32>,
+}
+
+/// meshの点ごとに影響する4つのjointのindexと重み。
+/// rigidに変形するmeshの場合はInterpolationがConstantで、mesh全体で4つだけになる
+#[derive(Debug, Clone)]
+pub struct SkinBindingData {
+    pub skeleton_path: String,
+    pub interpolation: Interpolation,
+    pub joint_indices: Vec<u32>,
+    pub joint_weights: Vec<f32>,
+    pub geom_bind_transform: [f32; 16],
+}
+
+/// blend shapeのinbetweenのtarget
+#[derive(Debug, Clone, Default)]
+pub struct BlendShapeInbetweenData {
+    pub weight: f32,
+    pub offsets: Vec<f32>,
+    pub normal_offsets: Option<Vec<f32>>,
+}
+
+/// meshのblend shapeの一つのtarget。
+/// point_indicesがない場合、offsetsはmeshの全ての点に対応する
+#[derive(Debug, Clone, Default)]
+pub struct BlendShapeData {
+    pub name: String,
+    pub offsets: Vec<f32>,
+    pub normal_offsets: Option<Vec<f32>>,
+    pub point_indices: Option<Vec<u32>>,
+    pub inbetweens: Vec<BlendShapeInbetweenData>,
+}
+
+#[derive(Debug, Default)]
+pub struct SkeletonsDiff {
+    pub update: HashMap<SdfPath, SkeletonData>,
+    pub destroy: Vec<SdfPath>,
+    pub poses: HashMap<SdfPath, SkeletonPoseData>,
+    pub skin_bindings: HashMap<SdfPath, SkinBindingData>,
+    pub destroy_skin_bindings: Vec<SdfPath>,
+    pub blend_shapes: HashMap<SdfPath, Vec<BlendShapeData>>,
+    pub destroy_blend_shapes: Vec<SdfPath>,
+    pub blend_shape_weights: HashMap<SdfPath, Vec<f32>>,
+}
+
+/// instancerのtransform matrixと、instanceごとのtransformとprimvar。
+/// prototypesはprototypeのroot pathと、そのprototypeが使うinstanceのindex
+#[derive(Debug, Default)]
+pub struct InstancerDiffItem {
+    pub transform_matrix: Option<[f32; 16]>,
+    pub instance_transforms: Vec<f32>,
+    pub prototypes: Vec<(String, Vec<u32>)>,
+    pub primvars: HashMap<String, PrimvarData>,
+}
+
+#[derive(Debug, Default)]
+pub struct InstancersDiff {
+    pub update: HashMap<SdfPath, InstancerDiffItem>,
+    pub destroy: Vec<SdfPath>,
+}
+
 #[derive(Debug, Default)]
 pub struct UsdDataDiff {
     pub meshes: MeshesDiff,
@@ -293,6 +775,8 @@ pub struct UsdDataDiff {
     pub cameras: CamerasDiff,
     pub render_settings: RenderSettingsDiff,
     pub materials: MaterialsDiff,
+    pub skeletons: SkeletonsDiff,
+    pub instancers: InstancersDiff,
 }
 impl UsdDataDiff {
     // === Mesh ===
@@ -322,12 +806,25 @@ impl UsdDataDiff {
         }
     }
 
+    fn create_mesh_extent(&mut self, path: String, extent: &[f32]) {
+        let data = extent[0..6].try_into().unwrap();
+        if let Some(create) = self.meshes.create.get_mut(&SdfPath(path)) {
+            create.extent = Some(data);
+        }
+    }
+
     fn create_mesh_normals(&mut self, path: String, data: &[f32]) {
         if let Some(create) = self.meshes.create.get_mut(&SdfPath(path)) {
             create.normals = Some(data.to_vec());
         }
     }
 
+    fn create_mesh_normals_indices(&mut self, path: String, data: &[u32]) {
+        if let Some(create) = self.meshes.create.get_mut(&SdfPath(path)) {
+            create.normals_indices = Some(data.to_vec());
+        }
+    }
+
     fn create_mesh_normals_interpolation(&mut self, path: String, interpolation: Interpolation) {
         if let Some(create) = self.meshes.create.get_mut(&SdfPath(path)) {
             create.normals_interpolation = Some(interpolation);
@@ -352,6 +849,35 @@ impl UsdDataDiff {
         }
     }
 
+    fn create_mesh_primvar(
+        &mut self,
+        path: String,
+        name: String,
+        element_type: PrimvarType,
+        interpolation: Interpolation,
+        data: &[f32],
+    ) {
+        if let Some(create) = self.meshes.create.get_mut(&SdfPath(path)) {
+            create.primvars.insert(
+                name,
+                PrimvarData {
+                    element_type,
+                    interpolation,
+                    values: data.to_vec(),
+                    indices: None,
+                },
+            );
+        }
+    }
+
+    fn create_mesh_primvar_indices(&mut self, path: String, name: String, data: &[u32]) {
+        if let Some(create) = self.meshes.create.get_mut(&SdfPath(path)) {
+            if let Some(primvar) = create.primvars.get_mut(&name) {
+                primvar.indices = Some(data.to_vec());
+            }
+        }
+    }
+
     fn create_mesh_face_vertex_indices(&mut self, path: String, data: &[u32]) {
         if let Some(create) = self.meshes.create.get_mut(&SdfPath(path)) {
             create.face_vertex_indices = Some(data.to_vec());
@@ -364,6 +890,61 @@ impl UsdDataDiff {
         }
     }
 
+    fn create_mesh_hole_indices(&mut self, path: String, data: &[u32]) {
+        if let Some(create) = self.meshes.create.get_mut(&SdfPath(path)) {
+            create.hole_indices = Some(data.to_vec());
+        }
+    }
+
+    fn create_mesh_subdivision_scheme(&mut self, path: String, scheme: SubdivisionScheme) {
+        if let Some(create) = self.meshes.create.get_mut(&SdfPath(path)) {
+            create.subdivision_scheme = Some(scheme);
+        }
+    }
+
+    fn create_mesh_interpolate_boundary(&mut self, path: String, value: BoundaryInterpolation) {
+        if let Some(create) = self.meshes.create.get_mut(&SdfPath(path)) {
+            create.subdivision_tags.interpolate_boundary = Some(value);
+        }
+    }
+
+    fn create_mesh_face_varying_linear_interpolation(
+        &mut self,
+        path: String,
+        value: FaceVaryingLinearInterpolation,
+    ) {
+        if let Some(create) = self.meshes.create.get_mut(&SdfPath(path)) {
+            create.subdivision_tags.face_varying_linear_interpolation = Some(value);
+        }
+    }
+
+    fn create_mesh_creases(
+        &mut self,
+        path: String,
+        indices: &[u32],
+        lengths: &[u32],
+        sharpnesses: &[f32],
+    ) {
+        if let Some(create) = self.meshes.create.get_mut(&SdfPath(path)) {
+            create.subdivision_tags.crease_indices = indices.to_vec();
+            create.subdivision_tags.crease_lengths = lengths.to_vec();
+            create.subdivision_tags.crease_sharpnesses = sharpnesses.to_vec();
+        }
+    }
+
+    fn create_mesh_corners(&mut self, path: String, indices: &[u32], sharpnesses: &[f32]) {
+        if let Some(create) = self.meshes.create.get_mut(&SdfPath(path)) {
+            create.subdivision_tags.corner_indices = indices.to_vec();
+            create.subdivision_tags.corner_sharpnesses = sharpnesses.to_vec();
+        }
+    }
+
+    fn create_mesh_refine_level(&mut self, path: String, level: u32) {
+        if let Some(create) = self.meshes.create.get_mut(&SdfPath(path)) {
+            create.refine_level = Some(level);
+        }
+    }
+
     fn create_mesh_geom_subset(
         &mut self,
         path: String,
@@ -425,12 +1006,25 @@ impl UsdDataDiff {
         }
     }
 
+    fn diff_mesh_data_extent(&mut self, path: String, extent: &[f32]) {
+        let data = extent[0..6].try_into().unwrap();
+        if let Some(diff) = self.meshes.diff_mesh_data.get_mut(&SdfPath(path)) {
+            diff.extent = Some(data);
+        }
+    }
+
     fn diff_mesh_data_normals(&mut self, path: String, data: &[f32]) {
         if let Some(diff) = self.meshes.diff_mesh_data.get_mut(&SdfPath(path)) {
             diff.normals = Some(data.to_vec());
         }
     }
 
+    fn diff_mesh_data_normals_indices(&mut self, path: String, data: &[u32]) {
+        if let Some(diff) = self.meshes.diff_mesh_data.get_mut(&SdfPath(path)) {
+            diff.normals_indices = Some(data.to_vec());
+        }
+    }
+
     fn diff_mesh_data_normals_interpolation(&mut self, path: String, interpolation: Interpolation) {
         if let Some(diff) = self.meshes.diff_mesh_data.get_mut(&SdfPath(path)) {
             diff.normals_interpolation = Some(interpolation);
@@ -455,6 +1049,35 @@ impl UsdDataDiff {
         }
     }
 
+    fn diff_mesh_data_primvar(
+        &mut self,
+        path: String,
+        name: String,
+        element_type: PrimvarType,
+        interpolation: Interpolation,
+        data: &[f32],
+    ) {
+        if let Some(diff) = self.meshes.diff_mesh_data.get_mut(&SdfPath(path)) {
+            diff.primvars.insert(
+                name,
+                PrimvarData {
+                    element_type,
+                    interpolation,
+                    values: data.to_vec(),
+                    indices: None,
+                },
+            );
+        }
+    }
+
+    fn diff_mesh_data_primvar_indices(&mut self, path: String, name: String, data: &[u32]) {
+        if let Some(diff) = self.meshes.diff_mesh_data.get_mut(&SdfPath(path)) {
+            if let Some(primvar) = diff.primvars.get_mut(&name) {
+                primvar.indices = Some(data.to_vec());
+            }
+        }
+    }
+
     fn diff_mesh_data_face_vertex_indices(&mut self, path: String, data: &[u32]) {
         if let Some(diff) = self.meshes.diff_mesh_data.get_mut(&SdfPath(path)) {
             diff.face_vertex_indices = Some(data.to_vec());
@@ -467,6 +1090,61 @@ impl UsdDataDiff {
         }
     }
 
+    fn diff_mesh_data_hole_indices(&mut self, path: String, data: &[u32]) {
+        if let Some(diff) = self.meshes.diff_mesh_data.get_mut(&SdfPath(path)) {
+            diff.hole_indices = Some(data.to_vec());
+        }
+    }
+
+    fn diff_mesh_data_subdivision_scheme(&mut self, path: String, scheme: SubdivisionScheme) {
+        if let Some(diff) = self.meshes.diff_mesh_data.get_mut(&SdfPath(path)) {
+            diff.subdivision_scheme = Some(scheme);
+        }
+    }
+
+    fn diff_mesh_data_interpolate_boundary(&mut self, path: String, value: BoundaryInterpolation) {
+        if let Some(diff) = self.meshes.diff_mesh_data.get_mut(&SdfPath(path)) {
+            diff.subdivision_tags.interpolate_boundary = Some(value);
+        }
+    }
+
+    fn diff_mesh_data_face_varying_linear_interpolation(
+        &mut self,
+        path: String,
+        value: FaceVaryingLinearInterpolation,
+    ) {
+        if let Some(diff) = self.meshes.diff_mesh_data.get_mut(&SdfPath(path)) {
+            diff.subdivision_tags.face_varying_linear_interpolation = Some(value);
+        }
+    }
+
+    fn diff_mesh_data_creases(
+        &mut self,
+        path: String,
+        indices: &[u32],
+        lengths: &[u32],
+        sharpnesses: &[f32],
+    ) {
+        if let Some(diff) = self.meshes.diff_mesh_data.get_mut(&SdfPath(path)) {
+            diff.subdivision_tags.crease_indices = indices.to_vec();
+            diff.subdivision_tags.crease_lengths = lengths.to_vec();
+            diff.subdivision_tags.crease_sharpnesses = sharpnesses.to_vec();
+        }
+    }
+
+    fn diff_mesh_data_corners(&mut self, path: String, indices: &[u32], sharpnesses: &[f32]) {
+        if let Some(diff) = self.meshes.diff_mesh_data.get_mut(&SdfPath(path)) {
+            diff.subdivision_tags.corner_indices = indices.to_vec();
+            diff.subdivision_tags.corner_sharpnesses = sharpnesses.to_vec();
+        }
+    }
+
+    fn diff_mesh_data_refine_level(&mut self, path: String, level: u32) {
+        if let Some(diff) = self.meshes.diff_mesh_data.get_mut(&SdfPath(path)) {
+            diff.refine_level = Some(level);
+        }
+    }
+
     fn diff_mesh_data_geom_subset(
         &mut self,
         path: String,
@@ -499,6 +1177,45 @@ impl UsdDataDiff {
         }
     }
 
+    fn diff_mesh_points(&mut self, path: String, data: &[f32]) {
+        self.meshes.diff_points.insert(
+            SdfPath(path),
+            MeshPointsDiff {
+                points: data.to_vec(),
+                ..Default::default()
+            },
+        );
+    }
+
+    fn diff_mesh_points_extent(&mut self, path: String, extent: &[f32]) {
+        let data = extent[0..6].try_into().unwrap();
+        if let Some(diff) = self.meshes.diff_points.get_mut(&SdfPath(path)) {
+            diff.extent = Some(data);
+        }
+    }
+
+    fn diff_mesh_points_normals(&mut self, path: String, data: &[f32]) {
+        if let Some(diff) = self.meshes.diff_points.get_mut(&SdfPath(path)) {
+            diff.normals = Some(data.to_vec());
+        }
+    }
+
+    fn diff_mesh_points_normals_indices(&mut self, path: String, data: &[u32]) {
+        if let Some(diff) = self.meshes.diff_points.get_mut(&SdfPath(path)) {
+            diff.normals_indices = Some(data.to_vec());
+        }
+    }
+
+    fn diff_mesh_points_normals_interpolation(
+        &mut self,
+        path: String,
+        interpolation: Interpolation,
+    ) {
+        if let Some(diff) = self.meshes.diff_points.get_mut(&SdfPath(path)) {
+            diff.normals_interpolation = Some(interpolation);
+        }
+    }
+
     // === Sphere Light ===
 
     fn add_or_update_sphere_light(&mut self, path: String) {
@@ -709,4 +1426,179 @@ impl UsdDataDiff {
     fn destroy_material(&mut self, path: String) {
         self.materials.destroy.push(SdfPath(path));
     }
+
+    // === Skeleton ===
+
+    fn add_or_update_skeleton(
+        &mut self,
+        path: String,
+        joints: &[String],
+        parents: &[i32],
+        bind_transforms: &[f32],
+        rest_transforms: &[f32],
+    ) {
+        self.skeletons.update.insert(
+            SdfPath(path),
+            SkeletonData {
+                joints: joints.to_vec(),
+                parents: parents.to_vec(),
+                bind_transforms: bind_transforms.to_vec(),
+                rest_transforms: rest_transforms.to_vec(),
+            },
+        );
+    }
+
+    fn destroy_skeleton(&mut self, path: String) {
+        self.skeletons.destroy.push(SdfPath(path));
+    }
+
+    fn update_skeleton_pose(
+        &mut self,
+        path: String,
+        transform_matrix: &[f32],
+        skinning_transforms: &[f32],
+    ) {
+        self.skeletons.poses.insert(
+            SdfPath(path),
+            SkeletonPoseData {
+                transform_matrix: transform_matrix[0..16].try_into().unwrap(),
+                skinning_transforms: skinning_transforms.to_vec(),
+            },
+        );
+    }
+
+    fn add_or_update_mesh_skin_binding(
+        &mut self,
+        path: String,
+        skeleton_path: String,
+        interpolation: Interpolation,
+        joint_indices: &[u32],
+        joint_weights: &[f32],
+        geom_bind_transform: &[f32],
+    ) {
+        self.skeletons.skin_bindings.insert(
+            SdfPath(path),
+            SkinBindingData {
+                skeleton_path,
+                interpolation,
+                joint_indices: joint_indices.to_vec(),
+                joint_weights: joint_weights.to_vec(),
+                geom_bind_transform: geom_bind_transform[0..16].try_into().unwrap(),
+            },
+        );
+    }
+
+    fn destroy_mesh_skin_binding(&mut self, path: String) {
+        self.skeletons.destroy_skin_bindings.push(SdfPath(path));
+    }
+
+    fn add_mesh_blend_shape(
+        &mut self,
+        path: String,
+        name: String,
+        offsets: &[f32],
+        normal_offsets: &[f32],
+        point_indices: &[u32],
+    ) {
+        self.skeletons
+            .blend_shapes
+            .entry(SdfPath(path))
+            .or_default()
+            .push(BlendShapeData {
+                name,
+                offsets: offsets.to_vec(),
+                normal_offsets: (!normal_offsets.is_empty()).then(|| normal_offsets.to_vec()),
+                point_indices: (!point_indices.is_empty()).then(|| point_indices.to_vec()),
+                inbetweens: Vec::new(),
+            });
+    }
+
+    fn add_mesh_blend_shape_inbetween(
+        &mut self,
+        path: String,
+        weight: f32,
+        offsets: &[f32],
+        normal_offsets: &[f32],
+    ) {
+        if let Some(blend_shape) = self
+            .skeletons
+            .blend_shapes
+            .get_mut(&SdfPath(path))
+            .and_then(|blend_shapes| blend_shapes.last_mut())
+        {
+            blend_shape.inbetweens.push(BlendShapeInbetweenData {
+                weight,
+                offsets: offsets.to_vec(),
+                normal_offsets: (!normal_offsets.is_empty()).then(|| normal_offsets.to_vec()),
+            });
+        }
+    }
+
+    fn destroy_mesh_blend_shapes(&mut self, path: String) {
+        self.skeletons.destroy_blend_shapes.push(SdfPath(path));
+    }
+
+    fn update_mesh_blend_shape_weights(&mut self, path: String, weights: &[f32]) {
+        self.skeletons
+            .blend_shape_weights
+            .insert(SdfPath(path), weights.to_vec());
+    }
+
+    // === Instancer ===
+
+    fn add_or_update_instancer(&mut self, path: String) {
+        self.instancers
+            .update
+            .insert(SdfPath(path), InstancerDiffItem::default());
+    }
+
+    fn add_or_update_instancer_transform_matrix(&mut self, path: String, matrix: &[f32]) {
+        let data = matrix[0..16].try_into().unwrap();
+        if let Some(update) = self.instancers.update.get_mut(&SdfPath(path)) {
+            update.transform_matrix = Some(data);
+        }
+    }
+
+    fn add_or_update_instancer_instance_transforms(&mut self, path: String, data: &[f32]) {
+        if let Some(update) = self.instancers.update.get_mut(&SdfPath(path)) {
+            update.instance_transforms = data.to_vec();
+        }
+    }
+
+    fn add_or_update_instancer_prototype(
+        &mut self,
+        path: String,
+        prototype_path: String,
+        instance_indices: &[u32],
+    ) {
+        if let Some(update) = self.instancers.update.get_mut(&SdfPath(path)) {
+            update
+                .prototypes
+                .push((prototype_path, instance_indices.to_vec()));
+        }
+    }
+
+    fn add_or_update_instancer_primvar(
+        &mut self,
+        path: String,
+        name: String,
+        element_type: PrimvarType,
+        values: &[f32],
+    ) {
+        if let Some(update) = self.instancers.update.get_mut(&SdfPath(path)) {
+            update.primvars.insert(
+                name,
+                PrimvarData {
+                    element_type,
+                    interpolation: Interpolation::Instance,
+                    values: values.to_vec(),
+                    indices: None,
+                },
+            );
+        }
+    }
+
+    fn destroy_instancer(&mut self, path: String) {
+        self.instancers.destroy.push(SdfPath(path));
+    }
 }
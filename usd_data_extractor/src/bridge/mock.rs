@@ -0,0 +1,1045 @@
+use std::collections::{HashMap, HashSet};
+use std::mem::discriminant;
+
+use super::{Interpolation, PrimvarType, SubdivisionScheme, SubdivisionTags, UsdDataDiff};
+use crate::{AttributeValue, StageMetadata};
+
+/// mock stageのMeshに含まれるGeomSubsetの情報
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MockGeomSubset {
+    pub indices_type: String,
+    pub indices: Vec<u32>,
+    pub material_path: Option<String>,
+}
+
+/// mock stageのMeshに含まれるpoints, normals, st以外のprimvarの情報
+#[derive(Debug, Clone, PartialEq)]
+pub struct MockPrimvar {
+    pub element_type: PrimvarType,
+    pub interpolation: Interpolation,
+    pub values: Vec<f32>,
+    pub indices: Option<Vec<u32>>,
+}
+
+/// mock stageのMeshの情報。
+/// Noneのフィールドはstageにauthorされていない属性として扱う。
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MockMesh {
+    pub transform_matrix: Option<[f32; 16]>,
+    pub left_handed: bool,
+    pub points: Option<Vec<f32>>,
+    /// extentのminとmaxを並べた6つの値
+    pub extent: Option<[f32; 6]>,
+    pub normals: Option<Vec<f32>>,
+    pub normals_indices: Option<Vec<u32>>,
+    pub normals_interpolation: Option<Interpolation>,
+    pub uvs: Option<Vec<f32>>,
+    pub uvs_indices: Option<Vec<u32>>,
+    pub uvs_interpolation: Option<Interpolation>,
+    pub primvars: HashMap<String, MockPrimvar>,
+    pub face_vertex_indices: Option<Vec<u32>>,
+    pub face_vertex_counts: Option<Vec<u32>>,
+    pub hole_indices: Option<Vec<u32>>,
+    pub subdivision_scheme: Option<SubdivisionScheme>,
+    pub subdivision_tags: SubdivisionTags,
+    pub refine_level: Option<u32>,
+    pub geom_subsets: HashMap<String, MockGeomSubset>,
+    pub material_path: Option<String>,
+    pub skin: Option<MockSkinBinding>,
+    pub blend_shapes: Vec<MockBlendShape>,
+    /// blend_shapesの並びのweight。USDではSkelAnimationから取得する
+    pub blend_shape_weights: Vec<f32>,
+}
+
+/// mock stageのMeshのblend shapeの情報。
+/// point_indicesがない場合、offsetsはmeshの全ての点に対応する
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MockBlendShape {
+    pub name: String,
+    pub offsets: Vec<f32>,
+    pub normal_offsets: Option<Vec<f32>>,
+    pub point_indices: Option<Vec<u32>>,
+    pub inbetweens: Vec<MockBlendShapeInbetween>,
+}
+
+/// mock stageのblend shapeのinbetweenの情報
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MockBlendShapeInbetween {
+    pub weight: f32,
+    pub offsets: Vec<f32>,
+    pub normal_offsets: Option<Vec<f32>>,
+}
+
+/// mock stageのMeshのskinningの束縛の情報。
+/// joint_indicesとjoint_weightsは点ごとに4つずつ並べたもの
+#[derive(Debug, Clone, PartialEq)]
+pub struct MockSkinBinding {
+    pub skeleton_path: String,
+    pub interpolation: Interpolation,
+    pub joint_indices: Vec<u32>,
+    pub joint_weights: Vec<f32>,
+    pub geom_bind_transform: [f32; 16],
+}
+
+/// mock stageのSkeletonの情報。
+/// transformはjointごとに16個の値を並べたもので、skinning_transformsはそのsampleでのポーズ
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MockSkeleton {
+    pub transform_matrix: Option<[f32; 16]>,
+    pub joints: Vec<String>,
+    pub parents: Vec<i32>,
+    pub bind_transforms: Vec<f32>,
+    pub rest_transforms: Vec<f32>,
+    pub skinning_transforms: Vec<f32>,
+}
+
+/// mock stageのPointInstancerやinstanceable primの情報。
+/// instance_transformsはinstanceごとに16個の値を並べたもので、
+/// prototypesはprototypeのroot pathとそのprototypeが使うinstanceのindexの組
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MockInstancer {
+    pub transform_matrix: Option<[f32; 16]>,
+    pub instance_transforms: Vec<f32>,
+    pub prototypes: Vec<(String, Vec<u32>)>,
+    pub primvars: HashMap<String, MockPrimvar>,
+}
+
+/// mock stageのSphereLightの情報
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MockSphereLight {
+    pub transform_matrix: Option<[f32; 16]>,
+    pub color: Option<[f32; 3]>,
+    pub intensity: Option<f32>,
+    pub cone_angle: Option<f32>,
+    pub cone_softness: Option<f32>,
+}
+
+/// mock stageのDistantLightの情報
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MockDistantLight {
+    pub transform_matrix: Option<[f32; 16]>,
+    pub color: Option<[f32; 3]>,
+    pub intensity: Option<f32>,
+}
+
+/// mock stageのCameraの情報
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MockCamera {
+    pub transform_matrix: Option<[f32; 16]>,
+    pub focal_length: Option<f32>,
+    pub vertical_aperture: Option<f32>,
+}
+
+/// mock stageのRenderSettingsの情報。
+/// render_productsはRenderProductのpathからカメラのpathへのmap。
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MockRenderSettings {
+    pub render_products: HashMap<String, String>,
+}
+
+/// mock stageのMaterialの情報
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MockMaterial {
+    pub diffuse_color: Option<[f32; 3]>,
+    pub emissive: Option<[f32; 3]>,
+    pub metallic: Option<f32>,
+    pub opacity: Option<f32>,
+    pub roughness: Option<f32>,
+    pub diffuse_color_file: Option<String>,
+    pub emissive_file: Option<String>,
+    pub metallic_file: Option<String>,
+    pub normal_file: Option<String>,
+    pub opacity_file: Option<String>,
+    pub roughness_file: Option<String>,
+}
+
+/// mock stageに置くprimの情報
+// mock stageのprimは数が少ないので、variantごとのサイズの差は気にしない
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum MockPrim {
+    Mesh(MockMesh),
+    SphereLight(MockSphereLight),
+    DistantLight(MockDistantLight),
+    Camera(MockCamera),
+    RenderSettings(MockRenderSettings),
+    Material(MockMaterial),
+    Skeleton(MockSkeleton),
+    Instancer(MockInstancer),
+}
+
+/// OpenUSDを使わずにUsdSceneExtractorを動かすための、メモリ上のstageの記述。
+/// time codeごとにstageに存在するprimの一覧を持つ。
+#[derive(Debug, Clone, Default)]
+pub struct MockStage {
+    start_time_code: f64,
+    end_time_code: f64,
+    metadata: StageMetadata,
+    // time codeの昇順に並んだsampleのリスト
+    samples: Vec<(f64, HashMap<String, MockPrim>)>,
+}
+impl MockStage {
+    pub fn new(start_time_code: f64, end_time_code: f64) -> Self {
+        Self {
+            start_time_code,
+            end_time_code,
+            metadata: StageMetadata::default(),
+            samples: Vec::new(),
+        }
+    }
+
+    /// stageのlayer metadataを設定する。
+    pub fn set_metadata(&mut self, metadata: StageMetadata) {
+        self.metadata = metadata;
+    }
+
+    /// time_codeでstageに存在するprimの一覧を設定する。
+    /// 次のsampleのtime codeまではこのprimの一覧が保持される。
+    pub fn set_prims(
+        &mut self,
+        time_code: f64,
+        prims: impl IntoIterator<Item = (String, MockPrim)>,
+    ) {
+        let prims = prims.into_iter().collect();
+        match self
+            .samples
+            .binary_search_by(|(t, _)| t.total_cmp(&time_code))
+        {
+            Ok(index) => self.samples[index].1 = prims,
+            Err(index) => self.samples.insert(index, (time_code, prims)),
+        }
+    }
+
+    // time_codeで有効なprimの一覧を返す。
+    // 最初のsampleより前のtime codeでは最初のsampleを返す。
+    fn prims(&self, time_code: f64) -> Option<&HashMap<String, MockPrim>> {
+        let index = self
+            .samples
+            .partition_point(|(t, _)| *t <= time_code)
+            .saturating_sub(1);
+        self.samples.get(index).map(|(_, prims)| prims)
+    }
+}
+
+// pathがancestorかその子孫のpathであるかどうか
+fn is_descendant(path: &str, ancestor: &str) -> bool {
+    path == ancestor
+        || path
+            .strip_prefix(ancestor)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+// 編集APIでsession layerに書き込まれる内容に相当する情報
+#[derive(Debug, Default)]
+struct MockSession {
+    transforms: HashMap<String, [f32; 16]>,
+    inactive: HashSet<String>,
+    invisible: HashSet<String>,
+    population_mask: Option<Vec<String>>,
+    // define_primで定義したprim。Noneはextractの対象にならない型のprim
+    defined: HashMap<String, Option<MockPrim>>,
+    // set_attributeで設定した属性
+    attributes: HashMap<String, HashMap<String, AttributeValue>>,
+}
+impl MockSession {
+    // pathかその祖先がpathsに含まれているかどうか
+    fn contains_ancestor(paths: &HashSet<String>, path: &str) -> bool {
+        paths.iter().any(|p| is_descendant(path, p))
+    }
+
+    // population maskが指定されている場合、maskのpathの祖先か子孫であるかどうか
+    fn in_population(&self, path: &str) -> bool {
+        let Some(mask) = &self.population_mask else {
+            return true;
+        };
+        mask.iter()
+            .any(|p| is_descendant(path, p) || is_descendant(p, path))
+    }
+
+    // stageのprimの一覧にsessionの編集を適用する
+    fn apply(&self, mut prims: HashMap<String, MockPrim>) -> HashMap<String, MockPrim> {
+        // 定義したprimは、stageに同じ種類のprimがなければ置き換える
+        for (path, defined) in &self.defined {
+            match (prims.get(path), defined) {
+                (Some(prim), Some(defined)) if discriminant(prim) == discriminant(defined) => {}
+                (_, Some(defined)) => {
+                    prims.insert(path.clone(), defined.clone());
+                }
+                (_, None) => {
+                    prims.remove(path);
+                }
+            }
+        }
+        for (path, attributes) in &self.attributes {
+            if let Some(prim) = prims.get_mut(path) {
+                for (name, value) in attributes {
+                    // 設定するときに確認しているので、ここでは失敗しない
+                    let _ = set_prim_attribute(prim, name, value);
+                }
+            }
+        }
+
+        prims
+            .into_iter()
+            .filter(|(path, _)| self.in_population(path))
+            .filter(|(path, _)| !Self::contains_ancestor(&self.inactive, path))
+            .filter(|(path, prim)| {
+                // 非表示のMeshはシーンから削除されたものとして扱う
+                !matches!(prim, MockPrim::Mesh(_))
+                    || !Self::contains_ancestor(&self.invisible, path)
+            })
+            .map(|(path, mut prim)| {
+                if let Some(matrix) = self.transforms.get(&path) {
+                    match &mut prim {
+                        MockPrim::Mesh(mesh) => mesh.transform_matrix = Some(*matrix),
+                        MockPrim::SphereLight(light) => light.transform_matrix = Some(*matrix),
+                        MockPrim::DistantLight(light) => light.transform_matrix = Some(*matrix),
+                        MockPrim::Camera(camera) => camera.transform_matrix = Some(*matrix),
+                        MockPrim::Skeleton(skeleton) => skeleton.transform_matrix = Some(*matrix),
+                        MockPrim::Instancer(instancer) => {
+                            instancer.transform_matrix = Some(*matrix)
+                        }
+                        _ => {}
+                    }
+                }
+                (path, prim)
+            })
+            .collect()
+    }
+}
+
+// BridgeUsdDataExtractorの代わりにMockStageからUsdDataDiffを生成する。
+// 前回のextractで送ったprimの状態を記録しておき、その差分をdiffとして記録する。
+pub(crate) struct MockUsdDataExtractor {
+    stage: MockStage,
+    session: MockSession,
+    prims: HashMap<String, MockPrim>,
+}
+impl MockUsdDataExtractor {
+    pub(crate) fn new(stage: MockStage) -> Self {
+        Self {
+            stage,
+            session: MockSession::default(),
+            prims: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn set_transform(&mut self, path: &str, matrix: [f32; 16]) {
+        self.session.transforms.insert(path.to_string(), matrix);
+    }
+
+    pub(crate) fn set_active(&mut self, path: &str, active: bool) {
+        if active {
+            self.session.inactive.remove(path);
+        } else {
+            self.session.inactive.insert(path.to_string());
+        }
+    }
+
+    pub(crate) fn set_visibility(&mut self, path: &str, visible: bool) {
+        if visible {
+            self.session.invisible.remove(path);
+        } else {
+            self.session.invisible.insert(path.to_string());
+        }
+    }
+
+    // sessionで定義したものも含め、いずれかのsampleでpathに存在するprim
+    fn find_prim(&self, path: &str) -> Option<Option<&MockPrim>> {
+        if let Some(defined) = self.session.defined.get(path) {
+            return Some(defined.as_ref());
+        }
+        self.stage
+            .samples
+            .iter()
+            .find_map(|(_, prims)| prims.get(path))
+            .map(Some)
+    }
+
+    pub(crate) fn set_attribute(
+        &mut self,
+        path: &str,
+        name: &str,
+        value: &AttributeValue,
+    ) -> Result<(), String> {
+        let Some(prim) = self.find_prim(path) else {
+            return Err("prim not found".to_string());
+        };
+        let Some(prim) = prim else {
+            return Err(format!("attribute {name} is not supported on this prim"));
+        };
+        // 対応している属性かどうかを、primの複製に設定して確認する
+        set_prim_attribute(&mut prim.clone(), name, value)?;
+        self.session
+            .attributes
+            .entry(path.to_string())
+            .or_default()
+            .insert(name.to_string(), value.clone());
+        Ok(())
+    }
+
+    pub(crate) fn define_prim(&mut self, path: &str, type_name: &str) -> Result<(), String> {
+        let prim = define_prim(type_name)?;
+        self.session.defined.insert(path.to_string(), prim);
+        Ok(())
+    }
+
+    pub(crate) fn set_population_mask(&mut self, population_mask: Option<Vec<String>>) {
+        self.session.population_mask = population_mask;
+    }
+
+    pub(crate) fn start_time_code(&self) -> f64 {
+        self.stage.start_time_code
+    }
+
+    pub(crate) fn end_time_code(&self) -> f64 {
+        self.stage.end_time_code
+    }
+
+    pub(crate) fn metadata(&self) -> &StageMetadata {
+        &self.stage.metadata
+    }
+
+    pub(crate) fn extract(&mut self, time_code: f64, diff: &mut UsdDataDiff) {
+        let prims = self.stage.prims(time_code).cloned().unwrap_or_default();
+        let prims = self.session.apply(prims);
+
+        // 削除されたprimと、種類が変わったprimをdestroyする
+        for (path, prev) in &self.prims {
+            match prims.get(path) {
+                Some(prim) if discriminant(prim) == discriminant(prev) => {}
+                _ => destroy_prim(diff, path, prev),
+            }
+        }
+
+        // 追加されたprimをcreateし、既存のprimは変更があればdiffを記録する
+        for (path, prim) in &prims {
+            match self.prims.get(path) {
+                Some(prev) if discriminant(prim) == discriminant(prev) => {
+                    update_prim(diff, path, prev, prim)
+                }
+                _ => create_prim(diff, path, prim),
+            }
+        }
+
+        self.prims = prims;
+    }
+}
+
+// define_primで定義するprim。
+// Hydraと同じく、transformとUSDのfallbackの値を持った状態で作る
+fn define_prim(type_name: &str) -> Result<Option<MockPrim>, String> {
+    let transform_matrix = Some(glam::Mat4::IDENTITY.to_cols_array());
+    let prim = match type_name {
+        "Mesh" => MockPrim::Mesh(MockMesh {
+            transform_matrix,
+            points: Some(Vec::new()),
+            face_vertex_indices: Some(Vec::new()),
+            face_vertex_counts: Some(Vec::new()),
+            ..Default::default()
+        }),
+        "SphereLight" => MockPrim::SphereLight(MockSphereLight {
+            transform_matrix,
+            color: Some([1.0; 3]),
+            intensity: Some(1.0),
+            ..Default::default()
+        }),
+        "DistantLight" => MockPrim::DistantLight(MockDistantLight {
+            transform_matrix,
+            color: Some([1.0; 3]),
+            intensity: Some(50000.0),
+        }),
+        "Camera" => MockPrim::Camera(MockCamera {
+            transform_matrix,
+            focal_length: Some(50.0),
+            vertical_aperture: Some(15.2908),
+        }),
+        "Material" => MockPrim::Material(MockMaterial::default()),
+        // extractの対象にならない型
+        "Xform" | "Scope" | "" => return Ok(None),
+        _ => return Err(format!("prim type {type_name} is not supported")),
+    };
+    Ok(Some(prim))
+}
+
+// set_attributeで設定した属性をprimに反映する。
+// mock stageのprimが持っている属性だけに対応する
+fn set_prim_attribute(
+    prim: &mut MockPrim,
+    name: &str,
+    value: &AttributeValue,
+) -> Result<(), String> {
+    let indices = |values: &[i32]| values.iter().map(|&i| i as u32).collect::<Vec<_>>();
+    match (prim, name, value) {
+        (MockPrim::Mesh(mesh), "points", AttributeValue::Point3fArray(values)) => {
+            mesh.points = Some(values.as_flattened().to_vec());
+        }
+        (MockPrim::Mesh(mesh), "normals", AttributeValue::Normal3fArray(values)) => {
+            mesh.normals = Some(values.as_flattened().to_vec());
+        }
+        (MockPrim::Mesh(mesh), "primvars:st", AttributeValue::TexCoord2fArray(values)) => {
+            mesh.uvs = Some(values.as_flattened().to_vec());
+        }
+        (MockPrim::Mesh(mesh), "extent", AttributeValue::Point3fArray(values))
+            if values.len() == 2 =>
+        {
+            mesh.extent = Some([values[0], values[1]].as_flattened().try_into().unwrap());
+        }
+        (MockPrim::Mesh(mesh), "faceVertexIndices", AttributeValue::IntArray(values)) => {
+            mesh.face_vertex_indices = Some(indices(values));
+        }
+        (MockPrim::Mesh(mesh), "faceVertexCounts", AttributeValue::IntArray(values)) => {
+            mesh.face_vertex_counts = Some(indices(values));
+        }
+        (MockPrim::Mesh(mesh), "holeIndices", AttributeValue::IntArray(values)) => {
+            mesh.hole_indices = Some(indices(values));
+        }
+        (MockPrim::Mesh(mesh), "refineLevel", AttributeValue::Int(value)) => {
+            mesh.refine_level = Some(*value as u32);
+        }
+        (MockPrim::SphereLight(light), "inputs:intensity", AttributeValue::Float(value)) => {
+            light.intensity = Some(*value);
+        }
+        (MockPrim::SphereLight(light), "inputs:color", AttributeValue::Color3f(value)) => {
+            light.color = Some(*value);
+        }
+        (MockPrim::SphereLight(light), "shaping:cone:angle", AttributeValue::Float(value)) => {
+            light.cone_angle = Some(*value);
+        }
+        (MockPrim::SphereLight(light), "shaping:cone:softness", AttributeValue::Float(value)) => {
+            light.cone_softness = Some(*value);
+        }
+        (MockPrim::DistantLight(light), "inputs:intensity", AttributeValue::Float(value)) => {
+            light.intensity = Some(*value);
+        }
+        (MockPrim::DistantLight(light), "inputs:color", AttributeValue::Color3f(value)) => {
+            light.color = Some(*value);
+        }
+        (MockPrim::Camera(camera), "focalLength", AttributeValue::Float(value)) => {
+            camera.focal_length = Some(*value);
+        }
+        (MockPrim::Camera(camera), "verticalAperture", AttributeValue::Float(value)) => {
+            camera.vertical_aperture = Some(*value);
+        }
+        _ => {
+            return Err(format!(
+                "attribute {name} of type {} is not supported on this prim",
+                value.type_name()
+            ))
+        }
+    }
+    Ok(())
+}
+
+fn create_prim(diff: &mut UsdDataDiff, path: &str, prim: &MockPrim) {
+    match prim {
+        MockPrim::Mesh(mesh) => create_mesh(diff, path, mesh),
+        MockPrim::SphereLight(light) => add_or_update_sphere_light(diff, path, light),
+        MockPrim::DistantLight(light) => add_or_update_distant_light(diff, path, light),
+        MockPrim::Camera(camera) => add_or_update_camera(diff, path, camera),
+        MockPrim::RenderSettings(settings) => add_or_update_render_settings(diff, path, settings),
+        MockPrim::Material(material) => add_or_update_material(diff, path, material),
+        MockPrim::Skeleton(skeleton) => {
+            add_or_update_skeleton(diff, path, skeleton);
+            update_skeleton_pose(diff, path, skeleton);
+        }
+        MockPrim::Instancer(instancer) => add_or_update_instancer(diff, path, instancer),
+    }
+}
+
+fn update_prim(diff: &mut UsdDataDiff, path: &str, prev: &MockPrim, prim: &MockPrim) {
+    if prev == prim {
+        return;
+    }
+    match prim {
+        MockPrim::Mesh(mesh) => {
+            let MockPrim::Mesh(prev) = prev else {
+                unreachable!()
+            };
+            if prev.transform_matrix != mesh.transform_matrix {
+                if let Some(matrix) = &mesh.transform_matrix {
+                    diff.diff_mesh_transform_matrix(path.to_string(), matrix);
+                }
+            }
+            // skinningの束縛はmeshのデータとは別に記録する
+            if prev.skin != mesh.skin {
+                match &mesh.skin {
+                    Some(skin) => add_or_update_mesh_skin_binding(diff, path, skin),
+                    None => diff.destroy_mesh_skin_binding(path.to_string()),
+                }
+            }
+            if prev.blend_shapes != mesh.blend_shapes {
+                if mesh.blend_shapes.is_empty() {
+                    diff.destroy_mesh_blend_shapes(path.to_string());
+                } else {
+                    add_mesh_blend_shapes(diff, path, mesh);
+                }
+            } else if prev.blend_shape_weights != mesh.blend_shape_weights {
+                diff.update_mesh_blend_shape_weights(path.to_string(), &mesh.blend_shape_weights);
+            }
+            // pointsとnormals、extent以外が変わっていなければ、pointsだけのdiffを記録する
+            let topology = |mesh: &MockMesh| MockMesh {
+                transform_matrix: None,
+                skin: None,
+                blend_shapes: Vec::new(),
+                blend_shape_weights: Vec::new(),
+                points: None,
+                extent: None,
+                normals: None,
+                normals_indices: None,
+                normals_interpolation: None,
+                ..mesh.clone()
+            };
+            if topology(prev) != topology(mesh) {
+                diff_mesh_data(diff, path, mesh);
+            } else if prev.points != mesh.points
+                || prev.extent != mesh.extent
+                || prev.normals != mesh.normals
+                || prev.normals_indices != mesh.normals_indices
+                || prev.normals_interpolation != mesh.normals_interpolation
+            {
+                diff_mesh_points(diff, path, mesh);
+            }
+        }
+        MockPrim::Skeleton(skeleton) => {
+            let MockPrim::Skeleton(prev) = prev else {
+                unreachable!()
+            };
+            // 構造が変わったSkeletonはポーズも送り直す
+            let structure = |skeleton: &MockSkeleton| MockSkeleton {
+                transform_matrix: None,
+                skinning_transforms: Vec::new(),
+                ..skeleton.clone()
+            };
+            if structure(prev) != structure(skeleton) {
+                add_or_update_skeleton(diff, path, skeleton);
+                update_skeleton_pose(diff, path, skeleton);
+            } else if prev.transform_matrix != skeleton.transform_matrix
+                || prev.skinning_transforms != skeleton.skinning_transforms
+            {
+                update_skeleton_pose(diff, path, skeleton);
+            }
+        }
+        _ => create_prim(diff, path, prim),
+    }
+}
+
+fn destroy_prim(diff: &mut UsdDataDiff, path: &str, prim: &MockPrim) {
+    let path = path.to_string();
+    match prim {
+        MockPrim::Mesh(mesh) => {
+            if mesh.skin.is_some() {
+                diff.destroy_mesh_skin_binding(path.clone());
+            }
+            if !mesh.blend_shapes.is_empty() {
+                diff.destroy_mesh_blend_shapes(path.clone());
+            }
+            diff.destroy_mesh(path)
+        }
+        MockPrim::SphereLight(_) => diff.destroy_sphere_light(path),
+        MockPrim::DistantLight(_) => diff.destroy_distant_light(path),
+        MockPrim::Camera(_) => diff.destroy_camera(path),
+        MockPrim::RenderSettings(_) => diff.destroy_render_settings(path),
+        MockPrim::Material(_) => diff.destroy_material(path),
+        MockPrim::Skeleton(_) => diff.destroy_skeleton(path),
+        MockPrim::Instancer(_) => diff.destroy_instancer(path),
+    }
+}
+
+fn create_mesh(diff: &mut UsdDataDiff, path: &str, mesh: &MockMesh) {
+    let p = || path.to_string();
+    diff.create_mesh(p());
+    if let Some(matrix) = &mesh.transform_matrix {
+        diff.create_mesh_transform_matrix(p(), matrix);
+    }
+    if mesh.left_handed {
+        diff.create_mesh_left_handed(p(), true);
+    }
+    if let Some(points) = &mesh.points {
+        diff.create_mesh_points(p(), points);
+    }
+    if let Some(extent) = &mesh.extent {
+        diff.create_mesh_extent(p(), extent);
+    }
+    if let Some(normals) = &mesh.normals {
+        diff.create_mesh_normals(p(), normals);
+    }
+    if let Some(normals_indices) = &mesh.normals_indices {
+        diff.create_mesh_normals_indices(p(), normals_indices);
+    }
+    if let Some(interpolation) = mesh.normals_interpolation {
+        diff.create_mesh_normals_interpolation(p(), interpolation);
+    }
+    if let Some(uvs) = &mesh.uvs {
+        diff.create_mesh_uvs(p(), uvs);
+    }
+    if let Some(uvs_indices) = &mesh.uvs_indices {
+        diff.create_mesh_uvs_indices(p(), uvs_indices);
+    }
+    if let Some(interpolation) = mesh.uvs_interpolation {
+        diff.create_mesh_uvs_interpolation(p(), interpolation);
+    }
+    for (name, primvar) in &mesh.primvars {
+        diff.create_mesh_primvar(
+            p(),
+            name.clone(),
+            primvar.element_type,
+            primvar.interpolation,
+            &primvar.values,
+        );
+        if let Some(indices) = &primvar.indices {
+            diff.create_mesh_primvar_indices(p(), name.clone(), indices);
+        }
+    }
+    if let Some(face_vertex_indices) = &mesh.face_vertex_indices {
+        diff.create_mesh_face_vertex_indices(p(), face_vertex_indices);
+    }
+    if let Some(face_vertex_counts) = &mesh.face_vertex_counts {
+        diff.create_mesh_face_vertex_counts(p(), face_vertex_counts);
+    }
+    if let Some(hole_indices) = &mesh.hole_indices {
+        diff.create_mesh_hole_indices(p(), hole_indices);
+    }
+    if let Some(scheme) = mesh.subdivision_scheme {
+        diff.create_mesh_subdivision_scheme(p(), scheme);
+    }
+    let tags = &mesh.subdivision_tags;
+    if let Some(value) = tags.interpolate_boundary {
+        diff.create_mesh_interpolate_boundary(p(), value);
+    }
+    if let Some(value) = tags.face_varying_linear_interpolation {
+        diff.create_mesh_face_varying_linear_interpolation(p(), value);
+    }
+    if !tags.crease_indices.is_empty() {
+        diff.create_mesh_creases(
+            p(),
+            &tags.crease_indices,
+            &tags.crease_lengths,
+            &tags.crease_sharpnesses,
+        );
+    }
+    if !tags.corner_indices.is_empty() {
+        diff.create_mesh_corners(p(), &tags.corner_indices, &tags.corner_sharpnesses);
+    }
+    if let Some(level) = mesh.refine_level {
+        diff.create_mesh_refine_level(p(), level);
+    }
+    for (name, subset) in &mesh.geom_subsets {
+        diff.create_mesh_geom_subset(
+            p(),
+            name.clone(),
+            subset.indices_type.clone(),
+            &subset.indices,
+        );
+        if let Some(material_path) = &subset.material_path {
+            diff.create_mesh_geom_subset_material_binding(p(), name.clone(), material_path.clone());
+        }
+    }
+    if let Some(material_path) = &mesh.material_path {
+        diff.create_mesh_material_binding(p(), material_path.clone());
+    }
+    if let Some(skin) = &mesh.skin {
+        add_or_update_mesh_skin_binding(diff, path, skin);
+    }
+    if !mesh.blend_shapes.is_empty() {
+        add_mesh_blend_shapes(diff, path, mesh);
+    }
+}
+
+fn diff_mesh_points(diff: &mut UsdDataDiff, path: &str, mesh: &MockMesh) {
+    let p = || path.to_string();
+    // pointsがなくなったmeshは壊れているので、全体のdiffとして送ってエラーにする
+    let Some(points) = &mesh.points else {
+        diff_mesh_data(diff, path, mesh);
+        return;
+    };
+    diff.diff_mesh_points(p(), points);
+    if let Some(extent) = &mesh.extent {
+        diff.diff_mesh_points_extent(p(), extent);
+    }
+    if let Some(normals) = &mesh.normals {
+        diff.diff_mesh_points_normals(p(), normals);
+    }
+    if let Some(normals_indices) = &mesh.normals_indices {
+        diff.diff_mesh_points_normals_indices(p(), normals_indices);
+    }
+    if let Some(interpolation) = mesh.normals_interpolation {
+        diff.diff_mesh_points_normals_interpolation(p(), interpolation);
+    }
+}
+
+fn diff_mesh_data(diff: &mut UsdDataDiff, path: &str, mesh: &MockMesh) {
+    let p = || path.to_string();
+    diff.diff_mesh_data(p());
+    if mesh.left_handed {
+        diff.diff_mesh_data_left_handed(p(), true);
+    }
+    if let Some(points) = &mesh.points {
+        diff.diff_mesh_data_points(p(), points);
+    }
+    if let Some(extent) = &mesh.extent {
+        diff.diff_mesh_data_extent(p(), extent);
+    }
+    if let Some(normals) = &mesh.normals {
+        diff.diff_mesh_data_normals(p(), normals);
+    }
+    if let Some(normals_indices) = &mesh.normals_indices {
+        diff.diff_mesh_data_normals_indices(p(), normals_indices);
+    }
+    if let Some(interpolation) = mesh.normals_interpolation {
+        diff.diff_mesh_data_normals_interpolation(p(), interpolation);
+    }
+    if let Some(uvs) = &mesh.uvs {
+        diff.diff_mesh_data_uvs(p(), uvs);
+    }
+    if let Some(uvs_indices) = &mesh.uvs_indices {
+        diff.diff_mesh_data_uvs_indices(p(), uvs_indices);
+    }
+    if let Some(interpolation) = mesh.uvs_interpolation {
+        diff.diff_mesh_data_uvs_interpolation(p(), interpolation);
+    }
+    for (name, primvar) in &mesh.primvars {
+        diff.diff_mesh_data_primvar(
+            p(),
+            name.clone(),
+            primvar.element_type,
+            primvar.interpolation,
+            &primvar.values,
+        );
+        if let Some(indices) = &primvar.indices {
+            diff.diff_mesh_data_primvar_indices(p(), name.clone(), indices);
+        }
+    }
+    if let Some(face_vertex_indices) = &mesh.face_vertex_indices {
+        diff.diff_mesh_data_face_vertex_indices(p(), face_vertex_indices);
+    }
+    if let Some(face_vertex_counts) = &mesh.face_vertex_counts {
+        diff.diff_mesh_data_face_vertex_counts(p(), face_vertex_counts);
+    }
+    if let Some(hole_indices) = &mesh.hole_indices {
+        diff.diff_mesh_data_hole_indices(p(), hole_indices);
+    }
+    if let Some(scheme) = mesh.subdivision_scheme {
+        diff.diff_mesh_data_subdivision_scheme(p(), scheme);
+    }
+    let tags = &mesh.subdivision_tags;
+    if let Some(value) = tags.interpolate_boundary {
+        diff.diff_mesh_data_interpolate_boundary(p(), value);
+    }
+    if let Some(value) = tags.face_varying_linear_interpolation {
+        diff.diff_mesh_data_face_varying_linear_interpolation(p(), value);
+    }
+    if !tags.crease_indices.is_empty() {
+        diff.diff_mesh_data_creases(
+            p(),
+            &tags.crease_indices,
+            &tags.crease_lengths,
+            &tags.crease_sharpnesses,
+        );
+    }
+    if !tags.corner_indices.is_empty() {
+        diff.diff_mesh_data_corners(p(), &tags.corner_indices, &tags.corner_sharpnesses);
+    }
+    if let Some(level) = mesh.refine_level {
+        diff.diff_mesh_data_refine_level(p(), level);
+    }
+    for (name, subset) in &mesh.geom_subsets {
+        diff.diff_mesh_data_geom_subset(
+            p(),
+            name.clone(),
+            subset.indices_type.clone(),
+            &subset.indices,
+        );
+        if let Some(material_path) = &subset.material_path {
+            diff.diff_mesh_data_geom_subset_material_binding(
+                p(),
+                name.clone(),
+                material_path.clone(),
+            );
+        }
+    }
+    if let Some(material_path) = &mesh.material_path {
+        diff.diff_mesh_material_binding(p(), material_path.clone());
+    }
+}
+
+// blend shapeを送り直し、weightも送り直す
+fn add_mesh_blend_shapes(diff: &mut UsdDataDiff, path: &str, mesh: &MockMesh) {
+    for blend_shape in &mesh.blend_shapes {
+        diff.add_mesh_blend_shape(
+            path.to_string(),
+            blend_shape.name.clone(),
+            &blend_shape.offsets,
+            blend_shape.normal_offsets.as_deref().unwrap_or_default(),
+            blend_shape.point_indices.as_deref().unwrap_or_default(),
+        );
+        for inbetween in &blend_shape.inbetweens {
+            diff.add_mesh_blend_shape_inbetween(
+                path.to_string(),
+                inbetween.weight,
+                &inbetween.offsets,
+                inbetween.normal_offsets.as_deref().unwrap_or_default(),
+            );
+        }
+    }
+    let mut weights = mesh.blend_shape_weights.clone();
+    weights.resize(mesh.blend_shapes.len(), 0.0);
+    diff.update_mesh_blend_shape_weights(path.to_string(), &weights);
+}
+
+fn add_or_update_mesh_skin_binding(diff: &mut UsdDataDiff, path: &str, skin: &MockSkinBinding) {
+    diff.add_or_update_mesh_skin_binding(
+        path.to_string(),
+        skin.skeleton_path.clone(),
+        skin.interpolation,
+        &skin.joint_indices,
+        &skin.joint_weights,
+        &skin.geom_bind_transform,
+    );
+}
+
+fn add_or_update_sphere_light(diff: &mut UsdDataDiff, path: &str, light: &MockSphereLight) {
+    let p = || path.to_string();
+    diff.add_or_update_sphere_light(p());
+    if let Some(matrix) = &light.transform_matrix {
+        diff.add_or_update_sphere_light_transform_matrix(p(), matrix);
+    }
+    if let Some([r, g, b]) = light.color {
+        diff.add_or_update_sphere_light_color(p(), r, g, b);
+    }
+    if let Some(intensity) = light.intensity {
+        diff.add_or_update_sphere_light_intensity(p(), intensity);
+    }
+    if let Some(angle) = light.cone_angle {
+        diff.add_or_update_sphere_light_cone_angle(p(), angle);
+    }
+    if let Some(softness) = light.cone_softness {
+        diff.add_or_update_sphere_light_cone_softness(p(), softness);
+    }
+}
+
+fn add_or_update_distant_light(diff: &mut UsdDataDiff, path: &str, light: &MockDistantLight) {
+    let p = || path.to_string();
+    diff.add_or_update_distant_light(p());
+    if let Some(matrix) = &light.transform_matrix {
+        diff.add_or_update_distant_light_transform_matrix(p(), matrix);
+    }
+    if let Some([r, g, b]) = light.color {
+        diff.add_or_update_distant_light_color(p(), r, g, b);
+    }
+    if let Some(intensity) = light.intensity {
+        diff.add_or_update_distant_light_intensity(p(), intensity);
+    }
+}
+
+fn add_or_update_camera(diff: &mut UsdDataDiff, path: &str, camera: &MockCamera) {
+    let p = || path.to_string();
+    diff.add_or_update_camera(p());
+    if let Some(matrix) = &camera.transform_matrix {
+        diff.add_or_update_camera_transform_matrix(p(), matrix);
+    }
+    if let Some(focal_length) = camera.focal_length {
+        diff.add_or_update_camera_focal_length(p(), focal_length);
+    }
+    if let Some(aperture) = camera.vertical_aperture {
+        diff.add_or_update_camera_vertical_aperture(p(), aperture);
+    }
+}
+
+fn add_or_update_instancer(diff: &mut UsdDataDiff, path: &str, instancer: &MockInstancer) {
+    let p = || path.to_string();
+    diff.add_or_update_instancer(p());
+    if let Some(matrix) = &instancer.transform_matrix {
+        diff.add_or_update_instancer_transform_matrix(p(), matrix);
+    }
+    diff.add_or_update_instancer_instance_transforms(p(), &instancer.instance_transforms);
+    for (prototype_path, instance_indices) in &instancer.prototypes {
+        diff.add_or_update_instancer_prototype(p(), prototype_path.clone(), instance_indices);
+    }
+    // USDと同じく、instanceごとのprimvarだけを送る
+    for (name, primvar) in &instancer.primvars {
+        if primvar.interpolation == Interpolation::Instance {
+            diff.add_or_update_instancer_primvar(
+                p(),
+                name.clone(),
+                primvar.element_type,
+                &primvar.values,
+            );
+        }
+    }
+}
+
+fn add_or_update_render_settings(
+    diff: &mut UsdDataDiff,
+    path: &str,
+    settings: &MockRenderSettings,
+) {
+    let p = || path.to_string();
+    diff.add_or_update_render_settings(p());
+    for (product_path, camera_path) in &settings.render_products {
+        diff.add_or_update_render_settings_render_product(
+            p(),
+            product_path.clone(),
+            camera_path.clone(),
+        );
+    }
+}
+
+fn add_or_update_material(diff: &mut UsdDataDiff, path: &str, material: &MockMaterial) {
+    let p = || path.to_string();
+    diff.add_or_update_material(p());
+    if let Some([r, g, b]) = material.diffuse_color {
+        diff.add_or_update_material_diffuse_color(p(), r, g, b);
+    }
+    if let Some([r, g, b]) = material.emissive {
+        diff.add_or_update_material_emissive(p(), r, g, b);
+    }
+    if let Some(metallic) = material.metallic {
+        diff.add_or_update_material_metallic(p(), metallic);
+    }
+    if let Some(opacity) = material.opacity {
+        diff.add_or_update_material_opacity(p(), opacity);
+    }
+    if let Some(roughness) = material.roughness {
+        diff.add_or_update_material_roughness(p(), roughness);
+    }
+    if let Some(file_path) = &material.diffuse_color_file {
+        diff.add_or_update_material_diffuse_color_file(p(), file_path.clone());
+    }
+    if let Some(file_path) = &material.emissive_file {
+        diff.add_or_update_material_emissive_file(p(), file_path.clone());
+    }
+    if let Some(file_path) = &material.metallic_file {
+        diff.add_or_update_material_metallic_file(p(), file_path.clone());
+    }
+    if let Some(file_path) = &material.normal_file {
+        diff.add_or_update_material_normal_file(p(), file_path.clone());
+    }
+    if let Some(file_path) = &material.opacity_file {
+        diff.add_or_update_material_opacity_file(p(), file_path.clone());
+    }
+    if let Some(file_path) = &material.roughness_file {
+        diff.add_or_update_material_roughness_file(p(), file_path.clone());
+    }
+}
+
+fn add_or_update_skeleton(diff: &mut UsdDataDiff, path: &str, skeleton: &MockSkeleton) {
+    diff.add_or_update_skeleton(
+        path.to_string(),
+        &skeleton.joints,
+        &skeleton.parents,
+        &skeleton.bind_transforms,
+        &skeleton.rest_transforms,
+    );
+}
+
+fn update_skeleton_pose(diff: &mut UsdDataDiff, path: &str, skeleton: &MockSkeleton) {
+    let transform_matrix = skeleton
+        .transform_matrix
+        .unwrap_or(glam::Mat4::IDENTITY.to_cols_array());
+    diff.update_skeleton_pose(
+        path.to_string(),
+        &transform_matrix,
+        &skeleton.skinning_transforms,
+    );
+}
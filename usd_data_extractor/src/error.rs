@@ -0,0 +1,47 @@
+use std::fmt;
+use std::path::PathBuf;
+
+/// usd_data_extractorで発生するエラー
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// stageを開けなかった
+    OpenStage { path: String, message: String },
+    /// UTF-8として扱えないファイルパスが渡された
+    InvalidPath(PathBuf),
+    /// primに必須の属性がauthorされていなかった
+    MissingAttribute {
+        path: String,
+        attribute: &'static str,
+    },
+    /// primのデータが壊れているなど、扱えないデータだった
+    UnsupportedData { path: String, reason: String },
+    /// stageの編集に失敗した
+    Edit { path: String, message: String },
+    /// stageからの情報の取得に失敗した
+    Query { path: String, message: String },
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::OpenStage { path, message } => {
+                write!(f, "failed to open stage {path}: {message}")
+            }
+            Error::InvalidPath(path) => {
+                write!(f, "path is not valid UTF-8: {}", path.display())
+            }
+            Error::MissingAttribute { path, attribute } => {
+                write!(f, "{path}: missing required attribute `{attribute}`")
+            }
+            Error::UnsupportedData { path, reason } => {
+                write!(f, "{path}: unsupported data: {reason}")
+            }
+            Error::Edit { path, message } => {
+                write!(f, "failed to edit {path}: {message}")
+            }
+            Error::Query { path, message } => {
+                write!(f, "failed to query {path}: {message}")
+            }
+        }
+    }
+}
+impl std::error::Error for Error {}
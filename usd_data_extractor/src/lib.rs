@@ -1,18 +1,98 @@
 use bridge::SubMeshData;
-use glam::{Mat4, Vec2, Vec3};
-use std::collections::HashMap;
+use glam::{Mat4, Vec2, Vec3, Vec4};
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
+use triangulation::triangulate_face;
 
 mod bridge;
+mod error;
+mod normals;
+mod subdivision;
+mod tangent;
+mod triangulation;
 
-pub use bridge::{Interpolation, SdfPath};
+#[cfg(feature = "mock")]
+pub use bridge::mock;
+pub use bridge::{
+    BoundaryInterpolation, FaceVaryingLinearInterpolation, Interpolation, PrimvarType, SdfPath,
+    SubdivisionScheme, SubdivisionTags,
+};
+pub use error::Error;
 
 /// USDから抽出したシーンのtransform matrixの情報
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct TransformMatrix {
     pub matrix: Mat4,
 }
 
+/// 座標軸に平行なbounding box
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+impl Aabb {
+    /// 全ての点を含む最小のbounding boxを求める。点がない場合は原点の大きさ0のbounding boxになる。
+    pub fn from_points(points: &[Vec3]) -> Self {
+        let Some((&first, rest)) = points.split_first() else {
+            return Self {
+                min: Vec3::ZERO,
+                max: Vec3::ZERO,
+            };
+        };
+        rest.iter().fold(
+            Self {
+                min: first,
+                max: first,
+            },
+            |aabb, &point| Self {
+                min: aabb.min.min(point),
+                max: aabb.max.max(point),
+            },
+        )
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn size(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    /// 2つのbounding boxを両方含む最小のbounding boxを求める。
+    pub fn union(&self, other: &Aabb) -> Self {
+        Self {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    /// transform matrixで変換したbounding boxの8つの角を全て含むbounding boxを求める。
+    /// meshのローカル座標系のextentと現在のtransform matrixから、ワールド座標系のAABBを求めるのに使う。
+    /// instanceを持つmeshの場合は、代わりに`MeshInstances::aabb`を使う。
+    pub fn transform(&self, transform_matrix: &TransformMatrix) -> Self {
+        let matrix = transform_matrix.matrix;
+        let center = matrix.transform_point3(self.center());
+        let half_size = self.size() * 0.5;
+        let half_size = matrix.x_axis.truncate().abs() * half_size.x
+            + matrix.y_axis.truncate().abs() * half_size.y
+            + matrix.z_axis.truncate().abs() * half_size.z;
+        Self {
+            min: center - half_size,
+            max: center + half_size,
+        }
+    }
+
+    // USDのextent属性の、minとmaxを並べた6つの値から作る
+    fn from_extent(extent: &[f32; 6]) -> Self {
+        Self {
+            min: Vec3::from_slice(&extent[0..3]),
+            max: Vec3::from_slice(&extent[3..6]),
+        }
+    }
+}
+
 /// 頂点バッファの一つの頂点情報
 #[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
 #[repr(C)]
@@ -22,38 +102,183 @@ pub struct Vertex {
     pub uv: Vec2,
 }
 
+/// 頂点バッファと同じ並びにduplicatedしたprimvarのデータ。
+/// 値はUSDのままで、UVのようなV方向の反転はしない。
+#[derive(Debug, Clone, PartialEq)]
+pub struct Primvar {
+    /// 1要素の型
+    pub element_type: PrimvarType,
+    /// 頂点ごとの値。1頂点あたりelement_typeのcomponent_count個のfloatが並ぶ
+    pub values: Vec<f32>,
+}
+
+/// sub meshのindexのデータ。
+/// 頂点をweldしたmeshで頂点数がu16に収まる場合はU16になる
+#[derive(Debug, Clone, PartialEq)]
+pub enum SubMeshIndices {
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+}
+impl SubMeshIndices {
+    pub fn len(&self) -> usize {
+        match self {
+            SubMeshIndices::U16(indices) => indices.len(),
+            SubMeshIndices::U32(indices) => indices.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// index bufferにそのまま書き込めるbyte列
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            SubMeshIndices::U16(indices) => bytemuck::cast_slice(indices),
+            SubMeshIndices::U32(indices) => bytemuck::cast_slice(indices),
+        }
+    }
+
+    /// u32に揃えたindex
+    pub fn to_u32(&self) -> Vec<u32> {
+        match self {
+            SubMeshIndices::U16(indices) => indices.iter().map(|&index| index as u32).collect(),
+            SubMeshIndices::U32(indices) => indices.clone(),
+        }
+    }
+}
+
 // sub meshのindex情報
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct SubMesh {
     /// sub meshのindices
-    pub indices: Vec<u32>,
+    pub indices: SubMeshIndices,
     /// materialのパス
     pub material: Option<String>,
 }
 
 /// USDから抽出した頂点属性などをduplicateしtriangulateし、sub meshに分割したデータ。
 /// 頂点バッファのデータと、sub meshのindex情報を持つ。
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct MeshData {
     /// 頂点バッファのデータ
     pub vertices: Vec<Vertex>,
     /// sub meshのindex情報
     pub sub_meshes: Vec<SubMesh>,
+    /// points, normals, st以外のprimvarのデータ。keyはprimvarの名前
+    pub primvars: HashMap<String, Primvar>,
+    /// 頂点バッファと同じ並びのMikkTSpaceのtangent。
+    /// xyzがtangentで、wはbitangentの向きの符号(±1)。bitangentはcross(normal, tangent) * w。
+    /// UVがない場合やtangentの生成を無効にした場合はNone
+    pub tangents: Option<Vec<Vec4>>,
+    /// meshのローカル座標系のbounding box。
+    /// extentがauthorされていればその値で、なければpointsから求める
+    pub extent: Aabb,
+    /// Skeletonに束縛されたmeshの、頂点バッファと同じ並びのskinningの情報。
+    /// CPU skinningを有効にした場合はポーズを適用済みなのでNoneになる
+    pub skin: Option<MeshSkin>,
+    /// 頂点バッファと同じ並びに展開したblend shape。weightはこの並びで指定する。
+    /// CPU skinningを有効にした場合は適用済みなので空になる
+    pub blend_shapes: Vec<BlendShape>,
 }
+
+// weldでどのsub meshからも使われずに捨てられた頂点
+const UNWELDED_VERTEX: u32 = u32::MAX;
+
 impl MeshData {
     fn new(
         left_handed: bool,
-        points: Vec<f32>,
+        points: &[f32],
         normals: Option<Vec<f32>>,
+        normals_indices: Option<Vec<u32>>,
         normals_interpolation: Option<Interpolation>,
         uvs: Option<Vec<f32>>,
         uvs_indices: Option<Vec<u32>>,
         uvs_interpolation: Option<Interpolation>,
-        face_vertex_indices: Vec<u32>,
-        face_vertex_counts: Vec<u32>,
+        primvars: HashMap<String, bridge::PrimvarData>,
+        face_vertex_indices: &[u32],
+        face_vertex_counts: &[u32],
+        hole_indices: Option<Vec<u32>>,
         geom_subsets: HashMap<String, SubMeshData>,
         material: Option<String>,
-    ) -> Self {
+        options: MeshOptions,
+    ) -> Result<Self, String> {
+        // 壊れたデータでpanicしないように、indexの範囲などを先に検証する
+        if !points.len().is_multiple_of(3) {
+            return Err(format!(
+                "points length {} is not a multiple of 3",
+                points.len()
+            ));
+        }
+        let point_count = points.len() / 3;
+        let face_vertex_count_sum = face_vertex_counts
+            .iter()
+            .map(|&count| count as usize)
+            .sum::<usize>();
+        if face_vertex_count_sum != face_vertex_indices.len() {
+            return Err(format!(
+                "sum of faceVertexCounts {} does not match faceVertexIndices length {}",
+                face_vertex_count_sum,
+                face_vertex_indices.len()
+            ));
+        }
+        if let Some(&index) = face_vertex_indices
+            .iter()
+            .find(|&&index| index as usize >= point_count)
+        {
+            return Err(format!(
+                "faceVertexIndices contains {index} but there are only {point_count} points"
+            ));
+        }
+        let face_count = face_vertex_counts.len();
+        // holeIndicesで穴として指定されたfaceは描画しない
+        let mut hole_faces = vec![false; face_count];
+        for &index in hole_indices.iter().flatten() {
+            let Some(hole_face) = hole_faces.get_mut(index as usize) else {
+                return Err(format!(
+                    "holeIndices contains {index} but there are only {face_count} faces"
+                ));
+            };
+            *hole_face = true;
+        }
+        let required_count = |interpolation| {
+            interpolation_element_count(
+                interpolation,
+                point_count,
+                face_count,
+                face_vertex_indices.len(),
+            )
+        };
+        // indexed primvarはindicesで展開してから、Interpolationに合わせてduplicatedする
+        let normals = match (normals, normals_interpolation) {
+            (Some(normals), Some(normals_interpolation)) => {
+                let normals = resolve_normals(
+                    &normals,
+                    normals_indices.as_deref(),
+                    normals_interpolation,
+                    required_count(normals_interpolation),
+                )?;
+                Some((normals, normals_interpolation))
+            }
+            _ => None,
+        };
+        let uvs = match (uvs, uvs_interpolation) {
+            (Some(uvs), Some(uvs_interpolation)) => {
+                if !uvs.len().is_multiple_of(2) {
+                    return Err(format!("uvs length {} is not a multiple of 2", uvs.len()));
+                }
+                let uvs = resolve_indexed_primvar(
+                    "uvs",
+                    bytemuck::cast_slice::<f32, Vec2>(&uvs),
+                    uvs_indices.as_deref(),
+                    uvs_interpolation,
+                    required_count(uvs_interpolation),
+                )?;
+                Some((uvs, uvs_interpolation))
+            }
+            _ => None,
+        };
+
         // InterpolationがVertexの頂点データをduplicatedするindexを計算する
         let duplicate_vertex_indices = {
             let mut vertex_indices = Vec::new();
@@ -66,7 +291,7 @@ impl MeshData {
         // duplicatedした頂点座標のデータを作成する
         // Pointsは常にInterpolationはVertex
         let vertex_points = {
-            let points = bytemuck::cast_slice::<f32, Vec3>(&points);
+            let points = bytemuck::cast_slice::<f32, Vec3>(points);
             let mut data = Vec::with_capacity(duplicate_vertex_indices.len());
             for &index in &duplicate_vertex_indices {
                 data.push(points[index]);
@@ -74,101 +299,90 @@ impl MeshData {
             data
         };
 
+        let face_triangles = triangulate_faces(
+            bytemuck::cast_slice::<f32, Vec3>(points),
+            face_vertex_indices,
+            face_vertex_counts,
+            left_handed,
+        );
+
         // duplicatedした法線ベクトルのデータを作成する
         let vertex_normals = {
-            let vertex_normals = if let (Some(normals), Some(normals_interpolation)) =
-                (normals, normals_interpolation)
-            {
+            let vertex_normals = normals.map(|(normals, normals_interpolation)| {
                 // データが渡された場合、そのデータのInterpolationに合わせてduplicatedする
-                // 現状はFaceVaryingとVertexのInterpolationのみ対応
-                match normals_interpolation {
-                    Interpolation::FaceVarying => {
-                        let normals = bytemuck::cast_slice::<f32, Vec3>(&normals);
-                        Some(normals.to_vec())
-                    }
-                    Interpolation::Vertex => {
-                        let normals = bytemuck::cast_slice::<f32, Vec3>(&normals);
-                        let mut data = Vec::with_capacity(duplicate_vertex_indices.len());
-                        for &index in &duplicate_vertex_indices {
-                            data.push(normals[index]);
-                        }
-                        Some(data)
-                    }
-                    _ => None,
-                }
-            } else {
-                None
-            };
+                expand_primvar(
+                    &normals,
+                    normals_interpolation,
+                    face_vertex_indices,
+                    face_vertex_counts,
+                )
+            });
             if let Some(vertex_normals) = vertex_normals {
                 vertex_normals
             } else {
-                // Interpolationが対応していない場合や、頂点法線データが渡されていない場合、頂点データから計算する
-                let points = bytemuck::cast_slice::<f32, Vec3>(&points);
-                let mut normals_point = vec![Vec3::ZERO; points.len()];
-                let indices = &duplicate_vertex_indices;
-                let mut index_offset = 0;
-                for face_vertex_count in &face_vertex_counts {
-                    let face_vertex_count = *face_vertex_count as usize;
-                    for i in 2..face_vertex_count {
-                        let p0 = points[indices[index_offset]];
-                        let p1 = points[indices[index_offset + i - 1]];
-                        let p2 = points[indices[index_offset + i]];
-                        let mut normal = (p1 - p0).cross(p2 - p0).normalize();
-                        if left_handed {
-                            normal = -normal;
-                        }
-                        for &index in &indices[index_offset..index_offset + face_vertex_count] {
-                            normals_point[index] += normal;
-                        }
-                    }
-                    index_offset += face_vertex_count;
-                }
-                let mut mean_normals = Vec::with_capacity(points.len());
-                for normals in normals_point {
-                    mean_normals.push(normals.normalize());
-                }
-                let mut vertex_normals = Vec::with_capacity(indices.len());
-                for &index in indices {
-                    vertex_normals.push(mean_normals[index]);
-                }
-                vertex_normals
+                // 頂点法線データが渡されていない場合、頂点データから計算する
+                normals::generate_normals(
+                    bytemuck::cast_slice::<f32, Vec3>(points),
+                    face_vertex_indices,
+                    face_vertex_counts,
+                    &face_triangles,
+                    options.crease_angle,
+                )
             }
         };
 
         // duplicatedしたUV座標のデータを作成する
-        let vertex_uvs = {
-            if let (Some(uvs), Some(uvs_indices), Some(uvs_interpolation)) =
-                (uvs, uvs_indices, uvs_interpolation)
-            {
-                match uvs_interpolation {
-                    Interpolation::FaceVarying => {
-                        let uvs = bytemuck::cast_slice::<f32, Vec2>(&uvs);
-                        let uvs = uvs_indices
-                            .iter()
-                            .map(|&index| uvs[index as usize])
-                            .map(|uv| Vec2::new(uv.x, 1.0 - uv.y))
-                            .collect();
-                        Some(uvs)
-                    }
-                    Interpolation::Vertex => {
-                        let uvs = bytemuck::cast_slice::<f32, Vec2>(&uvs);
-                        let uvs = uvs_indices
-                            .iter()
-                            .map(|&index| uvs[index as usize])
-                            .map(|uv| Vec2::new(uv.x, 1.0 - uv.y))
-                            .collect::<Vec<_>>();
-                        let mut data = Vec::with_capacity(duplicate_vertex_indices.len());
-                        for &index in &duplicate_vertex_indices {
-                            data.push(uvs[index]);
-                        }
-                        Some(data)
-                    }
-                    _ => None,
-                }
-            } else {
-                None
+        let vertex_uvs = uvs.map(|(uvs, uvs_interpolation)| {
+            let uvs = uvs
+                .iter()
+                .map(|uv| Vec2::new(uv.x, 1.0 - uv.y))
+                .collect::<Vec<_>>();
+            expand_primvar(
+                &uvs,
+                uvs_interpolation,
+                face_vertex_indices,
+                face_vertex_counts,
+            )
+        });
+
+        // points, normals, st以外のprimvarも頂点バッファと同じ並びにduplicatedする。
+        // 要素の型が実行時に決まるので、要素のindexを展開してから値をコピーする
+        let mut vertex_primvars = HashMap::with_capacity(primvars.len());
+        for (name, primvar) in primvars {
+            let component_count = primvar.element_type.component_count();
+            if !primvar.values.len().is_multiple_of(component_count) {
+                return Err(format!(
+                    "{name} length {} is not a multiple of {component_count}",
+                    primvar.values.len()
+                ));
             }
-        };
+            let element_count = (primvar.values.len() / component_count) as u32;
+            let element_indices = resolve_indexed_primvar(
+                &name,
+                &(0..element_count).collect::<Vec<_>>(),
+                primvar.indices.as_deref(),
+                primvar.interpolation,
+                required_count(primvar.interpolation),
+            )?;
+            let element_indices = expand_primvar(
+                &element_indices,
+                primvar.interpolation,
+                face_vertex_indices,
+                face_vertex_counts,
+            );
+            let mut values = Vec::with_capacity(element_indices.len() * component_count);
+            for index in element_indices {
+                let start = index as usize * component_count;
+                values.extend_from_slice(&primvar.values[start..start + component_count]);
+            }
+            vertex_primvars.insert(
+                name,
+                Primvar {
+                    element_type: primvar.element_type,
+                    values,
+                },
+            );
+        }
 
         // vertex bufferの情報を作る
         let mut vertices = Vec::with_capacity(vertex_points.len());
@@ -180,82 +394,573 @@ impl MeshData {
             });
         }
 
+        // UVがある場合はnormal mapping用のtangentを作る
+        let tangents = if options.tangents && vertex_uvs.is_some() {
+            tangent::generate_tangents(&vertices, &face_triangles.concat())
+        } else {
+            None
+        };
+
         // sub meshのindex情報を作る
-        let mut used_face_indices = Vec::new();
+        // faceごとにいずれかのgeomSubsetに含まれたかを記録し、残りのfaceでsub meshを作る
+        let mut used_faces = vec![false; face_count];
+        // faceを最後に追加したgeomSubsetの番号。geomSubset内の重複を除くのに使う
+        let mut face_subsets = vec![u32::MAX; face_count];
         let mut sub_meshes = Vec::new();
-        for (_sub_mesh_name, data) in geom_subsets {
+        for (subset_index, (_sub_mesh_name, data)) in geom_subsets.into_iter().enumerate() {
             // typeFaceSet以外のいgeomSubsetsには未対応
             if data.indices_type != "typeFaceSet" {
                 continue;
             }
 
-            // geomSubsetに含まれるfaceをtriangulateしたindexを作る
+            // geomSubsetに含まれるfaceをtriangulateしたindexを作る。
+            // 範囲外のfaceと穴のfaceは無視し、重複を除いてgeomSubsetのindexの順に並べる
+            let subset_index = subset_index as u32;
             let mut sub_mesh_indices = Vec::new();
-            let mut index_offset = 0;
-            for (i, face_vertex_count) in face_vertex_counts.iter().enumerate() {
-                let face_vertex_count = *face_vertex_count as usize;
-                if data.indices.contains(&(i as u32)) {
-                    for i in 2..face_vertex_count {
-                        if left_handed {
-                            sub_mesh_indices.push((index_offset + i) as u32);
-                            sub_mesh_indices.push((index_offset + i - 1) as u32);
-                            sub_mesh_indices.push(index_offset as u32);
-                        } else {
-                            sub_mesh_indices.push(index_offset as u32);
-                            sub_mesh_indices.push((index_offset + i - 1) as u32);
-                            sub_mesh_indices.push((index_offset + i) as u32);
-                        }
-                    }
-                    used_face_indices.push(i as u64);
+            for i in data.indices {
+                let i = i as usize;
+                if i >= face_count || hole_faces[i] || face_subsets[i] == subset_index {
+                    continue;
                 }
-                index_offset += face_vertex_count;
+                face_subsets[i] = subset_index;
+                sub_mesh_indices.extend_from_slice(&face_triangles[i]);
+                used_faces[i] = true;
             }
 
             sub_meshes.push(SubMesh {
-                indices: sub_mesh_indices,
+                indices: SubMeshIndices::U32(sub_mesh_indices),
                 material: data.material_path,
             });
         }
 
         // geomSubsetに含まれなかったfaceをtriangulateしたindexによるsub meshを作る
-        let mut base_indices = Vec::new();
-        for i in 0..face_vertex_counts.len() {
-            if !used_face_indices.contains(&(i as u64)) {
-                base_indices.push(i as u64);
+        let mut has_base_faces = false;
+        let mut sub_mesh_indices = Vec::new();
+        for (i, triangles) in face_triangles.iter().enumerate() {
+            if !used_faces[i] && !hole_faces[i] {
+                sub_mesh_indices.extend_from_slice(triangles);
+                has_base_faces = true;
             }
         }
-        if !base_indices.is_empty() {
-            let mut sub_mesh_indices = Vec::new();
-            let mut index_offset = 0;
-            for (i, face_vertex_count) in face_vertex_counts.iter().enumerate() {
-                let face_vertex_count = *face_vertex_count as usize;
-                if base_indices.contains(&(i as u64)) {
-                    for i in 2..face_vertex_count {
-                        if left_handed {
-                            sub_mesh_indices.push((index_offset + i) as u32);
-                            sub_mesh_indices.push((index_offset + i - 1) as u32);
-                            sub_mesh_indices.push(index_offset as u32);
-                        } else {
-                            sub_mesh_indices.push(index_offset as u32);
-                            sub_mesh_indices.push((index_offset + i - 1) as u32);
-                            sub_mesh_indices.push((index_offset + i) as u32);
-                        }
-                    }
-                    used_face_indices.push(i as u64);
-                }
-                index_offset += face_vertex_count;
-            }
+        if has_base_faces {
             sub_meshes.push(SubMesh {
-                indices: sub_mesh_indices,
+                indices: SubMeshIndices::U32(sub_mesh_indices),
                 material,
             });
         }
 
-        Self {
+        Ok(Self {
             vertices,
             sub_meshes,
+            primvars: vertex_primvars,
+            tangents,
+            extent: Aabb::from_points(bytemuck::cast_slice(points)),
+            skin: None,
+            blend_shapes: Vec::new(),
+        })
+    }
+
+    /// blend shapeをweightsで適用した頂点位置と法線を求める。
+    /// weightsはblend_shapesの並びで、足りない分のweightは0として扱う。
+    /// normal offsetsがないblend shapeでは法線は変わらない
+    pub fn blend_shape_points(&self, weights: &[f32]) -> MeshPoints {
+        let mut positions = self
+            .vertices
+            .iter()
+            .map(|vertex| vertex.position)
+            .collect::<Vec<_>>();
+        let mut normals = self
+            .vertices
+            .iter()
+            .map(|vertex| vertex.normal)
+            .collect::<Vec<_>>();
+        for (blend_shape, &weight) in self.blend_shapes.iter().zip(weights) {
+            if weight == 0.0 {
+                continue;
+            }
+            blend_shape.apply(weight, &mut positions, &mut normals);
+        }
+        for normal in &mut normals {
+            *normal = normal.normalize_or_zero();
+        }
+        let extent = Aabb::from_points(&positions);
+        MeshPoints {
+            positions,
+            normals,
+            tangents: None,
+            extent,
+        }
+    }
+
+    /// Skeletonのポーズとmeshのtransform matrixから、skinningした頂点位置と法線を求める。
+    /// 頂点位置と法線はmeshのローカル座標系で、meshのtransform matrixで描画すればポーズの位置になる。
+    /// skinの情報がない場合や、jointのindexがポーズのjointの数を超える場合はNoneを返す
+    pub fn skinned_points(
+        &self,
+        pose: &SkeletonPose,
+        transform_matrix: &TransformMatrix,
+    ) -> Option<MeshPoints> {
+        let skin = self.skin.as_ref()?;
+        let joint_count = pose.skinning_transforms.len();
+        if skin
+            .joint_indices
+            .iter()
+            .flatten()
+            .any(|&index| index as usize >= joint_count)
+        {
+            return None;
+        }
+        // Skeletonの座標系でskinningした頂点を、meshのローカル座標系に戻す
+        let to_local = transform_matrix.matrix.inverse() * pose.transform_matrix.matrix;
+        let mut positions = Vec::with_capacity(self.vertices.len());
+        let mut normals = Vec::with_capacity(self.vertices.len());
+        let mut tangents = self
+            .tangents
+            .as_ref()
+            .map(|tangents| Vec::with_capacity(tangents.len()));
+        for (i, vertex) in self.vertices.iter().enumerate() {
+            let indices = skin.joint_indices[i];
+            let weights = skin.joint_weights[i].to_array();
+            let mut skinning = Mat4::ZERO;
+            for (&index, &weight) in indices.iter().zip(&weights) {
+                skinning += pose.skinning_transforms[index as usize] * weight;
+            }
+            let matrix = to_local * skinning * skin.geom_bind_transform;
+            positions.push(matrix.transform_point3(vertex.position));
+            let normal_matrix = glam::Mat3::from_mat4(matrix).inverse().transpose();
+            normals.push((normal_matrix * vertex.normal).normalize_or_zero());
+            if let (Some(tangents), Some(rest)) = (&mut tangents, &self.tangents) {
+                let tangent = matrix.transform_vector3(rest[i].truncate());
+                tangents.push(tangent.normalize_or_zero().extend(rest[i].w));
+            }
+        }
+        let extent = Aabb::from_points(&positions);
+        Some(MeshPoints {
+            positions,
+            normals,
+            tangents,
+            extent,
+        })
+    }
+
+    /// skinned_pointsでポーズを適用したMeshDataを作る。
+    /// 返すMeshDataはskinの情報を持たないので、そのまま描画できる
+    pub fn skinned(
+        &self,
+        pose: &SkeletonPose,
+        transform_matrix: &TransformMatrix,
+    ) -> Option<MeshData> {
+        let points = self.skinned_points(pose, transform_matrix)?;
+        let mut mesh_data = self.clone();
+        mesh_data.apply_points(&points);
+        mesh_data.skin = None;
+        Some(mesh_data)
+    }
+
+    // pointsだけの差分を頂点バッファとtangent、extentに反映する
+    fn apply_points(&mut self, points: &MeshPoints) {
+        points.write_vertices(&mut self.vertices);
+        if points.tangents.is_some() {
+            self.tangents.clone_from(&points.tangents);
+        }
+        self.extent = points.extent;
+    }
+
+    // position, normal, uv, tangentとprimvarが全て同じ頂点を1つにまとめ、
+    // sub meshのindexを振り直す。どのsub meshからも使われない頂点は捨てる。
+    // 頂点はindexで最初に使われる順に並べ直し、頂点数がu16に収まる場合はindexをu16にする。
+    // weld前の頂点ごとに、weldした頂点のindexを返す。使われない頂点はUNWELDED_VERTEXになる。
+    // blend shapeは点ごとに動くので、point_indicesが渡された場合は違う点の頂点をまとめない
+    fn weld(&mut self, point_indices: Option<&[u32]>) -> Vec<u32> {
+        let mut remap = vec![UNWELDED_VERTEX; self.vertices.len()];
+        let mut welded_indices = HashMap::new();
+        let mut kept_vertices = Vec::new();
+        // 頂点データのbit列をkeyにする。keyのVecはmapに新しく入れるときだけ確保する
+        let mut key = Vec::new();
+        let mut sub_mesh_indices = Vec::with_capacity(self.sub_meshes.len());
+        for sub_mesh in &self.sub_meshes {
+            let mut indices = sub_mesh.indices.to_u32();
+            for index in &mut indices {
+                let vertex = *index as usize;
+                if remap[vertex] == UNWELDED_VERTEX {
+                    key.clear();
+                    key.extend_from_slice(bytemuck::cast_slice::<Vertex, u32>(
+                        &self.vertices[vertex..vertex + 1],
+                    ));
+                    if let Some(tangents) = &self.tangents {
+                        key.extend(tangents[vertex].to_array().map(f32::to_bits));
+                    }
+                    if let Some(point_indices) = point_indices {
+                        key.push(point_indices[vertex]);
+                    }
+                    if let Some(skin) = &self.skin {
+                        key.extend(skin.joint_indices[vertex]);
+                        key.extend(skin.joint_weights[vertex].to_array().map(f32::to_bits));
+                    }
+                    for primvar in self.primvars.values() {
+                        let component_count = primvar.element_type.component_count();
+                        let start = vertex * component_count;
+                        key.extend(
+                            primvar.values[start..start + component_count]
+                                .iter()
+                                .map(|value| value.to_bits()),
+                        );
+                    }
+                    remap[vertex] = match welded_indices.get(key.as_slice()) {
+                        Some(&welded) => welded,
+                        None => {
+                            let welded = kept_vertices.len() as u32;
+                            welded_indices.insert(key.clone(), welded);
+                            kept_vertices.push(vertex);
+                            welded
+                        }
+                    };
+                }
+                *index = remap[vertex];
+            }
+            sub_mesh_indices.push(indices);
+        }
+
+        self.vertices = kept_vertices.iter().map(|&i| self.vertices[i]).collect();
+        if let Some(tangents) = &mut self.tangents {
+            *tangents = kept_vertices.iter().map(|&i| tangents[i]).collect();
+        }
+        if let Some(skin) = &mut self.skin {
+            skin.joint_indices = kept_vertices
+                .iter()
+                .map(|&i| skin.joint_indices[i])
+                .collect();
+            skin.joint_weights = kept_vertices
+                .iter()
+                .map(|&i| skin.joint_weights[i])
+                .collect();
+        }
+        for primvar in self.primvars.values_mut() {
+            let component_count = primvar.element_type.component_count();
+            primvar.values = kept_vertices
+                .iter()
+                .flat_map(|&i| &primvar.values[i * component_count..(i + 1) * component_count])
+                .copied()
+                .collect();
+        }
+        for blend_shape in &mut self.blend_shapes {
+            blend_shape.weld(&remap);
+        }
+        let fits_u16 = kept_vertices.len() <= u16::MAX as usize + 1;
+        for (sub_mesh, indices) in self.sub_meshes.iter_mut().zip(sub_mesh_indices) {
+            sub_mesh.indices = if fits_u16 {
+                SubMeshIndices::U16(indices.into_iter().map(|index| index as u16).collect())
+            } else {
+                SubMeshIndices::U32(indices)
+            };
+        }
+        remap
+    }
+}
+
+/// topologyが変わらずにpointsだけが変わったmeshの、頂点バッファと同じ並びの頂点位置と法線。
+/// MeshDataのsub meshのindexやUV、primvarは変わらない。
+#[derive(Debug)]
+pub struct MeshPoints {
+    /// 頂点バッファと同じ並びの頂点位置
+    pub positions: Vec<Vec3>,
+    /// 頂点バッファと同じ並びの法線
+    pub normals: Vec<Vec3>,
+    /// tangentを生成しているmeshの場合は、作り直した頂点バッファと同じ並びのtangent
+    pub tangents: Option<Vec<Vec4>>,
+    /// meshのローカル座標系のbounding box
+    pub extent: Aabb,
+}
+impl MeshPoints {
+    /// MeshDataの頂点バッファの頂点位置と法線を書き換える。
+    pub fn write_vertices(&self, vertices: &mut [Vertex]) {
+        for ((vertex, &position), &normal) in
+            vertices.iter_mut().zip(&self.positions).zip(&self.normals)
+        {
+            vertex.position = position;
+            vertex.normal = normal;
+        }
+    }
+}
+
+/// 頂点バッファと同じ並びに展開したmeshのblend shape
+#[derive(Debug, Clone)]
+pub struct BlendShape {
+    /// blend shapeの名前
+    pub name: String,
+    /// blend shapeで動く頂点の、頂点バッファのindex
+    pub vertex_indices: Vec<u32>,
+    /// weightの小さい順に並べたtarget。weightが1のtargetとinbetweenを含む
+    pub targets: Vec<BlendShapeTarget>,
+}
+impl BlendShape {
+    // weightの前後のtargetを線形補間したoffsetを頂点位置と法線に加える。
+    // weightが0の点にはoffsetのないtargetがあるものとし、範囲外のweightは端の2つのtargetで外挿する
+    fn apply(&self, weight: f32, positions: &mut [Vec3], normals: &mut [Vec3]) {
+        let zero = self.targets.partition_point(|target| target.weight < 0.0);
+        let targets = self.targets[..zero]
+            .iter()
+            .map(Some)
+            .chain(std::iter::once(None))
+            .chain(self.targets[zero..].iter().map(Some))
+            .collect::<Vec<_>>();
+        let target_weight = |target: Option<&BlendShapeTarget>| target.map_or(0.0, |t| t.weight);
+        let segment = targets
+            .windows(2)
+            .position(|pair| weight < target_weight(pair[1]))
+            .unwrap_or(targets.len().saturating_sub(2));
+        let (Some(&lower), Some(&upper)) = (targets.get(segment), targets.get(segment + 1)) else {
+            return;
+        };
+        let range = target_weight(upper) - target_weight(lower);
+        let t = if range == 0.0 {
+            1.0
+        } else {
+            (weight - target_weight(lower)) / range
+        };
+        let offset = |target: Option<&BlendShapeTarget>, i: usize| {
+            target.map_or(Vec3::ZERO, |target| target.offsets[i])
+        };
+        let normal_offset = |target: Option<&BlendShapeTarget>, i: usize| {
+            target
+                .and_then(|target| target.normal_offsets.as_ref())
+                .map_or(Vec3::ZERO, |normal_offsets| normal_offsets[i])
+        };
+        for (i, &vertex) in self.vertex_indices.iter().enumerate() {
+            let vertex = vertex as usize;
+            positions[vertex] += offset(lower, i).lerp(offset(upper, i), t);
+            normals[vertex] += normal_offset(lower, i).lerp(normal_offset(upper, i), t);
+        }
+    }
+
+    // weldした頂点の並びにする。同じ頂点にまとめた頂点は同じ点なのでoffsetも同じになり、最初の1つを残す
+    fn weld(&mut self, remap: &[u32]) {
+        let mut welded = HashSet::new();
+        let kept = self
+            .vertex_indices
+            .iter()
+            .enumerate()
+            .filter(|&(_, &vertex)| {
+                let index = remap[vertex as usize];
+                index != UNWELDED_VERTEX && welded.insert(index)
+            })
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        self.vertex_indices = kept
+            .iter()
+            .map(|&i| remap[self.vertex_indices[i] as usize])
+            .collect();
+        for target in &mut self.targets {
+            target.offsets = kept.iter().map(|&i| target.offsets[i]).collect();
+            if let Some(normal_offsets) = &mut target.normal_offsets {
+                *normal_offsets = kept.iter().map(|&i| normal_offsets[i]).collect();
+            }
+        }
+    }
+}
+
+/// blend shapeの一つのtarget。offsetはBlendShapeのvertex_indicesの頂点ごとの値
+#[derive(Debug, Clone)]
+pub struct BlendShapeTarget {
+    /// targetのweight。inbetweenでないtargetは1
+    pub weight: f32,
+    /// 頂点位置のoffset
+    pub offsets: Vec<Vec3>,
+    /// 法線のoffset。authorされていなければNone
+    pub normal_offsets: Option<Vec<Vec3>>,
+}
+
+/// USDから抽出したSkeletonのjointの構造
+#[derive(Debug, Clone)]
+pub struct Skeleton {
+    /// jointのパス。SkeletonPoseやMeshSkinのjointのindexはこの並びのindex
+    pub joints: Vec<String>,
+    /// jointごとの親jointのindex。rootのjointはNone
+    pub parents: Vec<Option<usize>>,
+    /// bind時のjointのワールド座標系での変換
+    pub bind_transforms: Vec<Mat4>,
+    /// restポーズのjointの、親jointの座標系での変換
+    pub rest_transforms: Vec<Mat4>,
+}
+
+/// Skeletonのあるtime codeでのポーズ。
+/// skinningした頂点はSkeletonの座標系で
+/// `Σ weight * skinning_transforms[joint] * geom_bind_transform * point` になり、
+/// transform_matrixでワールド座標系に変換する。GPUのskinningではmeshのtransform matrixは使わない
+#[derive(Debug, Clone)]
+pub struct SkeletonPose {
+    /// Skeletonのtransform matrix
+    pub transform_matrix: TransformMatrix,
+    /// jointごとのskinningの変換
+    pub skinning_transforms: Vec<Mat4>,
+}
+
+/// Skeletonに束縛されたmeshの、頂点バッファと同じ並びのskinningの情報。
+/// GPUのskinningの頂点属性としてそのまま使える
+#[derive(Debug, Clone)]
+pub struct MeshSkin {
+    /// 束縛されたSkeletonのパス
+    pub skeleton: String,
+    /// 頂点ごとに影響する4つのjointのindex
+    pub joint_indices: Vec<[u32; 4]>,
+    /// 頂点ごとの4つのjointの重み。合計は1で、影響しないjointの重みは0
+    pub joint_weights: Vec<Vec4>,
+    /// meshのローカル座標系からbind時の座標系への変換
+    pub geom_bind_transform: Mat4,
+}
+
+/// instancerのprototypeに含まれるmeshを描画するinstanceの一覧。
+/// instanceごとに`transforms[i] * transform_matrix`でmeshを描画する
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeshInstances {
+    /// instanceごとのワールド座標系での変換
+    pub transforms: Vec<Mat4>,
+    /// instanceごとのprimvar。値はtransformsと同じ並びで、1instanceあたり1要素
+    pub primvars: HashMap<String, Primvar>,
+}
+impl MeshInstances {
+    /// meshのローカル座標系のextentとtransform matrixから、全てのinstanceを含む
+    /// ワールド座標系のAABBを求める。instanceがない場合はNoneを返す。
+    pub fn aabb(&self, extent: &Aabb, transform_matrix: &TransformMatrix) -> Option<Aabb> {
+        self.transforms
+            .iter()
+            .map(|transform| {
+                extent.transform(&TransformMatrix {
+                    matrix: *transform * transform_matrix.matrix,
+                })
+            })
+            .reduce(|aabb, instance| aabb.union(&instance))
+    }
+}
+
+// faceごとにtriangulateし、duplicatedした頂点のindexで三角形を作る。
+// left handedの場合は三角形の向きを反転する
+fn triangulate_faces(
+    points: &[Vec3],
+    face_vertex_indices: &[u32],
+    face_vertex_counts: &[u32],
+    left_handed: bool,
+) -> Vec<Vec<u32>> {
+    let mut face_triangles = Vec::with_capacity(face_vertex_counts.len());
+    let mut index_offset = 0;
+    for &face_vertex_count in face_vertex_counts {
+        let face_vertex_count = face_vertex_count as usize;
+        let face = &face_vertex_indices[index_offset..index_offset + face_vertex_count];
+        let mut triangles = Vec::with_capacity(face_vertex_count.saturating_sub(2) * 3);
+        for [a, b, c] in triangulate_face(points, face) {
+            let [a, b, c] = [a, b, c].map(|i| (index_offset + i) as u32);
+            if left_handed {
+                triangles.extend([c, b, a]);
+            } else {
+                triangles.extend([a, b, c]);
+            }
+        }
+        face_triangles.push(triangles);
+        index_offset += face_vertex_count;
+    }
+    face_triangles
+}
+
+// authorされた法線をindicesで展開する。値の数が足りない場合などはエラーを返す
+fn resolve_normals(
+    normals: &[f32],
+    indices: Option<&[u32]>,
+    interpolation: Interpolation,
+    required: usize,
+) -> Result<Vec<Vec3>, String> {
+    if !normals.len().is_multiple_of(3) {
+        return Err(format!(
+            "normals length {} is not a multiple of 3",
+            normals.len()
+        ));
+    }
+    resolve_indexed_primvar(
+        "normals",
+        bytemuck::cast_slice::<f32, Vec3>(normals),
+        indices,
+        interpolation,
+        required,
+    )
+}
+
+// primvarのInterpolationごとに、primvarが持つべき要素数を返す
+fn interpolation_element_count(
+    interpolation: Interpolation,
+    point_count: usize,
+    face_count: usize,
+    face_vertex_count: usize,
+) -> usize {
+    match interpolation {
+        Interpolation::Uniform => face_count,
+        Interpolation::Varying | Interpolation::Vertex => point_count,
+        Interpolation::FaceVarying => face_vertex_count,
+        // Constantと、meshでは意味を持たないInstanceはメッシュ全体で1つの値を持つ
+        _ => 1,
+    }
+}
+
+// indexed primvarの値をindicesで展開し、Interpolationに必要な要素数があるか検証する。
+// indicesがauthorされていない場合は値をそのまま使う。
+fn resolve_indexed_primvar<T: Copy>(
+    name: &str,
+    values: &[T],
+    indices: Option<&[u32]>,
+    interpolation: Interpolation,
+    required: usize,
+) -> Result<Vec<T>, String> {
+    let values = match indices {
+        Some(indices) => {
+            if let Some(&index) = indices
+                .iter()
+                .find(|&&index| index as usize >= values.len())
+            {
+                return Err(format!(
+                    "{name} indices contains {index} but there are only {} values",
+                    values.len()
+                ));
+            }
+            indices
+                .iter()
+                .map(|&index| values[index as usize])
+                .collect()
         }
+        None => values.to_vec(),
+    };
+    if values.len() < required {
+        return Err(format!(
+            "{name} has {} elements but {:?} interpolation requires {required}",
+            values.len(),
+            interpolation
+        ));
     }
+    Ok(values)
+}
+
+// primvarの値をInterpolationに合わせてface-vertexごとにduplicatedする。
+// 要素数は事前にinterpolation_element_countで検証されている前提。
+fn expand_primvar<T: Copy>(
+    values: &[T],
+    interpolation: Interpolation,
+    face_vertex_indices: &[u32],
+    face_vertex_counts: &[u32],
+) -> Vec<T> {
+    let mut data = Vec::with_capacity(face_vertex_indices.len());
+    let mut index_offset = 0;
+    for (face_index, &face_vertex_count) in face_vertex_counts.iter().enumerate() {
+        let face_vertex_count = face_vertex_count as usize;
+        let face = &face_vertex_indices[index_offset..index_offset + face_vertex_count];
+        for (i, &point_index) in face.iter().enumerate() {
+            let element_index = match interpolation {
+                Interpolation::Uniform => face_index,
+                Interpolation::Varying | Interpolation::Vertex => point_index as usize,
+                Interpolation::FaceVarying => index_offset + i,
+                _ => 0,
+            };
+            data.push(values[element_index]);
+        }
+        index_offset += face_vertex_count;
+    }
+    data
 }
 
 /// USDから抽出したシーンのSphereLightの情報
@@ -397,6 +1102,8 @@ pub enum SceneDiffItem {
     MeshDestroyed(SdfPath),
     MeshTransformMatrixDirtied(SdfPath, TransformMatrix),
     MeshDataDirtied(SdfPath, MeshData),
+    /// topologyが変わらずにpointsかnormalsだけが変わったmeshの、作り直した頂点位置と法線
+    MeshPointsDirtied(SdfPath, MeshPoints),
     SphereLightAddOrUpdate(SdfPath, SphereLight),
     SphereLightDestroyed(SdfPath),
     DistantLightAddOrUpdate(SdfPath, DistantLight),
@@ -407,109 +1114,1256 @@ pub enum SceneDiffItem {
     RenderSettingsDestroyed(SdfPath),
     MaterialAddOrUpdate(SdfPath, Material),
     MaterialDestroyed(SdfPath),
+    SkeletonAddOrUpdate(SdfPath, Skeleton),
+    SkeletonDestroyed(SdfPath),
+    SkeletonPoseDirtied(SdfPath, SkeletonPose),
+    /// blend shapeのweightが変わったmeshの、MeshDataのblend_shapesの並びのweight
+    MeshBlendShapeWeightsDirtied(SdfPath, Vec<f32>),
+    /// instancerのprototypeに含まれるmeshの、作り直したinstanceの一覧
+    MeshInstancesDirtied(SdfPath, MeshInstances),
+    /// instancerのprototypeでなくなったmesh。instanceを使わずに一つだけ描画する
+    MeshInstancesDestroyed(SdfPath),
 }
 
 /// シーンの変更点の差分情報全体
 pub struct SceneDiff {
     /// シーンの変更点の差分情報の要素のリスト
     pub items: Vec<SceneDiffItem>,
+    /// データが不正なためitemsに含められなかったprimの情報
+    pub warnings: Vec<Error>,
 }
-impl From<bridge::UsdDataDiff> for SceneDiff {
-    fn from(diff: bridge::UsdDataDiff) -> Self {
-        let mut items = Vec::new();
 
-        for (path, data) in diff.meshes.create {
-            items.push(SceneDiffItem::MeshCreated(
-                path,
-                TransformMatrix {
-                    matrix: data
-                        .transform_matrix
-                        .map_or(Mat4::IDENTITY, |data| Mat4::from_cols_array(&data)),
-                },
-                MeshData::new(
-                    data.left_handed.unwrap_or(false),
-                    data.points.unwrap(),
-                    data.normals,
-                    data.normals_interpolation,
-                    data.uvs,
-                    data.uvs_indices,
-                    data.uvs_interpolation,
-                    data.face_vertex_indices.unwrap(),
-                    data.face_vertex_counts.unwrap(),
-                    data.geom_subsets,
-                    data.material_path,
-                ),
-            ));
+/// 必須の属性を取り出す。authorされていなければMissingAttributeエラーにする。
+fn required<T>(path: &SdfPath, attribute: &'static str, value: Option<T>) -> Result<T, Error> {
+    value.ok_or_else(|| Error::MissingAttribute {
+        path: path.as_str().to_string(),
+        attribute,
+    })
+}
+
+// extractでMeshDataを作るときの設定
+#[derive(Debug, Clone, Copy)]
+struct MeshOptions {
+    // refineLevelが指定されていないmeshを細分割する回数
+    refine_level: u32,
+    // UVがあるmeshのtangentを生成するかどうか
+    tangents: bool,
+    // 同じ頂点データを持つ頂点をweldするかどうか
+    weld: bool,
+    // 法線を計算するときに、辺を越えて平均するfaceの法線のなす角の上限(度)
+    crease_angle: f32,
+    // Skeletonに束縛されたmeshにCPUでポーズを適用してから返すかどうか
+    cpu_skinning: bool,
+}
+impl Default for MeshOptions {
+    fn default() -> Self {
+        Self {
+            refine_level: 0,
+            tangents: true,
+            weld: false,
+            crease_angle: 180.0,
+            cpu_skinning: false,
         }
-        for path in diff.meshes.destroy {
-            items.push(SceneDiffItem::MeshDestroyed(path));
+    }
+}
+
+// extractの間で持ち越す、差分だけからは作れない情報
+#[derive(Default)]
+struct SceneCache {
+    // extractで送ったmeshの、pointsだけの差分から頂点を作り直すための情報
+    mesh_sources: HashMap<SdfPath, MeshSource>,
+    // meshのデータとは別の差分で届く、meshごとのskinningの束縛とblend shape
+    bindings: MeshBindings,
+    // meshごとの最新のblend shapeのweight
+    blend_shape_weights: HashMap<SdfPath, Vec<f32>>,
+    // instancerのパスごとの最新の情報
+    instancers: Instancers,
+    // extractで送ったmeshごとのinstanceの一覧
+    mesh_instances: HashMap<SdfPath, MeshInstances>,
+    // meshの補正済みのtransform matrix。instancerが変わったときに作り直すmeshの一覧にも使う
+    mesh_transforms: HashMap<SdfPath, TransformMatrix>,
+    // 以下はCPU skinningのための情報
+    // Skeletonのパスごとの最新のポーズ
+    skeleton_poses: HashMap<String, SkeletonPose>,
+    // ポーズを適用する前のskinningされたmesh
+    rest_meshes: HashMap<SdfPath, MeshData>,
+}
+
+// meshのデータとは別の差分で届く、meshごとのskinningの束縛とblend shape
+#[derive(Default)]
+struct MeshBindings {
+    skins: HashMap<SdfPath, bridge::SkinBindingData>,
+    blend_shapes: HashMap<SdfPath, Vec<bridge::BlendShapeData>>,
+}
+
+// instanceの一覧を作り直すために記録しておくinstancerの情報。
+// transformは座標系の補正前のもの
+struct Instancer {
+    transform_matrix: Mat4,
+    instance_transforms: Vec<Mat4>,
+    // prototypeのroot pathと、そのprototypeが使うinstanceのindex
+    prototypes: Vec<(String, Vec<u32>)>,
+    primvars: HashMap<String, bridge::PrimvarData>,
+}
+
+// instancerのパスごとの情報と、prototypeのroot pathからinstancerを引くための索引
+#[derive(Default)]
+struct Instancers {
+    instancers: HashMap<String, Instancer>,
+    // prototypeのroot pathごとの、そのprototypeを持つinstancerのパスとprototypesでの番号
+    prototypes: HashMap<String, (String, usize)>,
+}
+impl Instancers {
+    // instancerを追加または更新し、追加前と追加後のprototypeのroot pathを返す
+    fn insert(&mut self, path: String, instancer: Instancer) -> Vec<String> {
+        let mut roots = self.remove(&path);
+        for (index, (root, _)) in instancer.prototypes.iter().enumerate() {
+            self.prototypes.insert(root.clone(), (path.clone(), index));
+            roots.push(root.clone());
         }
-        for (path, matrix) in diff.meshes.diff_transform_matrix {
-            items.push(SceneDiffItem::MeshTransformMatrixDirtied(
-                path,
-                TransformMatrix {
-                    matrix: Mat4::from_cols_array(&matrix),
-                },
-            ));
+        self.instancers.insert(path, instancer);
+        roots
+    }
+
+    // instancerを削除し、削除したinstancerが持っていたprototypeのroot pathを返す
+    fn remove(&mut self, path: &str) -> Vec<String> {
+        let Some(instancer) = self.instancers.remove(path) else {
+            return Vec::new();
+        };
+        let roots = instancer
+            .prototypes
+            .into_iter()
+            .map(|(root, _)| root)
+            .collect::<Vec<_>>();
+        for root in &roots {
+            if self
+                .prototypes
+                .get(root)
+                .is_some_and(|(instancer_path, _)| instancer_path == path)
+            {
+                self.prototypes.remove(root);
+            }
         }
-        for (path, data) in diff.meshes.diff_mesh_data {
-            items.push(SceneDiffItem::MeshDataDirtied(
-                path,
-                MeshData::new(
-                    data.left_handed.unwrap_or(false),
-                    data.points.unwrap(),
-                    data.normals,
-                    data.normals_interpolation,
-                    data.uvs,
-                    data.uvs_indices,
-                    data.uvs_interpolation,
-                    data.face_vertex_indices.unwrap(),
-                    data.face_vertex_counts.unwrap(),
-                    data.geom_subsets,
-                    data.material_path,
-                ),
-            ));
+        roots
+    }
+
+    // pathのprimを含むprototypeを持つinstancerと、そのprototypeが使うinstanceのindexを探す。
+    // prototypeが入れ子になっている場合は、一番内側のprototypeを返す
+    fn enclosing_prototype(&self, path: &str) -> Option<(&str, &Instancer, &[u32])> {
+        ancestor_paths(path).find_map(|root| {
+            let (instancer_path, index) = self.prototypes.get(root)?;
+            if instancer_path == path {
+                return None;
+            }
+            let instancer = self.instancers.get(instancer_path)?;
+            let (_, indices) = instancer.prototypes.get(*index)?;
+            Some((instancer_path.as_str(), instancer, indices.as_slice()))
+        })
+    }
+
+    // rootsのprototypeの中にあるinstancerのprototypeのroot pathを、入れ子をたどって加える。
+    // 外側のinstanceの配置が変わると、内側のprototypeのinstanceの配置も変わる
+    fn nested_prototype_roots(&self, mut roots: HashSet<String>) -> HashSet<String> {
+        for _ in 0..MAX_INSTANCING_DEPTH {
+            let nested = self
+                .instancers
+                .iter()
+                .filter(|(path, _)| ancestor_paths(path).any(|path| roots.contains(path)))
+                .flat_map(|(_, instancer)| instancer.prototypes.iter())
+                .filter(|(root, _)| !roots.contains(root))
+                .map(|(root, _)| root.clone())
+                .collect::<Vec<_>>();
+            if nested.is_empty() {
+                break;
+            }
+            roots.extend(nested);
         }
+        roots
+    }
+}
 
-        for (path, data) in diff.sphere_lights.update {
-            items.push(SceneDiffItem::SphereLightAddOrUpdate(
-                path,
-                SphereLight::new(
-                    Mat4::from_cols_array(&data.transform_matrix.unwrap()),
-                    data.intensity.unwrap(),
-                    Vec3::from(data.color.unwrap()),
-                    data.cone_angle,
-                    data.cone_softness,
-                ),
+// pathとその祖先のpathを、pathに近いものから順に返す
+fn ancestor_paths(path: &str) -> impl Iterator<Item = &str> {
+    std::iter::successors(Some(path), |path| {
+        path.rfind('/').filter(|&i| i > 0).map(|i| &path[..i])
+    })
+}
+
+// pointsだけの差分から頂点を作り直すために、extractで送ったmeshごとに記録しておく情報
+struct MeshSource {
+    // 細分割前の差分情報。頂点位置と法線だけを作り直せない場合は、ここからMeshDataを作り直す
+    data: bridge::MeshDataDiff,
+    // 細分割していないmeshの、頂点位置と法線だけを作り直すための情報
+    deformable: Option<DeformableMesh>,
+}
+
+// topologyが変わらないmeshの頂点位置と法線を作り直すための情報
+struct DeformableMesh {
+    point_count: usize,
+    face_vertex_indices: Vec<u32>,
+    face_vertex_counts: Vec<u32>,
+    face_triangles: Vec<Vec<u32>>,
+    // tangentを作り直すための、weld前の頂点のUV。tangentを生成していない場合はNone
+    uvs: Option<Vec<Vec2>>,
+    // weldしたmeshの場合は、weld前の頂点ごとのweldした頂点のindexと、weldした頂点の数
+    welded: Option<(Vec<u32>, usize)>,
+}
+impl DeformableMesh {
+    // 新しいpointsとnormalsから頂点位置と法線を作り直す。
+    // pointsが変わってweldした頂点に違う値が入るようになった場合は、weldからやり直すためにNoneを返す
+    fn deform(
+        &self,
+        diff: &bridge::MeshPointsDiff,
+        crease_angle: f32,
+    ) -> Result<Option<MeshPoints>, String> {
+        if diff.points.len() != self.point_count * 3 {
+            return Err(format!(
+                "points length {} does not match the {} points of the mesh",
+                diff.points.len(),
+                self.point_count
             ));
         }
-        for path in diff.sphere_lights.destroy {
-            items.push(SceneDiffItem::SphereLightDestroyed(path));
+        let points = bytemuck::cast_slice::<f32, Vec3>(&diff.points);
+        let extent = match &diff.extent {
+            Some(extent) => Aabb::from_extent(extent),
+            None => Aabb::from_points(points),
+        };
+        let positions = self
+            .face_vertex_indices
+            .iter()
+            .map(|&index| points[index as usize])
+            .collect::<Vec<_>>();
+        let normals = match (&diff.normals, diff.normals_interpolation) {
+            (Some(normals), Some(interpolation)) => {
+                let required = interpolation_element_count(
+                    interpolation,
+                    self.point_count,
+                    self.face_vertex_counts.len(),
+                    self.face_vertex_indices.len(),
+                );
+                let normals = resolve_normals(
+                    normals,
+                    diff.normals_indices.as_deref(),
+                    interpolation,
+                    required,
+                )?;
+                expand_primvar(
+                    &normals,
+                    interpolation,
+                    &self.face_vertex_indices,
+                    &self.face_vertex_counts,
+                )
+            }
+            _ => normals::generate_normals(
+                points,
+                &self.face_vertex_indices,
+                &self.face_vertex_counts,
+                &self.face_triangles,
+                crease_angle,
+            ),
+        };
+        let tangents = self.uvs.as_ref().and_then(|uvs| {
+            let vertices = positions
+                .iter()
+                .zip(&normals)
+                .zip(uvs)
+                .map(|((&position, &normal), &uv)| Vertex {
+                    position,
+                    normal,
+                    uv,
+                })
+                .collect::<Vec<_>>();
+            tangent::generate_tangents(&vertices, &self.face_triangles.concat())
+        });
+
+        let Some((remap, welded_count)) = &self.welded else {
+            return Ok(Some(MeshPoints {
+                positions,
+                normals,
+                tangents,
+                extent,
+            }));
+        };
+        // weldした頂点の並びにまとめる
+        let weld = |values: &[Vec3]| weld_values(values, remap, *welded_count);
+        let (Some(positions), Some(normals)) = (weld(&positions), weld(&normals)) else {
+            return Ok(None);
+        };
+        let tangents = match tangents {
+            Some(tangents) => match weld_values(&tangents, remap, *welded_count) {
+                Some(tangents) => Some(tangents),
+                None => return Ok(None),
+            },
+            None => None,
+        };
+        Ok(Some(MeshPoints {
+            positions,
+            normals,
+            tangents,
+            extent,
+        }))
+    }
+}
+
+// weld前の頂点の値を、weldした頂点の並びにまとめる。
+// 同じ頂点にまとめた頂点の値が違う場合はNoneを返す
+fn weld_values<T: Copy + PartialEq>(
+    values: &[T],
+    remap: &[u32],
+    welded_count: usize,
+) -> Option<Vec<T>> {
+    let mut welded = vec![None; welded_count];
+    for (&value, &index) in values.iter().zip(remap) {
+        if index == UNWELDED_VERTEX {
+            continue;
+        }
+        match welded[index as usize] {
+            None => welded[index as usize] = Some(value),
+            Some(welded_value) if welded_value == value => {}
+            Some(_) => return None,
         }
+    }
+    welded.into_iter().collect()
+}
 
-        for (path, data) in diff.distant_lights.update {
-            items.push(SceneDiffItem::DistantLightAddOrUpdate(
-                path,
-                DistantLight::new(
-                    Mat4::from_cols_array(&data.transform_matrix.unwrap()),
-                    data.intensity.unwrap(),
-                    Vec3::from(data.color.unwrap()),
+// skinningの束縛を、meshの頂点バッファと同じface-vertexの並びに展開する
+fn mesh_skin(
+    skin: &bridge::SkinBindingData,
+    point_count: usize,
+    face_vertex_indices: &[u32],
+    face_vertex_counts: &[u32],
+) -> Result<MeshSkin, String> {
+    let required = match skin.interpolation {
+        Interpolation::Constant => 1,
+        _ => point_count,
+    };
+    if skin.joint_indices.len() != required * 4 || skin.joint_weights.len() != required * 4 {
+        return Err(format!(
+            "skin has {} joint indices and {} joint weights but {:?} interpolation requires {}",
+            skin.joint_indices.len(),
+            skin.joint_weights.len(),
+            skin.interpolation,
+            required * 4
+        ));
+    }
+    let joint_indices = skin
+        .joint_indices
+        .chunks_exact(4)
+        .map(|indices| [indices[0], indices[1], indices[2], indices[3]])
+        .collect::<Vec<_>>();
+    let joint_weights = skin
+        .joint_weights
+        .chunks_exact(4)
+        .map(Vec4::from_slice)
+        .collect::<Vec<_>>();
+    Ok(MeshSkin {
+        skeleton: skin.skeleton_path.clone(),
+        joint_indices: expand_primvar(
+            &joint_indices,
+            skin.interpolation,
+            face_vertex_indices,
+            face_vertex_counts,
+        ),
+        joint_weights: expand_primvar(
+            &joint_weights,
+            skin.interpolation,
+            face_vertex_indices,
+            face_vertex_counts,
+        ),
+        geom_bind_transform: Mat4::from_cols_array(&skin.geom_bind_transform),
+    })
+}
+
+// blend shapeを、meshの頂点バッファと同じface-vertexの並びに展開する。
+// 点ごとのoffsetは、その点を使う全ての頂点のoffsetになる
+fn mesh_blend_shapes(
+    blend_shapes: &[bridge::BlendShapeData],
+    point_count: usize,
+    face_vertex_indices: &[u32],
+) -> Result<Vec<BlendShape>, String> {
+    let mut point_vertices = vec![Vec::new(); point_count];
+    for (vertex, &point) in face_vertex_indices.iter().enumerate() {
+        point_vertices[point as usize].push(vertex as u32);
+    }
+    let mut mesh_blend_shapes = Vec::with_capacity(blend_shapes.len());
+    for data in blend_shapes {
+        let point_indices = match &data.point_indices {
+            Some(indices) => {
+                if let Some(&index) = indices.iter().find(|&&index| index as usize >= point_count) {
+                    return Err(format!(
+                        "blend shape {} pointIndices contains {index} but there are only {point_count} points",
+                        data.name
+                    ));
+                }
+                indices.clone()
+            }
+            // offsetsがないblend shapeはどの点も動かさない
+            None if data.offsets.is_empty() => Vec::new(),
+            None => (0..point_count as u32).collect(),
+        };
+        let offsets = |name, values: &[f32]| {
+            if values.len() != point_indices.len() * 3 {
+                return Err(format!(
+                    "blend shape {} {name} has {} values but {} points require {}",
+                    data.name,
+                    values.len(),
+                    point_indices.len(),
+                    point_indices.len() * 3
+                ));
+            }
+            Ok(point_indices
+                .iter()
+                .zip(bytemuck::cast_slice::<f32, Vec3>(values))
+                .flat_map(|(&point, &offset)| {
+                    std::iter::repeat_n(offset, point_vertices[point as usize].len())
+                })
+                .collect::<Vec<_>>())
+        };
+        let mut targets = Vec::with_capacity(data.inbetweens.len() + 1);
+        let inbetweens = data.inbetweens.iter().map(|inbetween| {
+            (
+                inbetween.weight,
+                &inbetween.offsets,
+                &inbetween.normal_offsets,
+            )
+        });
+        for (weight, target_offsets, normal_offsets) in
+            std::iter::once((1.0, &data.offsets, &data.normal_offsets)).chain(inbetweens)
+        {
+            targets.push(BlendShapeTarget {
+                weight,
+                offsets: offsets("offsets", target_offsets)?,
+                normal_offsets: normal_offsets
+                    .as_deref()
+                    .map(|normal_offsets| offsets("normalOffsets", normal_offsets))
+                    .transpose()?,
+            });
+        }
+        targets.sort_by(|a, b| a.weight.total_cmp(&b.weight));
+        mesh_blend_shapes.push(BlendShape {
+            name: data.name.clone(),
+            vertex_indices: point_indices
+                .iter()
+                .flat_map(|&point| point_vertices[point as usize].iter().copied())
+                .collect(),
+            targets,
+        });
+    }
+    Ok(mesh_blend_shapes)
+}
+
+/// 差分情報からMeshDataを作る。データが不正な場合はエラーを返す。
+/// primにrefineLevelが指定されていなければoptionsのrefine_levelの回数だけ細分割する。
+/// pointsだけの差分から頂点を作り直すためのMeshSourceも返す。
+/// skinningの束縛やblend shapeがあれば頂点バッファと同じ並びに展開するが、
+/// 細分割したmeshでは無視し、無視したことをwarningsに追加する。
+fn mesh_data(
+    path: &SdfPath,
+    data: bridge::MeshDataDiff,
+    bindings: &MeshBindings,
+    options: MeshOptions,
+    warnings: &mut Vec<Error>,
+) -> Result<(MeshData, MeshSource), Error> {
+    let unsupported = |reason| Error::UnsupportedData {
+        path: path.as_str().to_string(),
+        reason,
+    };
+    let level = data.refine_level.unwrap_or(options.refine_level);
+    let refined = level > 0 && data.subdivision_scheme != Some(SubdivisionScheme::None);
+    let source_data = data.clone();
+    let data = subdivision::refine(data, level).map_err(unsupported)?;
+    let left_handed = data.left_handed.unwrap_or(false);
+    let extent = data.extent;
+    let points = required(path, "points", data.points)?;
+    let face_vertex_indices = required(path, "faceVertexIndices", data.face_vertex_indices)?;
+    let face_vertex_counts = required(path, "faceVertexCounts", data.face_vertex_counts)?;
+    let mut mesh_data = MeshData::new(
+        left_handed,
+        &points,
+        data.normals,
+        data.normals_indices,
+        data.normals_interpolation,
+        data.uvs,
+        data.uvs_indices,
+        data.uvs_interpolation,
+        data.primvars,
+        &face_vertex_indices,
+        &face_vertex_counts,
+        data.hole_indices,
+        data.geom_subsets,
+        data.material_path,
+        options,
+    )
+    .map_err(unsupported)?;
+    if let Some(extent) = &extent {
+        mesh_data.extent = Aabb::from_extent(extent);
+    }
+    match bindings.skins.get(path) {
+        Some(_) if refined => warnings.push(unsupported(
+            "skinning of a refined mesh is not supported, ignoring the skin binding".to_string(),
+        )),
+        Some(skin) => {
+            mesh_data.skin = Some(
+                mesh_skin(
+                    skin,
+                    points.len() / 3,
+                    &face_vertex_indices,
+                    &face_vertex_counts,
+                )
+                .map_err(unsupported)?,
+            );
+        }
+        None => {}
+    }
+    match bindings.blend_shapes.get(path) {
+        Some(_) if refined => warnings.push(unsupported(
+            "blend shapes of a refined mesh are not supported, ignoring them".to_string(),
+        )),
+        Some(blend_shapes) => {
+            mesh_data.blend_shapes =
+                mesh_blend_shapes(blend_shapes, points.len() / 3, &face_vertex_indices)
+                    .map_err(unsupported)?;
+        }
+        None => {}
+    }
+    // weldで違う点の頂点をまとめるとblend shapeで別々に動かせなくなる
+    let weld_points =
+        (options.weld && !mesh_data.blend_shapes.is_empty()).then(|| face_vertex_indices.clone());
+
+    // 細分割したmeshはpointsが変わったら細分割からやり直す
+    let mut deformable = (!refined).then(|| DeformableMesh {
+        point_count: points.len() / 3,
+        face_triangles: triangulate_faces(
+            bytemuck::cast_slice::<f32, Vec3>(&points),
+            &face_vertex_indices,
+            &face_vertex_counts,
+            left_handed,
+        ),
+        face_vertex_indices,
+        face_vertex_counts,
+        uvs: mesh_data
+            .tangents
+            .as_ref()
+            .map(|_| mesh_data.vertices.iter().map(|vertex| vertex.uv).collect()),
+        welded: None,
+    });
+
+    if options.weld {
+        let vertex_count = mesh_data.vertices.len();
+        let remap = mesh_data.weld(weld_points.as_deref());
+        let welded_count = mesh_data.vertices.len();
+        log::debug!(
+            "{}: welded {vertex_count} vertices into {welded_count} (compression ratio {:.2})",
+            path.as_str(),
+            vertex_count as f64 / welded_count.max(1) as f64,
+        );
+        if let Some(mesh) = &mut deformable {
+            mesh.welded = Some((remap, welded_count));
+        }
+    }
+    let source = MeshSource {
+        data: source_data,
+        deformable,
+    };
+    Ok((mesh_data, source))
+}
+
+/// pointsだけの差分から、記録しておいたmeshの情報を使って頂点位置と法線を作り直す。
+/// 細分割したmeshや、weldした頂点の組が変わるmeshの場合はMeshData全体を作り直す。
+fn mesh_points(
+    path: SdfPath,
+    diff: bridge::MeshPointsDiff,
+    bindings: &MeshBindings,
+    options: MeshOptions,
+    mesh_sources: &mut HashMap<SdfPath, MeshSource>,
+    warnings: &mut Vec<Error>,
+) -> Result<SceneDiffItem, Error> {
+    let Some(source) = mesh_sources.get_mut(&path) else {
+        return Err(Error::UnsupportedData {
+            path: path.as_str().to_string(),
+            reason: "points changed on a mesh that was not extracted".to_string(),
+        });
+    };
+    // 頂点位置だけを更新できない場合はmeshを作り直し、データの検証もそちらに任せる
+    let points = source
+        .deformable
+        .as_ref()
+        .and_then(|mesh| mesh.deform(&diff, options.crease_angle).ok().flatten());
+    source.data.points = Some(diff.points);
+    source.data.extent = diff.extent;
+    source.data.normals = diff.normals;
+    source.data.normals_indices = diff.normals_indices;
+    source.data.normals_interpolation = diff.normals_interpolation;
+    if let Some(points) = points {
+        return Ok(SceneDiffItem::MeshPointsDirtied(path, points));
+    }
+
+    let (mesh_data, source) = mesh_data(&path, source.data.clone(), bindings, options, warnings)?;
+    mesh_sources.insert(path.clone(), source);
+    Ok(SceneDiffItem::MeshDataDirtied(path, mesh_data))
+}
+
+// Skeletonの差分情報から、jointの構造を作る
+fn skeleton(data: bridge::SkeletonData) -> Result<Skeleton, String> {
+    let joint_count = data.joints.len();
+    let transforms = |name, values: &[f32]| {
+        if values.len() != joint_count * 16 {
+            return Err(format!(
+                "{name} has {} values but {joint_count} joints require {}",
+                values.len(),
+                joint_count * 16
+            ));
+        }
+        Ok(values
+            .chunks_exact(16)
+            .map(Mat4::from_cols_slice)
+            .collect::<Vec<_>>())
+    };
+    let bind_transforms = transforms("bindTransforms", &data.bind_transforms)?;
+    let rest_transforms = transforms("restTransforms", &data.rest_transforms)?;
+    if data.parents.len() != joint_count {
+        return Err(format!(
+            "{} parent indices do not match {joint_count} joints",
+            data.parents.len()
+        ));
+    }
+    let parents = data
+        .parents
+        .iter()
+        .map(|&parent| usize::try_from(parent).ok())
+        .collect();
+    Ok(Skeleton {
+        joints: data.joints,
+        parents,
+        bind_transforms,
+        rest_transforms,
+    })
+}
+
+fn instancer(data: bridge::InstancerDiffItem) -> Result<Instancer, String> {
+    if !data.instance_transforms.len().is_multiple_of(16) {
+        return Err(format!(
+            "instance transforms length {} is not a multiple of 16",
+            data.instance_transforms.len()
+        ));
+    }
+    let instance_count = data.instance_transforms.len() / 16;
+    for (prototype_path, instance_indices) in &data.prototypes {
+        if let Some(&index) = instance_indices
+            .iter()
+            .find(|&&index| index as usize >= instance_count)
+        {
+            return Err(format!(
+                "prototype {prototype_path} uses instance {index} but there are {instance_count} instances"
+            ));
+        }
+    }
+    for (name, primvar) in &data.primvars {
+        let expected = instance_count * primvar.element_type.component_count();
+        if primvar.values.len() != expected {
+            return Err(format!(
+                "primvar {name} has {} values but {instance_count} instances require {expected}",
+                primvar.values.len()
+            ));
+        }
+    }
+    Ok(Instancer {
+        transform_matrix: data
+            .transform_matrix
+            .map_or(Mat4::IDENTITY, |data| Mat4::from_cols_array(&data)),
+        instance_transforms: data
+            .instance_transforms
+            .chunks_exact(16)
+            .map(Mat4::from_cols_slice)
+            .collect(),
+        prototypes: data.prototypes,
+        primvars: data.primvars,
+    })
+}
+
+// instancerのprototypeの入れ子をたどる深さの上限。循環した参照で止まらなくなるのを防ぐ
+const MAX_INSTANCING_DEPTH: usize = 32;
+
+// pathのprimを配置する、補正前のワールド座標系への変換の一覧。
+// 外側のinstancerのinstanceごとに、内側のinstancerのinstanceが並ぶ
+fn prototype_transforms(instancers: &Instancers, path: &str, depth: usize) -> Option<Vec<Mat4>> {
+    let (instancer_path, instancer, indices) = instancers.enclosing_prototype(path)?;
+    let outer = if depth < MAX_INSTANCING_DEPTH {
+        prototype_transforms(instancers, instancer_path, depth + 1)
+    } else {
+        None
+    }
+    .unwrap_or_else(|| vec![Mat4::IDENTITY]);
+    Some(
+        outer
+            .iter()
+            .flat_map(|outer| {
+                indices.iter().map(move |&index| {
+                    *outer
+                        * instancer.transform_matrix
+                        * instancer.instance_transforms[index as usize]
+                })
+            })
+            .collect(),
+    )
+}
+
+// pathのmeshを描画するinstanceの一覧を求める。instancerのprototypeでなければNone。
+// primvarは一番内側のinstancerのものを使う
+fn mesh_instances(instancers: &Instancers, path: &str, correction: Mat4) -> Option<MeshInstances> {
+    let (_, instancer, indices) = instancers.enclosing_prototype(path)?;
+    let transforms = prototype_transforms(instancers, path, 0)?;
+    // meshのtransform matrixには補正が掛かっているので、instanceの変換は補正した座標系で行う
+    let correction_inverse = correction.inverse();
+    let transforms = transforms
+        .into_iter()
+        .map(|transform| correction * transform * correction_inverse)
+        .collect::<Vec<_>>();
+    let repeat = transforms.len() / indices.len().max(1);
+    let primvars = instancer
+        .primvars
+        .iter()
+        .map(|(name, data)| {
+            let component_count = data.element_type.component_count();
+            let values = (0..repeat)
+                .flat_map(|_| indices.iter())
+                .flat_map(|&index| {
+                    let start = index as usize * component_count;
+                    data.values[start..start + component_count].iter().copied()
+                })
+                .collect();
+            (
+                name.clone(),
+                Primvar {
+                    element_type: data.element_type,
+                    values,
+                },
+            )
+        })
+        .collect();
+    Some(MeshInstances {
+        transforms,
+        primvars,
+    })
+}
+
+// instancerの差分をcacheに記録する。
+// 変わったinstancerのprototypeと、その中に入れ子になったprototypeのroot pathを返す
+fn update_instancers(
+    warnings: &mut Vec<Error>,
+    diff: bridge::InstancersDiff,
+    cache: &mut SceneCache,
+) -> HashSet<String> {
+    let mut roots = HashSet::new();
+    for path in diff.destroy {
+        roots.extend(cache.instancers.remove(path.as_str()));
+    }
+    for (path, data) in diff.update {
+        match instancer(data) {
+            Ok(instancer) => {
+                roots.extend(
+                    cache
+                        .instancers
+                        .insert(path.as_str().to_string(), instancer),
+                );
+            }
+            Err(reason) => {
+                roots.extend(cache.instancers.remove(path.as_str()));
+                warnings.push(Error::UnsupportedData {
+                    path: path.as_str().to_string(),
+                    reason,
+                });
+            }
+        }
+    }
+    cache.instancers.nested_prototype_roots(roots)
+}
+
+// pathsのmeshのinstanceの一覧を作り直し、前回から変わったものをitemsに追加する
+fn update_mesh_instances(
+    items: &mut Vec<SceneDiffItem>,
+    paths: impl IntoIterator<Item = SdfPath>,
+    correction: Mat4,
+    cache: &mut SceneCache,
+) {
+    for path in paths {
+        let instances = mesh_instances(&cache.instancers, path.as_str(), correction);
+        match instances {
+            Some(instances) => {
+                if cache.mesh_instances.get(&path) != Some(&instances) {
+                    cache.mesh_instances.insert(path.clone(), instances.clone());
+                    items.push(SceneDiffItem::MeshInstancesDirtied(path, instances));
+                }
+            }
+            None => {
+                if cache.mesh_instances.remove(&path).is_some() {
+                    items.push(SceneDiffItem::MeshInstancesDestroyed(path));
+                }
+            }
+        }
+    }
+}
+
+// Skeletonの追加、更新と削除をitemsに追加する
+fn update_skeletons(
+    items: &mut Vec<SceneDiffItem>,
+    warnings: &mut Vec<Error>,
+    skeletons: HashMap<SdfPath, bridge::SkeletonData>,
+    destroyed: Vec<SdfPath>,
+    cache: &mut SceneCache,
+) {
+    for (path, data) in skeletons {
+        match skeleton(data) {
+            Ok(skeleton) => items.push(SceneDiffItem::SkeletonAddOrUpdate(path, skeleton)),
+            Err(reason) => warnings.push(Error::UnsupportedData {
+                path: path.as_str().to_string(),
+                reason,
+            }),
+        }
+    }
+    for path in destroyed {
+        cache.skeleton_poses.remove(path.as_str());
+        items.push(SceneDiffItem::SkeletonDestroyed(path));
+    }
+}
+
+// Skeletonのポーズをcacheに記録してitemsに追加する。ポーズが変わったSkeletonのpathを返す
+fn update_skeleton_poses(
+    items: &mut Vec<SceneDiffItem>,
+    warnings: &mut Vec<Error>,
+    poses: HashMap<SdfPath, bridge::SkeletonPoseData>,
+    correction: Mat4,
+    cache: &mut SceneCache,
+) -> HashSet<String> {
+    let mut dirtied_skeletons = HashSet::new();
+    for (path, data) in poses {
+        if !data.skinning_transforms.len().is_multiple_of(16) {
+            warnings.push(Error::UnsupportedData {
+                path: path.as_str().to_string(),
+                reason: format!(
+                    "skinning transforms length {} is not a multiple of 16",
+                    data.skinning_transforms.len()
                 ),
+            });
+            continue;
+        }
+        let pose = SkeletonPose {
+            transform_matrix: TransformMatrix {
+                matrix: correction * Mat4::from_cols_array(&data.transform_matrix),
+            },
+            skinning_transforms: data
+                .skinning_transforms
+                .chunks_exact(16)
+                .map(Mat4::from_cols_slice)
+                .collect(),
+        };
+        cache
+            .skeleton_poses
+            .insert(path.as_str().to_string(), pose.clone());
+        dirtied_skeletons.insert(path.as_str().to_string());
+        items.push(SceneDiffItem::SkeletonPoseDirtied(path, pose));
+    }
+    dirtied_skeletons
+}
+
+// skinningの束縛の変更をcacheに記録する。束縛が変わったmeshのpathを返す
+fn update_skin_bindings(
+    skin_bindings: HashMap<SdfPath, bridge::SkinBindingData>,
+    destroyed: Vec<SdfPath>,
+    cache: &mut SceneCache,
+) -> HashSet<SdfPath> {
+    let mut rebind = HashSet::new();
+    for path in destroyed {
+        cache.bindings.skins.remove(&path);
+        rebind.insert(path);
+    }
+    for (path, data) in skin_bindings {
+        cache.bindings.skins.insert(path.clone(), data);
+        rebind.insert(path);
+    }
+    rebind
+}
+
+// blend shapeの変更をcacheに記録する。blend shapeが変わったmeshのpathを返す
+fn update_blend_shapes(
+    blend_shapes: HashMap<SdfPath, Vec<bridge::BlendShapeData>>,
+    destroyed: Vec<SdfPath>,
+    cache: &mut SceneCache,
+) -> HashSet<SdfPath> {
+    let mut rebind = HashSet::new();
+    for path in destroyed {
+        cache.bindings.blend_shapes.remove(&path);
+        rebind.insert(path);
+    }
+    for (path, data) in blend_shapes {
+        cache.bindings.blend_shapes.insert(path.clone(), data);
+        rebind.insert(path);
+    }
+    rebind
+}
+
+// blend shapeのweightをcacheに記録してitemsに追加する。weightが変わったmeshのpathを返す
+fn update_blend_shape_weights(
+    items: &mut Vec<SceneDiffItem>,
+    blend_shape_weights: HashMap<SdfPath, Vec<f32>>,
+    cache: &mut SceneCache,
+) -> HashSet<SdfPath> {
+    let mut dirtied_meshes = HashSet::new();
+    for (path, weights) in blend_shape_weights {
+        cache
+            .blend_shape_weights
+            .insert(path.clone(), weights.clone());
+        dirtied_meshes.insert(path.clone());
+        items.push(SceneDiffItem::MeshBlendShapeWeightsDirtied(path, weights));
+    }
+    dirtied_meshes
+}
+
+// 束縛やblend shapeが変わったmeshを、記録しておいた情報から作り直す
+fn rebind_meshes(
+    items: &mut Vec<SceneDiffItem>,
+    warnings: &mut Vec<Error>,
+    rebind: HashSet<SdfPath>,
+    options: MeshOptions,
+    cache: &mut SceneCache,
+) {
+    for path in rebind {
+        let Some(source) = cache.mesh_sources.get(&path) else {
+            continue;
+        };
+        let data = source.data.clone();
+        match mesh_data(&path, data, &cache.bindings, options, warnings) {
+            Ok((mesh_data, source)) => {
+                cache.mesh_sources.insert(path.clone(), source);
+                items.push(SceneDiffItem::MeshDataDirtied(path, mesh_data))
+            }
+            Err(err) => {
+                cache.mesh_sources.remove(&path);
+                warnings.push(err)
+            }
+        }
+    }
+}
+
+// ポーズを適用したmeshの頂点位置と法線を求める。ポーズやtransform matrixがまだない場合はNone
+fn skinned_points(cache: &SceneCache, path: &SdfPath, mesh_data: &MeshData) -> Option<MeshPoints> {
+    let skin = mesh_data.skin.as_ref()?;
+    let pose = cache.skeleton_poses.get(&skin.skeleton)?;
+    let transform_matrix = cache.mesh_transforms.get(path)?;
+    let points = mesh_data.skinned_points(pose, transform_matrix);
+    if points.is_none() {
+        log::warn!(
+            "{}: skin refers to joints that {} does not have",
+            path.as_str(),
+            skin.skeleton
+        );
+    }
+    points
+}
+
+// blend shapeとskinningをCPUで適用した頂点位置と法線を求める。blend shapeはskinningより先に適用する。
+// ポーズやtransform matrixがまだない場合は、blend shapeだけを適用する
+fn deformed_points(cache: &SceneCache, path: &SdfPath, rest: &MeshData) -> Option<MeshPoints> {
+    if rest.blend_shapes.is_empty() {
+        return skinned_points(cache, path, rest);
+    }
+    let weights = cache
+        .blend_shape_weights
+        .get(path)
+        .map_or(&[][..], Vec::as_slice);
+    let points = rest.blend_shape_points(weights);
+    if rest.skin.is_none() {
+        return Some(points);
+    }
+    let mut blended = rest.clone();
+    blended.apply_points(&points);
+    skinned_points(cache, path, &blended).or(Some(points))
+}
+
+// skinningやblend shapeのあるmeshにCPUで変形を適用し、適用する前のmeshはcacheに記録する。
+// ポーズやblend shapeのweight、transform matrixが変わったmeshは、変形し直した頂点位置と法線を追加する
+fn cpu_skinning(
+    items: &mut Vec<SceneDiffItem>,
+    dirtied_meshes: &HashSet<SdfPath>,
+    dirtied_skeletons: &HashSet<String>,
+    cache: &mut SceneCache,
+) {
+    let mut deformed = HashSet::new();
+    for item in items.iter_mut() {
+        match item {
+            SceneDiffItem::MeshCreated(path, _, mesh_data)
+            | SceneDiffItem::MeshDataDirtied(path, mesh_data) => {
+                if mesh_data.skin.is_none() && mesh_data.blend_shapes.is_empty() {
+                    cache.rest_meshes.remove(path);
+                    continue;
+                }
+                let rest = mesh_data.clone();
+                if let Some(points) = deformed_points(cache, path, &rest) {
+                    mesh_data.apply_points(&points);
+                }
+                mesh_data.skin = None;
+                mesh_data.blend_shapes.clear();
+                cache.rest_meshes.insert(path.clone(), rest);
+                deformed.insert(path.clone());
+            }
+            SceneDiffItem::MeshPointsDirtied(path, points) => {
+                let Some(rest) = cache.rest_meshes.get_mut(path) else {
+                    continue;
+                };
+                rest.apply_points(points);
+                if let Some(deformed_points) =
+                    deformed_points(cache, path, &cache.rest_meshes[path])
+                {
+                    *points = deformed_points;
+                }
+                deformed.insert(path.clone());
+            }
+            SceneDiffItem::MeshDestroyed(path) => {
+                cache.rest_meshes.remove(path);
+            }
+            _ => {}
+        }
+    }
+    for (path, rest) in &cache.rest_meshes {
+        let skeleton_dirtied = rest
+            .skin
+            .as_ref()
+            .is_some_and(|skin| dirtied_skeletons.contains(&skin.skeleton));
+        if deformed.contains(path) || !(dirtied_meshes.contains(path) || skeleton_dirtied) {
+            continue;
+        }
+        if let Some(points) = deformed_points(cache, path, rest) {
+            items.push(SceneDiffItem::MeshPointsDirtied(path.clone(), points));
+        }
+    }
+}
+
+impl SceneDiff {
+    // correctionはワールド座標系の補正のためにtransform matrixの左から掛ける行列
+    // cacheにはextractの間で持ち越す、差分だけからは作れない情報を記録する
+    fn new(
+        diff: bridge::UsdDataDiff,
+        correction: Mat4,
+        mesh_options: MeshOptions,
+        cache: &mut SceneCache,
+    ) -> Self {
+        let mut items = Vec::new();
+        let mut warnings = Vec::new();
+
+        // meshより先にSkeletonとskinningの束縛を処理し、meshを作るときに束縛を使えるようにする
+        let bridge::SkeletonsDiff {
+            update: skeletons,
+            destroy: destroyed_skeletons,
+            poses,
+            skin_bindings,
+            destroy_skin_bindings,
+            blend_shapes,
+            destroy_blend_shapes,
+            blend_shape_weights,
+        } = diff.skeletons;
+        update_skeletons(
+            &mut items,
+            &mut warnings,
+            skeletons,
+            destroyed_skeletons,
+            cache,
+        );
+        let dirtied_skeletons =
+            update_skeleton_poses(&mut items, &mut warnings, poses, correction, cache);
+        // 束縛やblend shapeが変わったmeshは、同じdiffでmesh全体の差分がなければ
+        // 記録しておいた情報から作り直す
+        let mut rebind = update_skin_bindings(skin_bindings, destroy_skin_bindings, cache);
+        rebind.extend(update_blend_shapes(
+            blend_shapes,
+            destroy_blend_shapes,
+            cache,
+        ));
+        rebind.retain(|path| {
+            !diff.meshes.create.contains_key(path) && !diff.meshes.diff_mesh_data.contains_key(path)
+        });
+        // transform matrixかblend shapeのweightが変わったmesh。CPU skinningで変形し直す
+        let mut dirtied_meshes = update_blend_shape_weights(&mut items, blend_shape_weights, cache);
+
+        let mesh_sources = &mut cache.mesh_sources;
+        let bindings = &cache.bindings;
+        let mesh_transforms = &mut cache.mesh_transforms;
+        let mut created_meshes = Vec::new();
+        for (path, data) in diff.meshes.create {
+            let transform_matrix = TransformMatrix {
+                matrix: correction
+                    * data
+                        .transform_matrix
+                        .map_or(Mat4::IDENTITY, |data| Mat4::from_cols_array(&data)),
+            };
+            let data = bridge::MeshDataDiff {
+                left_handed: data.left_handed,
+                points: data.points,
+                extent: data.extent,
+                normals: data.normals,
+                normals_indices: data.normals_indices,
+                normals_interpolation: data.normals_interpolation,
+                uvs: data.uvs,
+                uvs_indices: data.uvs_indices,
+                uvs_interpolation: data.uvs_interpolation,
+                primvars: data.primvars,
+                face_vertex_indices: data.face_vertex_indices,
+                face_vertex_counts: data.face_vertex_counts,
+                hole_indices: data.hole_indices,
+                subdivision_scheme: data.subdivision_scheme,
+                subdivision_tags: data.subdivision_tags,
+                refine_level: data.refine_level,
+                geom_subsets: data.geom_subsets,
+                material_path: data.material_path,
+            };
+            match mesh_data(&path, data, bindings, mesh_options, &mut warnings) {
+                Ok((mesh_data, source)) => {
+                    mesh_sources.insert(path.clone(), source);
+                    mesh_transforms.insert(path.clone(), transform_matrix);
+                    created_meshes.push(path.clone());
+                    items.push(SceneDiffItem::MeshCreated(
+                        path,
+                        transform_matrix,
+                        mesh_data,
+                    ))
+                }
+                Err(err) => warnings.push(err),
+            }
+        }
+        for path in diff.meshes.destroy {
+            mesh_sources.remove(&path);
+            mesh_transforms.remove(&path);
+            cache.mesh_instances.remove(&path);
+            items.push(SceneDiffItem::MeshDestroyed(path));
+        }
+        for (path, matrix) in diff.meshes.diff_transform_matrix {
+            let transform_matrix = TransformMatrix {
+                matrix: correction * Mat4::from_cols_array(&matrix),
+            };
+            mesh_transforms.insert(path.clone(), transform_matrix);
+            dirtied_meshes.insert(path.clone());
+            items.push(SceneDiffItem::MeshTransformMatrixDirtied(
+                path,
+                transform_matrix,
             ));
         }
+        for (path, data) in diff.meshes.diff_points {
+            // 同じmeshの全体の差分もある場合は、そちらでMeshDataを作り直す
+            if diff.meshes.diff_mesh_data.contains_key(&path) {
+                continue;
+            }
+            match mesh_points(
+                path,
+                data,
+                bindings,
+                mesh_options,
+                mesh_sources,
+                &mut warnings,
+            ) {
+                Ok(item) => items.push(item),
+                Err(err) => warnings.push(err),
+            }
+        }
+        for (path, data) in diff.meshes.diff_mesh_data {
+            match mesh_data(&path, data, bindings, mesh_options, &mut warnings) {
+                Ok((mesh_data, source)) => {
+                    mesh_sources.insert(path.clone(), source);
+                    items.push(SceneDiffItem::MeshDataDirtied(path, mesh_data))
+                }
+                Err(err) => {
+                    // 作れなかったmeshのpointsの差分は、古いtopologyでは扱えない
+                    mesh_sources.remove(&path);
+                    warnings.push(err)
+                }
+            }
+        }
+        rebind_meshes(&mut items, &mut warnings, rebind, mesh_options, cache);
+        if mesh_options.cpu_skinning {
+            cpu_skinning(&mut items, &dirtied_meshes, &dirtied_skeletons, cache);
+        }
+
+        // 作ったmeshと、変わったinstancerのprototypeに含まれるmeshのinstanceを作り直す。
+        // 作ったmeshは一つだけ描画する状態から始まるので、前回と同じinstanceでも送り直す
+        let dirtied_roots = update_instancers(&mut warnings, diff.instancers, cache);
+        for path in &created_meshes {
+            cache.mesh_instances.remove(path);
+        }
+        let mut instanced_meshes = created_meshes.into_iter().collect::<HashSet<_>>();
+        if !dirtied_roots.is_empty() {
+            instanced_meshes.extend(
+                cache
+                    .mesh_transforms
+                    .keys()
+                    .filter(|path| {
+                        ancestor_paths(path.as_str()).any(|path| dirtied_roots.contains(path))
+                    })
+                    .cloned(),
+            );
+        }
+        update_mesh_instances(&mut items, instanced_meshes, correction, cache);
+
+        for (path, data) in diff.sphere_lights.update {
+            let light = (|| {
+                Ok(SphereLight::new(
+                    correction
+                        * Mat4::from_cols_array(&required(
+                            &path,
+                            "transformMatrix",
+                            data.transform_matrix,
+                        )?),
+                    required(&path, "intensity", data.intensity)?,
+                    Vec3::from(required(&path, "color", data.color)?),
+                    data.cone_angle,
+                    data.cone_softness,
+                ))
+            })();
+            match light {
+                Ok(light) => items.push(SceneDiffItem::SphereLightAddOrUpdate(path, light)),
+                Err(err) => warnings.push(err),
+            }
+        }
+        for path in diff.sphere_lights.destroy {
+            items.push(SceneDiffItem::SphereLightDestroyed(path));
+        }
+
+        for (path, data) in diff.distant_lights.update {
+            let light = (|| {
+                Ok(DistantLight::new(
+                    correction
+                        * Mat4::from_cols_array(&required(
+                            &path,
+                            "transformMatrix",
+                            data.transform_matrix,
+                        )?),
+                    required(&path, "intensity", data.intensity)?,
+                    Vec3::from(required(&path, "color", data.color)?),
+                ))
+            })();
+            match light {
+                Ok(light) => items.push(SceneDiffItem::DistantLightAddOrUpdate(path, light)),
+                Err(err) => warnings.push(err),
+            }
+        }
         for path in diff.distant_lights.destroy {
             items.push(SceneDiffItem::DistantLightDestroyed(path));
         }
 
         for (path, data) in diff.cameras.update {
-            items.push(SceneDiffItem::CameraAddOrUpdate(
-                path,
-                Camera::new(
-                    Mat4::from_cols_array(&data.transform_matrix.unwrap()),
-                    data.focal_length.unwrap(),
-                    data.vertical_aperture.unwrap(),
-                ),
-            ));
+            let camera = (|| {
+                Ok(Camera::new(
+                    correction
+                        * Mat4::from_cols_array(&required(
+                            &path,
+                            "transformMatrix",
+                            data.transform_matrix,
+                        )?),
+                    required(&path, "focalLength", data.focal_length)?,
+                    required(&path, "verticalAperture", data.vertical_aperture)?,
+                ))
+            })();
+            match camera {
+                Ok(camera) => items.push(SceneDiffItem::CameraAddOrUpdate(path, camera)),
+                Err(err) => warnings.push(err),
+            }
         }
         for path in diff.cameras.destroy {
             items.push(SceneDiffItem::CameraDestroyed(path));
@@ -556,41 +2410,2284 @@ impl From<bridge::UsdDataDiff> for SceneDiff {
             items.push(SceneDiffItem::MaterialDestroyed(path));
         }
 
-        Self { items }
+        Self { items, warnings }
+    }
+}
+
+/// 編集APIでprimの属性に設定する値
+#[derive(Debug, Clone, PartialEq)]
+pub enum AttributeValue {
+    Bool(bool),
+    Int(i32),
+    IntArray(Vec<i32>),
+    Float(f32),
+    Float3([f32; 3]),
+    Color3f([f32; 3]),
+    FloatArray(Vec<f32>),
+    Point3fArray(Vec<[f32; 3]>),
+    Normal3fArray(Vec<[f32; 3]>),
+    TexCoord2fArray(Vec<[f32; 2]>),
+    Double(f64),
+    Double3([f64; 3]),
+    String(String),
+    Token(String),
+    Asset(String),
+}
+impl AttributeValue {
+    /// 属性が存在しない場合に作る属性のSdfValueTypeNameの名前
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            AttributeValue::Bool(_) => "bool",
+            AttributeValue::Int(_) => "int",
+            AttributeValue::IntArray(_) => "int[]",
+            AttributeValue::Float(_) => "float",
+            AttributeValue::Float3(_) => "float3",
+            AttributeValue::Color3f(_) => "color3f",
+            AttributeValue::FloatArray(_) => "float[]",
+            AttributeValue::Point3fArray(_) => "point3f[]",
+            AttributeValue::Normal3fArray(_) => "normal3f[]",
+            AttributeValue::TexCoord2fArray(_) => "texCoord2f[]",
+            AttributeValue::Double(_) => "double",
+            AttributeValue::Double3(_) => "double3",
+            AttributeValue::String(_) => "string",
+            AttributeValue::Token(_) => "token",
+            AttributeValue::Asset(_) => "asset",
+        }
+    }
+}
+
+/// USDファイルからstageを開く際のオプション
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StageOpenOptions {
+    /// stageに読み込むprimのパスのリスト。
+    /// 指定したprimとその祖先、子孫だけがstageに読み込まれる。
+    /// Noneの場合はstage全体を読み込む。
+    pub population_mask: Option<Vec<String>>,
+    /// stageを開く際にpayloadをloadするかどうか
+    pub load_payloads: bool,
+}
+impl Default for StageOpenOptions {
+    fn default() -> Self {
+        Self {
+            population_mask: None,
+            load_payloads: true,
+        }
+    }
+}
+
+/// stageのupAxis
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpAxis {
+    Y,
+    Z,
+}
+
+/// stageのlayer metadataの情報
+#[derive(Debug, Clone, PartialEq)]
+pub struct StageMetadata {
+    pub up_axis: UpAxis,
+    pub meters_per_unit: f64,
+    pub time_codes_per_second: f64,
+    pub frames_per_second: f64,
+    /// defaultPrimが設定されていればそのprimのパス
+    pub default_prim: Option<String>,
+}
+impl Default for StageMetadata {
+    // metadataがauthorされていない場合のOpenUSDのfallback値
+    fn default() -> Self {
+        Self {
+            up_axis: UpAxis::Y,
+            meters_per_unit: 0.01,
+            time_codes_per_second: 24.0,
+            frames_per_second: 24.0,
+            default_prim: None,
+        }
+    }
+}
+impl StageMetadata {
+    /// stageの座標系をY-upでメートル単位の座標系に変換する行列を返す。
+    pub fn y_up_meters_matrix(&self) -> Mat4 {
+        let scale = Mat4::from_scale(Vec3::splat(self.meters_per_unit as f32));
+        match self.up_axis {
+            UpAxis::Y => scale,
+            // +Zを+Y、+Yを-Zに移すようにX軸まわりに回転する
+            UpAxis::Z => scale * Mat4::from_rotation_x(-std::f32::consts::FRAC_PI_2),
+        }
+    }
+}
+
+/// primが持つvariant setの情報
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VariantSet {
+    /// variant setの名前
+    pub name: String,
+    /// 選択できるvariantの名前のリスト
+    pub variants: Vec<String>,
+    /// 現在選択されているvariantの名前
+    pub selection: Option<String>,
+}
+
+/// メモリ上のlayerから作ったstageのエラーに使うパス
+const IN_MEMORY_STAGE_PATH: &str = "<in-memory stage>";
+
+#[cfg(not(feature = "mock"))]
+fn edit_error(path: &str, e: cxx::Exception) -> Error {
+    Error::Edit {
+        path: path.to_string(),
+        message: String::from(e.what()),
+    }
+}
+
+#[cfg(not(feature = "mock"))]
+fn query_error(path: &str, e: cxx::Exception) -> Error {
+    Error::Query {
+        path: path.to_string(),
+        message: String::from(e.what()),
+    }
+}
+
+#[cfg(feature = "mock")]
+fn mock_unsupported_edit(path: &str, operation: &str) -> Error {
+    Error::Edit {
+        path: path.to_string(),
+        message: format!("{operation} is not supported with the mock backend"),
     }
 }
 
 pub struct UsdSceneExtractor {
+    #[cfg(not(feature = "mock"))]
     inner: cxx::UniquePtr<bridge::ffi::BridgeUsdDataExtractor>,
+    #[cfg(feature = "mock")]
+    inner: bridge::mock::MockUsdDataExtractor,
     start_time_code: f64,
     end_time_code: f64,
+    metadata: StageMetadata,
+    y_up_meters: bool,
+    mesh_options: MeshOptions,
+    cache: SceneCache,
 }
 impl UsdSceneExtractor {
-    pub fn new(path: impl AsRef<Path>) -> Result<Self, String> {
-        let path = path.as_ref().to_str().unwrap();
-        let inner = bridge::ffi::new_usd_data_extractor(path);
-        let inner = inner.map_err(|e| String::from(e.what()))?;
+    /// USDファイルからstage全体をpayloadもloadして開く。
+    pub fn new(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::open_with_options(path, &StageOpenOptions::default())
+    }
+
+    /// population maskやpayloadのloadを指定してUSDファイルからstageを開く。
+    #[cfg(not(feature = "mock"))]
+    pub fn open_with_options(
+        path: impl AsRef<Path>,
+        options: &StageOpenOptions,
+    ) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let path = path
+            .to_str()
+            .ok_or_else(|| Error::InvalidPath(path.to_path_buf()))?;
+        let inner = bridge::ffi::new_usd_data_extractor(
+            path,
+            options.population_mask.as_deref().unwrap_or_default(),
+            options.population_mask.is_some(),
+            options.load_payloads,
+        );
+        let inner = inner.map_err(|e| Error::OpenStage {
+            path: path.to_string(),
+            message: String::from(e.what()),
+        })?;
+        Ok(Self::from_inner(inner))
+    }
+
+    /// USDA形式の文字列をroot layerとするstageからシーンを抽出するExtractorを作る。
+    /// root layerはanonymous layerになるので、sublayerやreferenceの相対パスは
+    /// カレントディレクトリからの相対パスとして解決される。
+    #[cfg(not(feature = "mock"))]
+    pub fn from_usda_str(usda: &str) -> Result<Self, Error> {
+        let inner = bridge::ffi::new_usd_data_extractor_from_usda_str(usda);
+        let inner = inner.map_err(|e| Error::OpenStage {
+            path: IN_MEMORY_STAGE_PATH.to_string(),
+            message: String::from(e.what()),
+        })?;
+        Ok(Self::from_inner(inner))
+    }
+
+    /// identifierのlayerをそれぞれroot layerとsession layerとするstageから
+    /// シーンを抽出するExtractorを作る。
+    /// layerは`SdfLayer::FindOrOpen`で開くので、同じプロセスで既に開かれているlayerや
+    /// anonymous layerのidentifierを渡すと、そのlayerをそのまま使う。
+    /// session layerが`None`の場合は空のsession layerが使われる。
+    #[cfg(not(feature = "mock"))]
+    pub fn from_layers(root: &str, session: Option<&str>) -> Result<Self, Error> {
+        let inner = bridge::ffi::new_usd_data_extractor_from_layers(root, session.unwrap_or(""));
+        let inner = inner.map_err(|e| Error::OpenStage {
+            path: root.to_string(),
+            message: String::from(e.what()),
+        })?;
+        Ok(Self::from_inner(inner))
+    }
+
+    /// USDA形式の文字列をそれぞれroot layerとsession layerとするstageから
+    /// シーンを抽出するExtractorを作る。
+    /// session layerが`None`の場合は空のsession layerが使われる。
+    #[cfg(not(feature = "mock"))]
+    pub fn from_usda_layers(root: &str, session: Option<&str>) -> Result<Self, Error> {
+        let inner =
+            bridge::ffi::new_usd_data_extractor_from_usda_layers(root, session.unwrap_or(""));
+        let inner = inner.map_err(|e| Error::OpenStage {
+            path: IN_MEMORY_STAGE_PATH.to_string(),
+            message: String::from(e.what()),
+        })?;
+        Ok(Self::from_inner(inner))
+    }
+
+    #[cfg(not(feature = "mock"))]
+    fn from_inner(inner: cxx::UniquePtr<bridge::ffi::BridgeUsdDataExtractor>) -> Self {
         let start_time_code = inner.start_time_code();
         let end_time_code = inner.end_time_code();
-        Ok(Self {
+        let up_axis = match inner.up_axis().as_str() {
+            "Z" => UpAxis::Z,
+            _ => UpAxis::Y,
+        };
+        let default_prim = inner.default_prim();
+        let metadata = StageMetadata {
+            up_axis,
+            meters_per_unit: inner.meters_per_unit(),
+            time_codes_per_second: inner.time_codes_per_second(),
+            frames_per_second: inner.frames_per_second(),
+            default_prim: (!default_prim.is_empty()).then_some(default_prim),
+        };
+        Self {
             inner,
             start_time_code,
             end_time_code,
+            metadata,
+            y_up_meters: false,
+            mesh_options: MeshOptions::default(),
+            cache: SceneCache::default(),
+        }
+    }
+
+    /// mock backendではUSDファイルを開けないので常にエラーを返す。
+    /// 代わりに`from_mock_stage`を使う。
+    #[cfg(feature = "mock")]
+    pub fn open_with_options(
+        path: impl AsRef<Path>,
+        _options: &StageOpenOptions,
+    ) -> Result<Self, Error> {
+        Err(Error::OpenStage {
+            path: path.as_ref().display().to_string(),
+            message: "cannot open a USD file with the mock backend".to_string(),
         })
     }
 
-    pub fn time_code_range(&self) -> (f64, f64) {
-        (self.start_time_code, self.end_time_code)
+    /// mock backendではUSDAを読めないので常にエラーを返す。
+    #[cfg(feature = "mock")]
+    pub fn from_usda_str(_usda: &str) -> Result<Self, Error> {
+        Err(Error::OpenStage {
+            path: IN_MEMORY_STAGE_PATH.to_string(),
+            message: "cannot parse USDA with the mock backend".to_string(),
+        })
     }
 
-    pub fn extract(&mut self, time_code: f64) -> SceneDiff {
-        let inner = self.inner.pin_mut();
+    /// mock backendではlayerを開けないので常にエラーを返す。
+    #[cfg(feature = "mock")]
+    pub fn from_layers(root: &str, _session: Option<&str>) -> Result<Self, Error> {
+        Err(Error::OpenStage {
+            path: root.to_string(),
+            message: "cannot open layers with the mock backend".to_string(),
+        })
+    }
 
-        let mut usd_data_diff = bridge::UsdDataDiff::default();
-        let pin_usd_data_diff = std::pin::Pin::new(&mut usd_data_diff);
+    /// mock backendではUSDAを読めないので常にエラーを返す。
+    #[cfg(feature = "mock")]
+    pub fn from_usda_layers(_root: &str, _session: Option<&str>) -> Result<Self, Error> {
+        Err(Error::OpenStage {
+            path: IN_MEMORY_STAGE_PATH.to_string(),
+            message: "cannot create a stage from USDA layers with the mock backend".to_string(),
+        })
+    }
 
-        inner.extract(time_code, pin_usd_data_diff);
+    /// OpenUSDの代わりにメモリ上のmock stageからシーンを抽出するExtractorを作る。
+    #[cfg(feature = "mock")]
+    pub fn from_mock_stage(stage: mock::MockStage) -> Self {
+        let inner = bridge::mock::MockUsdDataExtractor::new(stage);
+        let start_time_code = inner.start_time_code();
+        let end_time_code = inner.end_time_code();
+        let metadata = inner.metadata().clone();
+        Self {
+            inner,
+            start_time_code,
+            end_time_code,
+            metadata,
+            y_up_meters: false,
+            mesh_options: MeshOptions::default(),
+            cache: SceneCache::default(),
+        }
+    }
+
+    /// extractで返すtransformを、stageのupAxisとmetersPerUnitによらず
+    /// Y-upでメートル単位の座標系に変換するかどうかを設定する。
+    /// 既に送ったprimのtransformは変換し直されないので、最初のextractの前に設定する。
+    pub fn with_y_up_meters(mut self, enabled: bool) -> Self {
+        self.y_up_meters = enabled;
+        self
+    }
 
-        usd_data_diff.into()
+    /// subdivisionSchemeがnoneでないmeshを細分割する回数を設定する。
+    /// primのdisplayStyleにrefineLevelが指定されている場合はそちらを優先する。
+    /// 0の場合は細分割せず、上限は8。
+    pub fn with_refine_level(mut self, level: u32) -> Self {
+        self.mesh_options.refine_level = level.min(subdivision::MAX_REFINE_LEVEL);
+        self
+    }
+
+    /// 法線が与えられていないmeshの法線を計算するときのcrease angleを度で設定する。
+    /// 辺を共有するfaceの法線のなす角がこれ未満の場合だけ法線を平均し、
+    /// それ以上の辺では頂点の法線を分ける。デフォルトは180度で、常に平均する。
+    pub fn with_crease_angle(mut self, degrees: f32) -> Self {
+        self.mesh_options.crease_angle = degrees;
+        self
+    }
+
+    /// MeshDataの同じ頂点データを持つ頂点をweldするかどうかを設定する。デフォルトは無効。
+    /// 有効にすると頂点バッファが小さくなり、sub meshのindexが実際に頂点を共有するようになる。
+    /// weld前後の頂点数はlogのdebugレベルで出力する。
+    pub fn with_vertex_welding(mut self, enabled: bool) -> Self {
+        self.mesh_options.weld = enabled;
+        self
+    }
+
+    /// UVがあるmeshのMeshDataにtangentを生成するかどうかを設定する。デフォルトは有効。
+    /// normal mappingを使わない場合は無効にすると、extractが速くなり頂点データも小さくなる。
+    pub fn with_tangents(mut self, enabled: bool) -> Self {
+        self.mesh_options.tangents = enabled;
+        self
+    }
+
+    /// Skeletonに束縛されたmeshに、CPUでblend shapeとポーズを適用してから返すかどうかを設定する。
+    /// デフォルトは無効。有効にするとMeshDataは変形を適用済みでskinとblend_shapesを持たず、
+    /// ポーズやblend shapeのweight、meshのtransform matrixが変わるたびに
+    /// MeshPointsDirtiedで頂点位置と法線を返す。
+    /// 無効の場合はMeshSkinとSkeletonPose、BlendShapeとweightを使ってGPUで変形する。
+    pub fn with_cpu_skinning(mut self, enabled: bool) -> Self {
+        self.mesh_options.cpu_skinning = enabled;
+        self
+    }
+
+    pub fn time_code_range(&self) -> (f64, f64) {
+        (self.start_time_code, self.end_time_code)
+    }
+
+    pub fn stage_metadata(&self) -> &StageMetadata {
+        &self.metadata
+    }
+
+    // extractで返すtransformに左から掛ける座標系の補正の行列
+    fn correction_matrix(&self) -> Mat4 {
+        if self.y_up_meters {
+            self.metadata.y_up_meters_matrix()
+        } else {
+            Mat4::IDENTITY
+        }
+    }
+
+    #[cfg(not(feature = "mock"))]
+    pub fn extract(&mut self, time_code: f64) -> SceneDiff {
+        let inner = self.inner.pin_mut();
+
+        let mut usd_data_diff = bridge::UsdDataDiff::default();
+        let pin_usd_data_diff = std::pin::Pin::new(&mut usd_data_diff);
+
+        inner.extract(time_code, pin_usd_data_diff);
+
+        SceneDiff::new(
+            usd_data_diff,
+            self.correction_matrix(),
+            self.mesh_options,
+            &mut self.cache,
+        )
+    }
+
+    /// primの属性の値をsession layerに設定する。
+    /// 属性が存在しない場合は`value`の型で作る。
+    #[cfg(not(feature = "mock"))]
+    pub fn set_attribute(
+        &mut self,
+        path: &str,
+        name: &str,
+        value: &AttributeValue,
+    ) -> Result<(), Error> {
+        let inner = self.inner.pin_mut();
+        let type_name = value.type_name();
+        let result = match value {
+            AttributeValue::Bool(value) => inner.set_attribute_bool(path, name, *value),
+            AttributeValue::Int(value) => inner.set_attribute_int(path, name, type_name, &[*value]),
+            AttributeValue::IntArray(values) => {
+                inner.set_attribute_int(path, name, type_name, values)
+            }
+            AttributeValue::Float(value) => {
+                inner.set_attribute_float(path, name, type_name, &[*value])
+            }
+            AttributeValue::Float3(value) | AttributeValue::Color3f(value) => {
+                inner.set_attribute_float(path, name, type_name, value)
+            }
+            AttributeValue::FloatArray(values) => {
+                inner.set_attribute_float(path, name, type_name, values)
+            }
+            AttributeValue::Point3fArray(values) | AttributeValue::Normal3fArray(values) => {
+                inner.set_attribute_float(path, name, type_name, values.as_flattened())
+            }
+            AttributeValue::TexCoord2fArray(values) => {
+                inner.set_attribute_float(path, name, type_name, values.as_flattened())
+            }
+            AttributeValue::Double(value) => {
+                inner.set_attribute_double(path, name, type_name, &[*value])
+            }
+            AttributeValue::Double3(value) => {
+                inner.set_attribute_double(path, name, type_name, value)
+            }
+            AttributeValue::String(value)
+            | AttributeValue::Token(value)
+            | AttributeValue::Asset(value) => {
+                inner.set_attribute_string(path, name, type_name, value)
+            }
+        };
+        result.map_err(|e| edit_error(path, e))
+    }
+
+    /// primのxformOpOrderを単一のtransform opに置き換えて、matrixをsession layerに設定する。
+    #[cfg(not(feature = "mock"))]
+    pub fn set_transform(&mut self, path: &str, matrix: Mat4) -> Result<(), Error> {
+        let inner = self.inner.pin_mut();
+        inner
+            .set_transform(path, &matrix.to_cols_array())
+            .map_err(|e| edit_error(path, e))
+    }
+
+    /// primのactiveをsession layerに設定する。
+    /// 非activeなprimとその子孫はシーンから削除されたものとしてdiffに現れる。
+    #[cfg(not(feature = "mock"))]
+    pub fn set_active(&mut self, path: &str, active: bool) -> Result<(), Error> {
+        let inner = self.inner.pin_mut();
+        inner
+            .set_active(path, active)
+            .map_err(|e| edit_error(path, e))
+    }
+
+    /// primのvisibilityをsession layerに設定する。
+    /// 非表示になったMeshは`MeshDestroyed`、再表示されたMeshは`MeshCreated`としてdiffに現れる。
+    #[cfg(not(feature = "mock"))]
+    pub fn set_visibility(&mut self, path: &str, visible: bool) -> Result<(), Error> {
+        let inner = self.inner.pin_mut();
+        inner
+            .set_visibility(path, visible)
+            .map_err(|e| edit_error(path, e))
+    }
+
+    /// primのvariant selectionをsession layerに設定する。
+    #[cfg(not(feature = "mock"))]
+    pub fn set_variant_selection(
+        &mut self,
+        path: &str,
+        variant_set: &str,
+        variant: &str,
+    ) -> Result<(), Error> {
+        let inner = self.inner.pin_mut();
+        inner
+            .set_variant_selection(path, variant_set, variant)
+            .map_err(|e| edit_error(path, e))
+    }
+
+    /// pathとその子孫のpayloadをloadする。
+    /// loadされてシーンに現れたprimは次のextractで追加のdiffとして通知される。
+    #[cfg(not(feature = "mock"))]
+    pub fn load_payloads(&mut self, path: &str) -> Result<(), Error> {
+        let inner = self.inner.pin_mut();
+        inner.load_payloads(path).map_err(|e| edit_error(path, e))
+    }
+
+    /// pathとその子孫のpayloadをunloadする。
+    /// unloadされてシーンから消えたprimは次のextractで削除のdiffとして通知される。
+    #[cfg(not(feature = "mock"))]
+    pub fn unload_payloads(&mut self, path: &str) -> Result<(), Error> {
+        let inner = self.inner.pin_mut();
+        inner.unload_payloads(path).map_err(|e| edit_error(path, e))
+    }
+
+    /// stageのpopulation maskを変更する。Noneの場合はstage全体を読み込む。
+    /// maskの変更でシーンに現れた/消えたprimは次のextractでdiffとして通知される。
+    #[cfg(not(feature = "mock"))]
+    pub fn set_population_mask(&mut self, population_mask: Option<&[String]>) -> Result<(), Error> {
+        let inner = self.inner.pin_mut();
+        inner
+            .set_population_mask(
+                population_mask.unwrap_or_default(),
+                population_mask.is_some(),
+            )
+            .map_err(|e| Error::Edit {
+                path: "population mask".to_string(),
+                message: String::from(e.what()),
+            })
+    }
+
+    /// stage上でvariant setを持つprimのパスのリストを返す。
+    #[cfg(not(feature = "mock"))]
+    pub fn variant_set_prim_paths(&self) -> Vec<String> {
+        self.inner.variant_set_prim_paths()
+    }
+
+    /// primが持つvariant setの一覧を、選択できるvariantと現在の選択とともに返す。
+    #[cfg(not(feature = "mock"))]
+    pub fn variant_sets(&self, path: &str) -> Result<Vec<VariantSet>, Error> {
+        let names = self
+            .inner
+            .variant_set_names(path)
+            .map_err(|e| query_error(path, e))?;
+        let mut variant_sets = Vec::with_capacity(names.len());
+        for name in names {
+            let variants = self
+                .inner
+                .variant_names(path, &name)
+                .map_err(|e| query_error(path, e))?;
+            let selection = self
+                .inner
+                .variant_selection(path, &name)
+                .map_err(|e| query_error(path, e))?;
+            variant_sets.push(VariantSet {
+                name,
+                variants,
+                selection: (!selection.is_empty()).then_some(selection),
+            });
+        }
+        Ok(variant_sets)
+    }
+
+    /// `type_name`のprimをsession layerに定義する。
+    #[cfg(not(feature = "mock"))]
+    pub fn define_prim(&mut self, path: &str, type_name: &str) -> Result<(), Error> {
+        let inner = self.inner.pin_mut();
+        inner
+            .define_prim(path, type_name)
+            .map_err(|e| edit_error(path, e))
+    }
+
+    /// primをシーンから削除する。
+    /// specは削除せずにprimを非activeにするので、`set_active`で元に戻せる。
+    #[cfg(not(feature = "mock"))]
+    pub fn remove_prim(&mut self, path: &str) -> Result<(), Error> {
+        let inner = self.inner.pin_mut();
+        inner.remove_prim(path).map_err(|e| edit_error(path, e))
+    }
+
+    #[cfg(feature = "mock")]
+    pub fn extract(&mut self, time_code: f64) -> SceneDiff {
+        let mut usd_data_diff = bridge::UsdDataDiff::default();
+
+        self.inner.extract(time_code, &mut usd_data_diff);
+
+        SceneDiff::new(
+            usd_data_diff,
+            self.correction_matrix(),
+            self.mesh_options,
+            &mut self.cache,
+        )
+    }
+
+    /// mock backendでは、mock stageのprimが持っている属性だけを編集できる。
+    #[cfg(feature = "mock")]
+    pub fn set_attribute(
+        &mut self,
+        path: &str,
+        name: &str,
+        value: &AttributeValue,
+    ) -> Result<(), Error> {
+        self.inner
+            .set_attribute(path, name, value)
+            .map_err(|message| Error::Edit {
+                path: path.to_string(),
+                message,
+            })
+    }
+
+    #[cfg(feature = "mock")]
+    pub fn set_transform(&mut self, path: &str, matrix: Mat4) -> Result<(), Error> {
+        self.inner.set_transform(path, matrix.to_cols_array());
+        Ok(())
+    }
+
+    #[cfg(feature = "mock")]
+    pub fn set_active(&mut self, path: &str, active: bool) -> Result<(), Error> {
+        self.inner.set_active(path, active);
+        Ok(())
+    }
+
+    #[cfg(feature = "mock")]
+    pub fn set_visibility(&mut self, path: &str, visible: bool) -> Result<(), Error> {
+        self.inner.set_visibility(path, visible);
+        Ok(())
+    }
+
+    /// mock stageにはvariantがないので常にエラーを返す。
+    #[cfg(feature = "mock")]
+    pub fn set_variant_selection(
+        &mut self,
+        path: &str,
+        _variant_set: &str,
+        _variant: &str,
+    ) -> Result<(), Error> {
+        Err(mock_unsupported_edit(path, "set_variant_selection"))
+    }
+
+    /// mock stageにはpayloadがないので何もしない。
+    #[cfg(feature = "mock")]
+    pub fn load_payloads(&mut self, _path: &str) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// mock stageにはpayloadがないので何もしない。
+    #[cfg(feature = "mock")]
+    pub fn unload_payloads(&mut self, _path: &str) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[cfg(feature = "mock")]
+    pub fn set_population_mask(&mut self, population_mask: Option<&[String]>) -> Result<(), Error> {
+        self.inner
+            .set_population_mask(population_mask.map(|mask| mask.to_vec()));
+        Ok(())
+    }
+
+    /// mock stageにはvariantがないので常に空のリストを返す。
+    #[cfg(feature = "mock")]
+    pub fn variant_set_prim_paths(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// mock stageにはvariantがないので常に空のリストを返す。
+    #[cfg(feature = "mock")]
+    pub fn variant_sets(&self, _path: &str) -> Result<Vec<VariantSet>, Error> {
+        Ok(Vec::new())
+    }
+
+    /// mock backendでは、mock stageのprimの種類とXform、Scopeだけを定義できる。
+    #[cfg(feature = "mock")]
+    pub fn define_prim(&mut self, path: &str, type_name: &str) -> Result<(), Error> {
+        self.inner
+            .define_prim(path, type_name)
+            .map_err(|message| Error::Edit {
+                path: path.to_string(),
+                message,
+            })
+    }
+
+    #[cfg(feature = "mock")]
+    pub fn remove_prim(&mut self, path: &str) -> Result<(), Error> {
+        self.inner.set_active(path, false);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 1x1の四角形1枚のpoints
+    const QUAD_POINTS: [f32; 12] = [
+        0.0, 0.0, 0.0, //
+        1.0, 0.0, 0.0, //
+        1.0, 1.0, 0.0, //
+        0.0, 1.0, 0.0, //
+    ];
+
+    // 四角形と五角形が1つの辺を共有するメッシュ
+    const NGON_POINTS: [f32; 21] = [
+        0.0, 0.0, 0.0, //
+        1.0, 0.0, 0.0, //
+        1.0, 1.0, 0.0, //
+        0.0, 1.0, 0.0, //
+        2.0, 0.0, 0.0, //
+        2.5, 1.0, 0.0, //
+        2.0, 2.0, 0.0, //
+    ];
+    const NGON_INDICES: [u32; 9] = [0, 1, 2, 3, 1, 4, 5, 6, 2];
+    const NGON_COUNTS: [u32; 2] = [4, 5];
+
+    // primvarの値、indices、Interpolationの組
+    type PrimvarInput = (Vec<f32>, Option<Vec<u32>>, Interpolation);
+
+    fn unzip_primvar(
+        primvar: Option<PrimvarInput>,
+    ) -> (Option<Vec<f32>>, Option<Vec<u32>>, Option<Interpolation>) {
+        match primvar {
+            Some((values, indices, interpolation)) => (Some(values), indices, Some(interpolation)),
+            None => (None, None, None),
+        }
+    }
+
+    // MeshData::newの入力。テストで使わないフィールドはDefaultのままにする
+    #[derive(Default)]
+    struct MeshInput<'a> {
+        left_handed: bool,
+        points: &'a [f32],
+        normals: Option<PrimvarInput>,
+        uvs: Option<PrimvarInput>,
+        primvars: HashMap<String, bridge::PrimvarData>,
+        face_vertex_indices: &'a [u32],
+        face_vertex_counts: &'a [u32],
+        hole_indices: Option<Vec<u32>>,
+        geom_subsets: HashMap<String, SubMeshData>,
+        material_path: Option<String>,
+        options: MeshOptions,
+    }
+    impl MeshInput<'_> {
+        fn build(self) -> Result<MeshData, String> {
+            let (normals, normals_indices, normals_interpolation) = unzip_primvar(self.normals);
+            let (uvs, uvs_indices, uvs_interpolation) = unzip_primvar(self.uvs);
+            MeshData::new(
+                self.left_handed,
+                self.points,
+                normals,
+                normals_indices,
+                normals_interpolation,
+                uvs,
+                uvs_indices,
+                uvs_interpolation,
+                self.primvars,
+                self.face_vertex_indices,
+                self.face_vertex_counts,
+                self.hole_indices,
+                self.geom_subsets,
+                self.material_path,
+                self.options,
+            )
+        }
+    }
+
+    fn normals(mesh: &MeshData) -> Vec<Vec3> {
+        mesh.vertices.iter().map(|v| v.normal).collect()
+    }
+
+    fn uvs(mesh: &MeshData) -> Vec<Vec2> {
+        mesh.vertices.iter().map(|v| v.uv).collect()
+    }
+
+    #[test]
+    fn constant_interpolation_on_quad() {
+        let normals_data = vec![0.0, 0.0, -1.0];
+        let uvs_data = vec![0.25, 0.75];
+        let mesh = MeshInput {
+            points: &QUAD_POINTS,
+            face_vertex_indices: &[0, 1, 2, 3],
+            face_vertex_counts: &[4],
+            normals: Some((normals_data, None, Interpolation::Constant)),
+            uvs: Some((uvs_data, Some(vec![0]), Interpolation::Constant)),
+            ..Default::default()
+        }
+        .build()
+        .unwrap();
+        assert_eq!(normals(&mesh), vec![Vec3::new(0.0, 0.0, -1.0); 4]);
+        assert_eq!(uvs(&mesh), vec![Vec2::new(0.25, 0.25); 4]);
+    }
+
+    #[test]
+    fn uniform_interpolation_on_ngons() {
+        let normals_data = vec![0.0, 0.0, 1.0, 0.0, 1.0, 0.0];
+        let uvs_data = vec![0.0, 0.0, 1.0, 1.0];
+        let mesh = MeshInput {
+            points: &NGON_POINTS,
+            face_vertex_indices: &NGON_INDICES,
+            face_vertex_counts: &NGON_COUNTS,
+            normals: Some((normals_data, None, Interpolation::Uniform)),
+            uvs: Some((uvs_data, Some(vec![1, 0]), Interpolation::Uniform)),
+            ..Default::default()
+        }
+        .build()
+        .unwrap();
+        let mut expected_normals = vec![Vec3::Z; 4];
+        expected_normals.extend([Vec3::Y; 5]);
+        assert_eq!(normals(&mesh), expected_normals);
+        let mut expected_uvs = vec![Vec2::new(1.0, 0.0); 4];
+        expected_uvs.extend([Vec2::new(0.0, 1.0); 5]);
+        assert_eq!(uvs(&mesh), expected_uvs);
+    }
+
+    #[test]
+    fn vertex_and_varying_interpolation_on_ngons() {
+        for interpolation in [Interpolation::Vertex, Interpolation::Varying] {
+            let normals_data = (0..7).flat_map(|i| [i as f32, 0.0, 1.0]).collect();
+            let uvs_data = (0..7).flat_map(|i| [i as f32, 0.0]).collect();
+            let uvs_indices = (0..7).rev().collect();
+            let mesh = MeshInput {
+                points: &NGON_POINTS,
+                face_vertex_indices: &NGON_INDICES,
+                face_vertex_counts: &NGON_COUNTS,
+                normals: Some((normals_data, None, interpolation)),
+                uvs: Some((uvs_data, Some(uvs_indices), interpolation)),
+                ..Default::default()
+            }
+            .build()
+            .unwrap();
+            let expected_normals = NGON_INDICES
+                .iter()
+                .map(|&i| Vec3::new(i as f32, 0.0, 1.0))
+                .collect::<Vec<_>>();
+            assert_eq!(normals(&mesh), expected_normals);
+            let expected_uvs = NGON_INDICES
+                .iter()
+                .map(|&i| Vec2::new((6 - i) as f32, 1.0))
+                .collect::<Vec<_>>();
+            assert_eq!(uvs(&mesh), expected_uvs);
+        }
+    }
+
+    #[test]
+    fn face_varying_interpolation_on_ngons() {
+        let normals_data = (0..9).flat_map(|i| [0.0, i as f32, 1.0]).collect();
+        let uvs_data = vec![0.0, 0.0, 0.5, 0.5];
+        let uvs_indices = (0..9).map(|i| i % 2).collect();
+        let mesh = MeshInput {
+            points: &NGON_POINTS,
+            face_vertex_indices: &NGON_INDICES,
+            face_vertex_counts: &NGON_COUNTS,
+            normals: Some((normals_data, None, Interpolation::FaceVarying)),
+            uvs: Some((uvs_data, Some(uvs_indices), Interpolation::FaceVarying)),
+            ..Default::default()
+        }
+        .build()
+        .unwrap();
+        let expected_normals = (0..9)
+            .map(|i| Vec3::new(0.0, i as f32, 1.0))
+            .collect::<Vec<_>>();
+        assert_eq!(normals(&mesh), expected_normals);
+        let expected_uvs = (0..9)
+            .map(|i| match i % 2 {
+                0 => Vec2::new(0.0, 1.0),
+                _ => Vec2::new(0.5, 0.5),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(uvs(&mesh), expected_uvs);
+    }
+
+    #[test]
+    fn too_short_primvar_is_rejected() {
+        // Uniformは面の数だけ値が必要
+        let result = MeshInput {
+            points: &NGON_POINTS,
+            face_vertex_indices: &NGON_INDICES,
+            face_vertex_counts: &NGON_COUNTS,
+            normals: Some((vec![0.0, 0.0, 1.0], None, Interpolation::Uniform)),
+            ..Default::default()
+        }
+        .build();
+        assert!(result.is_err());
+        let result = MeshInput {
+            points: &NGON_POINTS,
+            face_vertex_indices: &NGON_INDICES,
+            face_vertex_counts: &NGON_COUNTS,
+            uvs: Some((vec![0.0, 0.0], Some(vec![0; 6]), Interpolation::Vertex)),
+            ..Default::default()
+        }
+        .build();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn missing_normals_are_computed() {
+        let mesh = MeshInput {
+            points: &QUAD_POINTS,
+            face_vertex_indices: &[0, 1, 2, 3],
+            face_vertex_counts: &[4],
+            ..Default::default()
+        }
+        .build()
+        .unwrap();
+        assert_eq!(normals(&mesh), vec![Vec3::Z; 4]);
+        assert_eq!(uvs(&mesh), vec![Vec2::ZERO; 4]);
+    }
+
+    #[test]
+    fn indexed_normals_are_resolved() {
+        // 四角形と五角形でそれぞれ1つの法線を共有するfacetedな法線
+        let normals_data = vec![0.0, 0.0, 1.0, 0.0, 1.0, 0.0];
+        let normals_indices = vec![0, 0, 0, 0, 1, 1, 1, 1, 1];
+        let face_varying = MeshInput {
+            points: &NGON_POINTS,
+            face_vertex_indices: &NGON_INDICES,
+            face_vertex_counts: &NGON_COUNTS,
+            normals: Some((
+                normals_data.clone(),
+                Some(normals_indices),
+                Interpolation::FaceVarying,
+            )),
+            ..Default::default()
+        }
+        .build()
+        .unwrap();
+        let mut expected_normals = vec![Vec3::Z; 4];
+        expected_normals.extend([Vec3::Y; 5]);
+        assert_eq!(normals(&face_varying), expected_normals);
+
+        // Uniformでもindicesは面ごとの値を引く
+        let uniform = MeshInput {
+            points: &NGON_POINTS,
+            face_vertex_indices: &NGON_INDICES,
+            face_vertex_counts: &NGON_COUNTS,
+            normals: Some((normals_data, Some(vec![1, 0]), Interpolation::Uniform)),
+            ..Default::default()
+        }
+        .build()
+        .unwrap();
+        let mut expected_normals = vec![Vec3::Y; 4];
+        expected_normals.extend([Vec3::Z; 5]);
+        assert_eq!(normals(&uniform), expected_normals);
+    }
+
+    #[test]
+    fn unindexed_uvs_are_used_directly() {
+        let uvs_data = vec![0.0, 0.0, 1.0, 0.0, 1.0, 1.0, 0.0, 1.0];
+        let mesh = MeshInput {
+            points: &QUAD_POINTS,
+            face_vertex_indices: &[0, 1, 2, 3],
+            face_vertex_counts: &[4],
+            uvs: Some((uvs_data, None, Interpolation::Vertex)),
+            ..Default::default()
+        }
+        .build()
+        .unwrap();
+        let expected_uvs = vec![
+            Vec2::new(0.0, 1.0),
+            Vec2::new(1.0, 1.0),
+            Vec2::new(1.0, 0.0),
+            Vec2::new(0.0, 0.0),
+        ];
+        assert_eq!(uvs(&mesh), expected_uvs);
+    }
+
+    #[test]
+    fn out_of_range_normals_indices_are_rejected() {
+        let result = MeshInput {
+            points: &QUAD_POINTS,
+            face_vertex_indices: &[0, 1, 2, 3],
+            face_vertex_counts: &[4],
+            normals: Some((
+                vec![0.0, 0.0, 1.0],
+                Some(vec![0, 0, 0, 1]),
+                Interpolation::FaceVarying,
+            )),
+            ..Default::default()
+        }
+        .build();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn generic_primvars_are_expanded() {
+        let mut primvars = HashMap::new();
+        primvars.insert(
+            "displayColor".to_string(),
+            bridge::PrimvarData {
+                element_type: PrimvarType::Float3,
+                interpolation: Interpolation::Uniform,
+                values: vec![1.0, 0.0, 0.0, 0.0, 0.0, 1.0],
+                indices: None,
+            },
+        );
+        primvars.insert(
+            "displayOpacity".to_string(),
+            bridge::PrimvarData {
+                element_type: PrimvarType::Float,
+                interpolation: Interpolation::Constant,
+                values: vec![0.5],
+                indices: None,
+            },
+        );
+        primvars.insert(
+            "st1".to_string(),
+            bridge::PrimvarData {
+                element_type: PrimvarType::Float2,
+                interpolation: Interpolation::FaceVarying,
+                values: vec![0.0, 0.0, 1.0, 1.0],
+                indices: Some((0..9).map(|i| i % 2).collect()),
+            },
+        );
+        let mesh = MeshInput {
+            points: &NGON_POINTS,
+            face_vertex_indices: &NGON_INDICES,
+            face_vertex_counts: &NGON_COUNTS,
+            primvars,
+            ..Default::default()
+        }
+        .build()
+        .unwrap();
+
+        let display_color = &mesh.primvars["displayColor"];
+        assert_eq!(display_color.element_type, PrimvarType::Float3);
+        let mut expected = [1.0, 0.0, 0.0].repeat(4);
+        expected.extend([0.0, 0.0, 1.0].repeat(5));
+        assert_eq!(display_color.values, expected);
+
+        assert_eq!(mesh.primvars["displayOpacity"].values, vec![0.5; 9]);
+
+        let expected = (0..9)
+            .flat_map(|i| match i % 2 {
+                0 => [0.0, 0.0],
+                _ => [1.0, 1.0],
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(mesh.primvars["st1"].values, expected);
+    }
+
+    #[test]
+    fn malformed_generic_primvar_is_rejected() {
+        let mut primvars = HashMap::new();
+        primvars.insert(
+            "displayColor".to_string(),
+            bridge::PrimvarData {
+                element_type: PrimvarType::Float3,
+                interpolation: Interpolation::Vertex,
+                values: vec![1.0, 0.0],
+                indices: None,
+            },
+        );
+        let result = MeshInput {
+            points: &QUAD_POINTS,
+            face_vertex_indices: &[0, 1, 2, 3],
+            face_vertex_counts: &[4],
+            primvars,
+            ..Default::default()
+        }
+        .build();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn hole_faces_are_excluded_from_sub_meshes() {
+        // 四角形を3つ並べ、1つ目と2つ目をgeomSubsetに入れて2つ目と3つ目を穴にする
+        let points = (0..8)
+            .flat_map(|i| [(i / 2) as f32, (i % 2) as f32, 0.0])
+            .collect::<Vec<_>>();
+        let indices = [0, 2, 3, 1, 2, 4, 5, 3, 4, 6, 7, 5];
+        let mut geom_subsets = HashMap::new();
+        geom_subsets.insert(
+            "subset".to_string(),
+            SubMeshData {
+                indices_type: "typeFaceSet".to_string(),
+                indices: vec![0, 1],
+                material_path: None,
+            },
+        );
+        let mesh = MeshInput {
+            points: &points,
+            face_vertex_indices: &indices,
+            face_vertex_counts: &[4, 4, 4],
+            hole_indices: Some(vec![1, 2]),
+            geom_subsets,
+            ..Default::default()
+        }
+        .build()
+        .unwrap();
+        // geomSubsetには穴でない1つ目の四角形だけが残り、
+        // geomSubsetに含まれないfaceは全て穴なので残りのsub meshは作られない
+        assert_eq!(mesh.sub_meshes.len(), 1);
+        assert_eq!(
+            mesh.sub_meshes[0].indices,
+            SubMeshIndices::U32(vec![0, 1, 2, 0, 2, 3])
+        );
+
+        let result = MeshInput {
+            points: &points,
+            face_vertex_indices: &indices,
+            face_vertex_counts: &[4, 4, 4],
+            hole_indices: Some(vec![3]),
+            ..Default::default()
+        }
+        .build();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn geom_subsets_partition_faces() {
+        // 四角形を3つ並べ、重複や範囲外のindexを含むgeomSubsetと、
+        // 1つ目の四角形を共有するgeomSubsetを作る
+        let points = (0..8)
+            .flat_map(|i| [(i / 2) as f32, (i % 2) as f32, 0.0])
+            .collect::<Vec<_>>();
+        let indices = [0, 2, 3, 1, 2, 4, 5, 3, 4, 6, 7, 5];
+        let mut geom_subsets = HashMap::new();
+        geom_subsets.insert(
+            "a".to_string(),
+            SubMeshData {
+                indices_type: "typeFaceSet".to_string(),
+                indices: vec![1, 7, 0, 1],
+                material_path: Some("/a".to_string()),
+            },
+        );
+        geom_subsets.insert(
+            "b".to_string(),
+            SubMeshData {
+                indices_type: "typeFaceSet".to_string(),
+                indices: vec![0],
+                material_path: Some("/b".to_string()),
+            },
+        );
+        let mesh = MeshInput {
+            points: &points,
+            face_vertex_indices: &indices,
+            face_vertex_counts: &[4, 4, 4],
+            geom_subsets,
+            material_path: Some("/base".to_string()),
+            ..Default::default()
+        }
+        .build()
+        .unwrap();
+
+        let sub_mesh = |material: &str| {
+            let sub_mesh = mesh
+                .sub_meshes
+                .iter()
+                .find(|sub_mesh| sub_mesh.material.as_deref() == Some(material))
+                .unwrap();
+            sub_mesh.indices.to_u32()
+        };
+        // 重複と範囲外のindexは無視され、geomSubsetのindexの順に並ぶ
+        assert_eq!(sub_mesh("/a"), vec![4, 5, 6, 4, 6, 7, 0, 1, 2, 0, 2, 3]);
+        assert_eq!(sub_mesh("/b"), vec![0, 1, 2, 0, 2, 3]);
+        // どのgeomSubsetにも含まれない3つ目の四角形だけがmeshのmaterialのsub meshになる
+        assert_eq!(sub_mesh("/base"), vec![8, 9, 10, 8, 10, 11]);
+        assert_eq!(mesh.sub_meshes.len(), 3);
+    }
+
+    #[test]
+    fn tangents_are_generated_only_with_uvs() {
+        let uvs_data = vec![0.0, 0.0, 1.0, 0.0, 1.0, 1.0, 0.0, 1.0];
+        let with_uvs = MeshInput {
+            points: &QUAD_POINTS,
+            face_vertex_indices: &[0, 1, 2, 3],
+            face_vertex_counts: &[4],
+            uvs: Some((uvs_data, None, Interpolation::Vertex)),
+            ..Default::default()
+        }
+        .build()
+        .unwrap();
+        let tangents = with_uvs.tangents.unwrap();
+        assert_eq!(tangents.len(), with_uvs.vertices.len());
+        for tangent in tangents {
+            assert!((tangent.truncate().length() - 1.0).abs() < 1e-5);
+            assert_eq!(tangent.w.abs(), 1.0);
+        }
+
+        let without_uvs = MeshInput {
+            points: &QUAD_POINTS,
+            face_vertex_indices: &[0, 1, 2, 3],
+            face_vertex_counts: &[4],
+            ..Default::default()
+        }
+        .build()
+        .unwrap();
+        assert!(without_uvs.tangents.is_none());
+    }
+
+    // 三角形の頂点のpositionの列。weldの前後で同じ三角形が描かれることの確認に使う
+    fn triangle_positions(mesh: &MeshData) -> Vec<Vec3> {
+        mesh.sub_meshes
+            .iter()
+            .flat_map(|sub_mesh| sub_mesh.indices.to_u32())
+            .map(|index| mesh.vertices[index as usize].position)
+            .collect()
+    }
+
+    #[test]
+    fn welding_merges_identical_vertices() {
+        let normals_data = [0.0, 0.0, 1.0].repeat(7);
+        let mut mesh = MeshInput {
+            points: &NGON_POINTS,
+            face_vertex_indices: &NGON_INDICES,
+            face_vertex_counts: &NGON_COUNTS,
+            normals: Some((normals_data.clone(), None, Interpolation::Vertex)),
+            ..Default::default()
+        }
+        .build()
+        .unwrap();
+        assert_eq!(mesh.vertices.len(), 9);
+        let positions = triangle_positions(&mesh);
+        mesh.weld(None);
+        // 2つのfaceが共有する辺の頂点がまとまり、pointsの数まで減る
+        assert_eq!(mesh.vertices.len(), 7);
+        assert!(matches!(mesh.sub_meshes[0].indices, SubMeshIndices::U16(_)));
+        assert_eq!(triangle_positions(&mesh), positions);
+
+        // faceごとに値の違うprimvarがあると共有する辺の頂点はまとめられない
+        let mut primvars = HashMap::new();
+        primvars.insert(
+            "displayColor".to_string(),
+            bridge::PrimvarData {
+                element_type: PrimvarType::Float3,
+                interpolation: Interpolation::Uniform,
+                values: vec![1.0, 0.0, 0.0, 0.0, 0.0, 1.0],
+                indices: None,
+            },
+        );
+        let mut mesh = MeshInput {
+            points: &NGON_POINTS,
+            face_vertex_indices: &NGON_INDICES,
+            face_vertex_counts: &NGON_COUNTS,
+            normals: Some((normals_data, None, Interpolation::Vertex)),
+            primvars,
+            ..Default::default()
+        }
+        .build()
+        .unwrap();
+        let positions = triangle_positions(&mesh);
+        mesh.weld(None);
+        assert_eq!(mesh.vertices.len(), 9);
+        assert_eq!(mesh.primvars["displayColor"].values.len(), 9 * 3);
+        assert_eq!(triangle_positions(&mesh), positions);
+    }
+
+    #[test]
+    fn deformed_points_match_rebuilt_mesh() {
+        let path = SdfPath::default();
+        let data = |points: &[f32]| bridge::MeshDataDiff {
+            points: Some(points.to_vec()),
+            uvs: Some(NGON_POINTS.chunks(3).flat_map(|p| [p[0], p[1]]).collect()),
+            uvs_interpolation: Some(Interpolation::Vertex),
+            face_vertex_indices: Some(NGON_INDICES.to_vec()),
+            face_vertex_counts: Some(NGON_COUNTS.to_vec()),
+            ..Default::default()
+        };
+        let deform = |options: MeshOptions, points: &[f32]| {
+            let (_, source) = mesh_data(
+                &path,
+                data(&NGON_POINTS),
+                &MeshBindings::default(),
+                options,
+                &mut Vec::new(),
+            )
+            .unwrap();
+            let mut mesh_sources = HashMap::from([(SdfPath::default(), source)]);
+            let diff = bridge::MeshPointsDiff {
+                points: points.to_vec(),
+                ..Default::default()
+            };
+            mesh_points(
+                SdfPath::default(),
+                diff,
+                &MeshBindings::default(),
+                options,
+                &mut mesh_sources,
+                &mut Vec::new(),
+            )
+            .unwrap()
+        };
+        let assert_rebuilt = |options: MeshOptions, points: &[f32], deformed: &MeshPoints| {
+            let (expected, _) = mesh_data(
+                &path,
+                data(points),
+                &MeshBindings::default(),
+                options,
+                &mut Vec::new(),
+            )
+            .unwrap();
+            assert_eq!(deformed.positions.len(), expected.vertices.len());
+            assert_eq!(deformed.extent, expected.extent);
+            for (i, vertex) in expected.vertices.iter().enumerate() {
+                assert_eq!(deformed.positions[i], vertex.position);
+                assert!(deformed.normals[i].abs_diff_eq(vertex.normal, 1e-5));
+            }
+            let tangents = deformed.tangents.as_ref().unwrap();
+            for (tangent, expected) in tangents.iter().zip(&expected.tangents.unwrap()) {
+                assert!(tangent.abs_diff_eq(*expected, 1e-5));
+            }
+        };
+
+        // 全体を持ち上げても法線は変わらない
+        let lifted = NGON_POINTS
+            .iter()
+            .enumerate()
+            .map(|(i, &value)| if i % 3 == 2 { value + 1.0 } else { value })
+            .collect::<Vec<_>>();
+        // 五角形の点を持ち上げて、共有する辺で折れ曲がるようにする
+        let mut creased = NGON_POINTS.to_vec();
+        for z in [14, 17, 20] {
+            creased[z] = 1.0;
+        }
+
+        for weld in [false, true] {
+            let options = MeshOptions {
+                weld,
+                crease_angle: 30.0,
+                ..Default::default()
+            };
+            let SceneDiffItem::MeshPointsDirtied(_, deformed) = deform(options, &lifted) else {
+                panic!("expected MeshPointsDirtied");
+            };
+            assert_rebuilt(options, &lifted, &deformed);
+        }
+
+        let options = MeshOptions {
+            crease_angle: 30.0,
+            ..Default::default()
+        };
+        let SceneDiffItem::MeshPointsDirtied(_, deformed) = deform(options, &creased) else {
+            panic!("expected MeshPointsDirtied");
+        };
+        assert_rebuilt(options, &creased, &deformed);
+
+        // weldした頂点の法線が折れ目で分かれるので、weldからやり直す
+        let options = MeshOptions {
+            weld: true,
+            ..options
+        };
+        let SceneDiffItem::MeshDataDirtied(_, mesh) = deform(options, &creased) else {
+            panic!("expected MeshDataDirtied");
+        };
+        assert_eq!(mesh.vertices.len(), 9);
+    }
+
+    #[test]
+    fn points_count_change_rebuilds_mesh() {
+        let path = SdfPath::default();
+        let data = bridge::MeshDataDiff {
+            points: Some(NGON_POINTS.to_vec()),
+            face_vertex_indices: Some(NGON_INDICES.to_vec()),
+            face_vertex_counts: Some(NGON_COUNTS.to_vec()),
+            ..Default::default()
+        };
+        let options = MeshOptions::default();
+        let (_, source) = mesh_data(
+            &path,
+            data,
+            &MeshBindings::default(),
+            options,
+            &mut Vec::new(),
+        )
+        .unwrap();
+        let mut mesh_sources = HashMap::from([(path.clone(), source)]);
+        let mut points_changed = |points: Vec<f32>| {
+            let diff = bridge::MeshPointsDiff {
+                points,
+                ..Default::default()
+            };
+            mesh_points(
+                path.clone(),
+                diff,
+                &MeshBindings::default(),
+                options,
+                &mut mesh_sources,
+                &mut Vec::new(),
+            )
+        };
+
+        // 頂点位置だけを更新できないので、meshを作り直す
+        let mut added = NGON_POINTS.to_vec();
+        added.extend([5.0, 5.0, 0.0]);
+        let Ok(SceneDiffItem::MeshDataDirtied(_, mesh)) = points_changed(added) else {
+            panic!("expected MeshDataDirtied");
+        };
+        assert_eq!(mesh.extent.max, Vec3::new(5.0, 5.0, 0.0));
+
+        // 作り直せないデータはmeshの検証のエラーになる
+        let result = points_changed(NGON_POINTS[..9].to_vec());
+        assert!(matches!(result, Err(Error::UnsupportedData { .. })));
+    }
+
+    #[test]
+    fn extent_falls_back_to_points() {
+        let path = SdfPath::default();
+        let data = bridge::MeshDataDiff {
+            points: Some(NGON_POINTS.to_vec()),
+            face_vertex_indices: Some(NGON_INDICES.to_vec()),
+            face_vertex_counts: Some(NGON_COUNTS.to_vec()),
+            ..Default::default()
+        };
+        let (mesh, _) = mesh_data(
+            &path,
+            data.clone(),
+            &MeshBindings::default(),
+            MeshOptions::default(),
+            &mut Vec::new(),
+        )
+        .unwrap();
+        assert_eq!(mesh.extent.min, Vec3::ZERO);
+        assert_eq!(mesh.extent.max, Vec3::new(2.5, 2.0, 0.0));
+
+        // authorされたextentはpointsより優先する
+        let data = bridge::MeshDataDiff {
+            extent: Some([-1.0, -1.0, -1.0, 3.0, 3.0, 1.0]),
+            ..data
+        };
+        let (mesh, _) = mesh_data(
+            &path,
+            data,
+            &MeshBindings::default(),
+            MeshOptions::default(),
+            &mut Vec::new(),
+        )
+        .unwrap();
+        assert_eq!(mesh.extent.min, Vec3::splat(-1.0));
+        assert_eq!(mesh.extent.max, Vec3::new(3.0, 3.0, 1.0));
+    }
+
+    #[test]
+    fn extent_is_transformed_to_world_space() {
+        let extent = Aabb {
+            min: Vec3::new(-1.0, -1.0, -1.0),
+            max: Vec3::new(3.0, 3.0, 1.0),
+        };
+        // Z軸周りに90度回転すると(x, y, z)は(-y, x, z)になり、その後xに10平行移動する
+        let transform = TransformMatrix {
+            matrix: Mat4::from_translation(Vec3::new(10.0, 0.0, 0.0))
+                * Mat4::from_rotation_z(std::f32::consts::FRAC_PI_2),
+        };
+        let world = extent.transform(&transform);
+        assert!(world.min.abs_diff_eq(Vec3::new(7.0, -1.0, -1.0), 1e-5));
+        assert!(world.max.abs_diff_eq(Vec3::new(11.0, 3.0, 1.0), 1e-5));
+
+        // 45度回転すると、角を全て含むように大きくなる
+        let transform = TransformMatrix {
+            matrix: Mat4::from_rotation_z(std::f32::consts::FRAC_PI_4),
+        };
+        let world = extent.transform(&transform);
+        let half_size = 2.0 * std::f32::consts::SQRT_2;
+        assert!((world.size().x - 2.0 * half_size).abs() < 1e-5);
+        assert!((world.size().y - 2.0 * half_size).abs() < 1e-5);
+    }
+
+    #[test]
+    fn y_up_meters_matrix_converts_z_up_centimeters() {
+        let metadata = StageMetadata {
+            up_axis: UpAxis::Z,
+            meters_per_unit: 0.01,
+            ..Default::default()
+        };
+        // Z-upのcmで(100, 200, 300)の点は、Y-upのmで(1, 3, -2)になる
+        let matrix = metadata.y_up_meters_matrix();
+        let point = matrix.transform_point3(Vec3::new(100.0, 200.0, 300.0));
+        assert!(point.abs_diff_eq(Vec3::new(1.0, 3.0, -2.0), 1e-5));
+
+        // Y-upのmでは変換しない
+        let metadata = StageMetadata {
+            meters_per_unit: 1.0,
+            ..Default::default()
+        };
+        assert_eq!(metadata.y_up_meters_matrix(), Mat4::IDENTITY);
+    }
+
+    // 点0と3をjoint 0に、点1と2をjoint 1に束縛した四角形のskinning
+    fn quad_skin_binding() -> bridge::SkinBindingData {
+        bridge::SkinBindingData {
+            skeleton_path: String::new(),
+            interpolation: Interpolation::Vertex,
+            joint_indices: vec![0, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0],
+            joint_weights: vec![
+                1.0, 0.0, 0.0, 0.0, //
+                1.0, 0.0, 0.0, 0.0, //
+                1.0, 0.0, 0.0, 0.0, //
+                1.0, 0.0, 0.0, 0.0, //
+            ],
+            geom_bind_transform: Mat4::IDENTITY.to_cols_array(),
+        }
+    }
+
+    // joint 1だけをZ方向に1動かしたポーズ
+    fn quad_skeleton_pose() -> bridge::SkeletonPoseData {
+        let skinning_transforms = [
+            Mat4::IDENTITY,
+            Mat4::from_translation(Vec3::new(0.0, 0.0, 1.0)),
+        ];
+        bridge::SkeletonPoseData {
+            transform_matrix: Mat4::from_translation(Vec3::new(5.0, 0.0, 0.0)).to_cols_array(),
+            skinning_transforms: skinning_transforms
+                .iter()
+                .flat_map(|matrix| matrix.to_cols_array())
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn skin_is_expanded_and_skinned_on_cpu() {
+        let path = SdfPath::default();
+        let data = bridge::MeshDataDiff {
+            points: Some(QUAD_POINTS.to_vec()),
+            face_vertex_indices: Some(vec![0, 1, 2, 3]),
+            face_vertex_counts: Some(vec![4]),
+            ..Default::default()
+        };
+        let options = MeshOptions {
+            weld: true,
+            ..Default::default()
+        };
+        let bindings = MeshBindings {
+            skins: HashMap::from([(path.clone(), quad_skin_binding())]),
+            ..Default::default()
+        };
+        let (mesh, _) =
+            mesh_data(&path, data.clone(), &bindings, options, &mut Vec::new()).unwrap();
+        let mesh_skin = mesh.skin.as_ref().unwrap();
+        assert_eq!(mesh_skin.joint_indices.len(), mesh.vertices.len());
+        for (vertex, indices) in mesh.vertices.iter().zip(&mesh_skin.joint_indices) {
+            let expected = if vertex.position.x > 0.5 { 1 } else { 0 };
+            assert_eq!(indices[0], expected);
+        }
+
+        // meshのローカル座標系では、Skeletonとmeshのtransformの差とjointの移動が加わる
+        let pose = quad_skeleton_pose();
+        let pose = SkeletonPose {
+            transform_matrix: TransformMatrix {
+                matrix: Mat4::from_cols_array(&pose.transform_matrix),
+            },
+            skinning_transforms: pose
+                .skinning_transforms
+                .chunks_exact(16)
+                .map(Mat4::from_cols_slice)
+                .collect(),
+        };
+        let transform = TransformMatrix {
+            matrix: Mat4::from_translation(Vec3::new(2.0, 0.0, 0.0)),
+        };
+        let skinned = mesh.skinned(&pose, &transform).unwrap();
+        assert!(skinned.skin.is_none());
+        for (rest, posed) in mesh.vertices.iter().zip(&skinned.vertices) {
+            let z = if rest.position.x > 0.5 { 1.0 } else { 0.0 };
+            let expected = rest.position + Vec3::new(3.0, 0.0, z);
+            assert!(posed.position.abs_diff_eq(expected, 1e-5));
+        }
+        assert!(skinned
+            .extent
+            .max
+            .abs_diff_eq(Vec3::new(4.0, 1.0, 1.0), 1e-5));
+
+        // 点の数と重みの数が合わない束縛は不正なデータとして扱う
+        let skin = bridge::SkinBindingData {
+            joint_weights: vec![1.0; 4],
+            ..quad_skin_binding()
+        };
+        let bindings = MeshBindings {
+            skins: HashMap::from([(path.clone(), skin)]),
+            ..Default::default()
+        };
+        assert!(mesh_data(&path, data, &bindings, options, &mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn bindings_of_refined_mesh_are_reported_as_warnings() {
+        let path = SdfPath::default();
+        let data = bridge::MeshDataDiff {
+            points: Some(QUAD_POINTS.to_vec()),
+            face_vertex_indices: Some(vec![0, 1, 2, 3]),
+            face_vertex_counts: Some(vec![4]),
+            refine_level: Some(1),
+            ..Default::default()
+        };
+        let bindings = MeshBindings {
+            skins: HashMap::from([(path.clone(), quad_skin_binding())]),
+            blend_shapes: HashMap::from([(
+                path.clone(),
+                vec![bridge::BlendShapeData {
+                    offsets: vec![0.0; 12],
+                    ..Default::default()
+                }],
+            )]),
+        };
+        // 細分割したmeshは束縛とblend shapeを無視して作り、無視したことをwarningsに残す
+        let mut warnings = Vec::new();
+        let (mesh, _) = mesh_data(
+            &path,
+            data,
+            &bindings,
+            MeshOptions::default(),
+            &mut warnings,
+        )
+        .unwrap();
+        assert!(mesh.skin.is_none());
+        assert!(mesh.blend_shapes.is_empty());
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings
+            .iter()
+            .all(|warning| matches!(warning, Error::UnsupportedData { .. })));
+    }
+
+    #[test]
+    fn cpu_skinning_follows_skeleton_pose() {
+        let path = SdfPath::default();
+        let options = MeshOptions {
+            cpu_skinning: true,
+            ..Default::default()
+        };
+        let mut cache = SceneCache::default();
+        let mut diff = bridge::UsdDataDiff::default();
+        diff.meshes.create.insert(
+            path.clone(),
+            bridge::MeshCreate {
+                points: Some(QUAD_POINTS.to_vec()),
+                face_vertex_indices: Some(vec![0, 1, 2, 3]),
+                face_vertex_counts: Some(vec![4]),
+                ..Default::default()
+            },
+        );
+        diff.skeletons
+            .skin_bindings
+            .insert(path.clone(), quad_skin_binding());
+        diff.skeletons
+            .poses
+            .insert(path.clone(), quad_skeleton_pose());
+        let scene_diff = SceneDiff::new(diff, Mat4::IDENTITY, options, &mut cache);
+        assert!(scene_diff.warnings.is_empty());
+        let mesh = scene_diff
+            .items
+            .iter()
+            .find_map(|item| match item {
+                SceneDiffItem::MeshCreated(_, _, mesh) => Some(mesh),
+                _ => None,
+            })
+            .unwrap();
+        assert!(mesh.skin.is_none());
+        assert!(mesh.extent.max.abs_diff_eq(Vec3::new(6.0, 1.0, 1.0), 1e-5));
+
+        // ポーズだけが変わった場合は、restのmeshからポーズを適用し直した頂点位置を返す
+        let mut diff = bridge::UsdDataDiff::default();
+        let mut pose = quad_skeleton_pose();
+        pose.transform_matrix = Mat4::IDENTITY.to_cols_array();
+        diff.skeletons.poses.insert(path.clone(), pose);
+        let scene_diff = SceneDiff::new(diff, Mat4::IDENTITY, options, &mut cache);
+        let points = scene_diff
+            .items
+            .iter()
+            .find_map(|item| match item {
+                SceneDiffItem::MeshPointsDirtied(_, points) => Some(points),
+                _ => None,
+            })
+            .unwrap();
+        assert!(points.extent.min.abs_diff_eq(Vec3::ZERO, 1e-5));
+        assert!(points
+            .extent
+            .max
+            .abs_diff_eq(Vec3::new(1.0, 1.0, 1.0), 1e-5));
+    }
+
+    #[test]
+    fn blend_shapes_interpolate_inbetweens() {
+        let path = SdfPath::default();
+        let data = bridge::MeshDataDiff {
+            points: Some(QUAD_POINTS.to_vec()),
+            face_vertex_indices: Some(vec![0, 1, 2, 3]),
+            face_vertex_counts: Some(vec![4]),
+            ..Default::default()
+        };
+        // 点2だけをZ方向に動かし、weight 0.5のinbetweenで先に1まで動かす
+        let bindings = MeshBindings {
+            blend_shapes: HashMap::from([(
+                path.clone(),
+                vec![bridge::BlendShapeData {
+                    name: "lift".to_string(),
+                    offsets: vec![0.0, 0.0, 2.0],
+                    point_indices: Some(vec![2]),
+                    inbetweens: vec![bridge::BlendShapeInbetweenData {
+                        weight: 0.5,
+                        offsets: vec![0.0, 0.0, 1.0],
+                        normal_offsets: None,
+                    }],
+                    ..Default::default()
+                }],
+            )]),
+            ..Default::default()
+        };
+        let options = MeshOptions {
+            weld: true,
+            ..Default::default()
+        };
+        let (mesh, _) = mesh_data(&path, data, &bindings, options, &mut Vec::new()).unwrap();
+        assert_eq!(mesh.blend_shapes.len(), 1);
+        assert_eq!(mesh.blend_shapes[0].vertex_indices.len(), 1);
+        let weights = mesh.blend_shapes[0]
+            .targets
+            .iter()
+            .map(|target| target.weight)
+            .collect::<Vec<_>>();
+        assert_eq!(weights, [0.5, 1.0]);
+
+        let lifted = |weight: f32| {
+            let points = mesh.blend_shape_points(&[weight]);
+            let vertex = mesh.blend_shapes[0].vertex_indices[0] as usize;
+            points.positions[vertex].z
+        };
+        assert!((lifted(0.25) - 0.5).abs() < 1e-5);
+        assert!((lifted(0.75) - 1.5).abs() < 1e-5);
+        // 範囲外のweightは端の2つのtargetで外挿する
+        assert!((lifted(2.0) - 4.0).abs() < 1e-5);
+        // weightが足りない場合は0として扱う
+        let points = mesh.blend_shape_points(&[]);
+        for (vertex, &position) in mesh.vertices.iter().zip(&points.positions) {
+            assert_eq!(vertex.position, position);
+        }
+    }
+
+    #[test]
+    fn nested_instancers_compose_instance_transforms() {
+        let translations = |translations: &[[f32; 3]]| {
+            translations
+                .iter()
+                .flat_map(|&t| Mat4::from_translation(Vec3::from(t)).to_cols_array())
+                .collect::<Vec<_>>()
+        };
+        let outer = bridge::InstancerDiffItem {
+            transform_matrix: Some(Mat4::from_translation(Vec3::X * 10.0).to_cols_array()),
+            instance_transforms: translations(&[[0.0, 0.0, 0.0], [0.0, 100.0, 0.0]]),
+            prototypes: vec![("/Outer/Proto".to_string(), vec![1, 0])],
+            ..Default::default()
+        };
+        let inner = bridge::InstancerDiffItem {
+            transform_matrix: None,
+            instance_transforms: translations(&[[1.0, 0.0, 0.0], [2.0, 0.0, 0.0], [3.0, 0.0, 0.0]]),
+            prototypes: vec![("/Outer/Proto/Inner/Sphere".to_string(), vec![2, 0])],
+            primvars: HashMap::from([(
+                "displayOpacity".to_string(),
+                bridge::PrimvarData {
+                    element_type: PrimvarType::Float,
+                    interpolation: Interpolation::Instance,
+                    values: vec![0.1, 0.2, 0.3],
+                    indices: None,
+                },
+            )]),
+        };
+        let mut instancers = Instancers::default();
+        instancers.insert("/Outer".to_string(), instancer(outer).unwrap());
+        instancers.insert("/Outer/Proto/Inner".to_string(), instancer(inner).unwrap());
+
+        // 外側のinstanceごとに内側のinstanceが並ぶ
+        let instances = mesh_instances(
+            &instancers,
+            "/Outer/Proto/Inner/Sphere/Mesh",
+            Mat4::IDENTITY,
+        )
+        .unwrap();
+        let positions = instances
+            .transforms
+            .iter()
+            .map(|transform| transform.w_axis.truncate())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            positions,
+            [
+                Vec3::new(13.0, 100.0, 0.0),
+                Vec3::new(11.0, 100.0, 0.0),
+                Vec3::new(13.0, 0.0, 0.0),
+                Vec3::new(11.0, 0.0, 0.0),
+            ]
+        );
+        assert_eq!(
+            instances.primvars["displayOpacity"].values,
+            [0.3, 0.1, 0.3, 0.1]
+        );
+
+        // prototypeのroot pathはpathの要素単位で比べる
+        let instances =
+            mesh_instances(&instancers, "/Outer/Proto/Inner/SphereCopy", Mat4::IDENTITY).unwrap();
+        assert_eq!(instances.transforms.len(), 2);
+        assert!(instances.primvars.is_empty());
+        assert!(mesh_instances(&instancers, "/Other/Mesh", Mat4::IDENTITY).is_none());
+
+        // 外側のinstancerのprototypeには、内側のinstancerのprototypeも含まれる
+        let roots = instancers.nested_prototype_roots(HashSet::from(["/Outer/Proto".to_string()]));
+        assert!(roots.contains("/Outer/Proto/Inner/Sphere"));
+
+        // SphereCopyの2つのinstanceを全て含むAABB
+        let extent = Aabb {
+            min: Vec3::splat(-0.5),
+            max: Vec3::splat(0.5),
+        };
+        let transform_matrix = TransformMatrix {
+            matrix: Mat4::from_translation(Vec3::Z),
+        };
+        let aabb = instances.aabb(&extent, &transform_matrix).unwrap();
+        assert!(aabb.min.abs_diff_eq(Vec3::new(9.5, -0.5, 0.5), 1e-5));
+        assert!(aabb.max.abs_diff_eq(Vec3::new(10.5, 100.5, 1.5), 1e-5));
+
+        // prototypeが使うinstanceが範囲外のinstancerは受け付けない
+        let invalid = bridge::InstancerDiffItem {
+            instance_transforms: translations(&[[0.0, 0.0, 0.0]]),
+            prototypes: vec![("/Proto".to_string(), vec![1])],
+            ..Default::default()
+        };
+        assert!(instancer(invalid).is_err());
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod mock_tests {
+    use super::mock::{MockInstancer, MockMesh, MockPrim, MockSphereLight, MockStage};
+    use super::*;
+
+    const QUAD_POINTS: [f32; 12] = [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0, 0.0];
+
+    fn quad() -> MockMesh {
+        MockMesh {
+            transform_matrix: Some(Mat4::IDENTITY.to_cols_array()),
+            points: Some(QUAD_POINTS.to_vec()),
+            face_vertex_indices: Some(vec![0, 1, 2, 3]),
+            face_vertex_counts: Some(vec![4]),
+            ..Default::default()
+        }
+    }
+
+    fn extractor(samples: Vec<(f64, Vec<(&str, MockPrim)>)>) -> UsdSceneExtractor {
+        let mut stage = MockStage::new(0.0, 10.0);
+        for (time_code, prims) in samples {
+            stage.set_prims(
+                time_code,
+                prims
+                    .into_iter()
+                    .map(|(path, prim)| (path.to_string(), prim)),
+            );
+        }
+        UsdSceneExtractor::from_mock_stage(stage)
+    }
+
+    fn item_names(diff: &SceneDiff) -> Vec<&'static str> {
+        diff.items
+            .iter()
+            .map(|item| match item {
+                SceneDiffItem::MeshCreated(..) => "MeshCreated",
+                SceneDiffItem::MeshDestroyed(..) => "MeshDestroyed",
+                SceneDiffItem::MeshTransformMatrixDirtied(..) => "MeshTransformMatrixDirtied",
+                SceneDiffItem::MeshDataDirtied(..) => "MeshDataDirtied",
+                SceneDiffItem::MeshPointsDirtied(..) => "MeshPointsDirtied",
+                SceneDiffItem::MeshInstancesDirtied(..) => "MeshInstancesDirtied",
+                SceneDiffItem::SphereLightAddOrUpdate(..) => "SphereLightAddOrUpdate",
+                SceneDiffItem::SphereLightDestroyed(..) => "SphereLightDestroyed",
+                _ => "Other",
+            })
+            .collect()
+    }
+
+    #[test]
+    fn extract_reports_mesh_lifecycle() {
+        let mut moved = quad();
+        moved.transform_matrix = Some(Mat4::from_translation(Vec3::X).to_cols_array());
+        let mut lifted = moved.clone();
+        lifted.points = Some(QUAD_POINTS.iter().map(|&v| v + 1.0).collect());
+        let mut triangle = lifted.clone();
+        triangle.face_vertex_indices = Some(vec![0, 1, 2]);
+        triangle.face_vertex_counts = Some(vec![3]);
+        let mut extractor = extractor(vec![
+            (0.0, vec![("/World/Mesh", MockPrim::Mesh(quad()))]),
+            (1.0, vec![("/World/Mesh", MockPrim::Mesh(moved))]),
+            (2.0, vec![("/World/Mesh", MockPrim::Mesh(lifted))]),
+            (3.0, vec![("/World/Mesh", MockPrim::Mesh(triangle))]),
+            (4.0, vec![]),
+        ]);
+
+        let diff = extractor.extract(0.0);
+        assert_eq!(item_names(&diff), ["MeshCreated"]);
+        let SceneDiffItem::MeshCreated(_, _, mesh) = &diff.items[0] else {
+            unreachable!();
+        };
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_eq!(mesh.sub_meshes[0].indices.len(), 6);
+
+        // 同じsampleの中では差分はない
+        assert!(extractor.extract(0.5).items.is_empty());
+
+        let diff = extractor.extract(1.0);
+        assert_eq!(item_names(&diff), ["MeshTransformMatrixDirtied"]);
+        let SceneDiffItem::MeshTransformMatrixDirtied(_, matrix) = &diff.items[0] else {
+            unreachable!();
+        };
+        assert_eq!(matrix.matrix, Mat4::from_translation(Vec3::X));
+
+        // pointsだけが変わった場合は頂点位置だけを返す
+        let diff = extractor.extract(2.0);
+        assert_eq!(item_names(&diff), ["MeshPointsDirtied"]);
+        let SceneDiffItem::MeshPointsDirtied(_, points) = &diff.items[0] else {
+            unreachable!();
+        };
+        assert!(points.extent.min.abs_diff_eq(Vec3::ONE, 1e-5));
+
+        // topologyが変わった場合はmeshを作り直す
+        let diff = extractor.extract(3.0);
+        assert_eq!(item_names(&diff), ["MeshDataDirtied"]);
+        let SceneDiffItem::MeshDataDirtied(_, mesh) = &diff.items[0] else {
+            unreachable!();
+        };
+        assert_eq!(mesh.sub_meshes[0].indices.len(), 3);
+
+        let diff = extractor.extract(4.0);
+        assert_eq!(item_names(&diff), ["MeshDestroyed"]);
+        assert!(diff.warnings.is_empty());
+    }
+
+    #[test]
+    fn malformed_prim_is_reported_as_warning() {
+        let mut broken = quad();
+        broken.face_vertex_indices = Some(vec![0, 1, 2, 42]);
+        let mut extractor = extractor(vec![(
+            0.0,
+            vec![
+                ("/World/Broken", MockPrim::Mesh(broken)),
+                ("/World/Mesh", MockPrim::Mesh(quad())),
+            ],
+        )]);
+
+        // 不正なMeshはwarningsに入り、残りのMeshはdiffに残る
+        let diff = extractor.extract(0.0);
+        assert_eq!(item_names(&diff), ["MeshCreated"]);
+        assert_eq!(diff.warnings.len(), 1);
+        assert!(matches!(
+            &diff.warnings[0],
+            Error::UnsupportedData { path, .. } if path == "/World/Broken"
+        ));
+    }
+
+    #[test]
+    fn only_meshes_of_dirtied_instancers_are_reinstanced() {
+        let instancer = |prototype: &str, x: f32| {
+            MockPrim::Instancer(MockInstancer {
+                transform_matrix: Some(Mat4::IDENTITY.to_cols_array()),
+                instance_transforms: [Vec3::ZERO, Vec3::new(x, 0.0, 0.0)]
+                    .iter()
+                    .flat_map(|&t| Mat4::from_translation(t).to_cols_array())
+                    .collect(),
+                prototypes: vec![(prototype.to_string(), vec![0, 1])],
+                ..Default::default()
+            })
+        };
+        let prims = |x: f32| {
+            vec![
+                ("/A", instancer("/A/Proto", 1.0)),
+                ("/A/Proto/Mesh", MockPrim::Mesh(quad())),
+                ("/B", instancer("/B/Proto", x)),
+                ("/B/Proto/Mesh", MockPrim::Mesh(quad())),
+            ]
+        };
+        let mut extractor = extractor(vec![(0.0, prims(1.0)), (1.0, prims(2.0))]);
+        let diff = extractor.extract(0.0);
+        let mut instanced = diff
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                SceneDiffItem::MeshInstancesDirtied(path, instances) => {
+                    assert_eq!(instances.transforms.len(), 2);
+                    Some(path.as_str())
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        instanced.sort();
+        assert_eq!(instanced, ["/A/Proto/Mesh", "/B/Proto/Mesh"]);
+
+        // 変わったinstancerのprototypeのmeshだけinstanceを作り直す
+        let diff = extractor.extract(1.0);
+        let [SceneDiffItem::MeshInstancesDirtied(path, instances)] = diff.items.as_slice() else {
+            panic!(
+                "expected a single MeshInstancesDirtied, got {:?}",
+                item_names(&diff)
+            );
+        };
+        assert_eq!(path.as_str(), "/B/Proto/Mesh");
+        assert_eq!(
+            instances.transforms[1],
+            Mat4::from_translation(Vec3::X * 2.0)
+        );
+    }
+
+    #[test]
+    fn inactive_and_invisible_prims_are_destroyed_and_recreated() {
+        let mut extractor = extractor(vec![(
+            0.0,
+            vec![
+                ("/World/Mesh", MockPrim::Mesh(quad())),
+                ("/Other/Mesh", MockPrim::Mesh(quad())),
+            ],
+        )]);
+        assert_eq!(item_names(&extractor.extract(0.0)).len(), 2);
+
+        // 祖先のprimの編集も子孫に効く
+        extractor.set_active("/World", false).unwrap();
+        assert_eq!(item_names(&extractor.extract(0.0)), ["MeshDestroyed"]);
+        extractor.set_active("/World", true).unwrap();
+        assert_eq!(item_names(&extractor.extract(0.0)), ["MeshCreated"]);
+
+        extractor.set_visibility("/World/Mesh", false).unwrap();
+        assert_eq!(item_names(&extractor.extract(0.0)), ["MeshDestroyed"]);
+        extractor.set_visibility("/World/Mesh", true).unwrap();
+        assert_eq!(item_names(&extractor.extract(0.0)), ["MeshCreated"]);
+    }
+
+    #[test]
+    fn set_transform_overrides_stage_transform() {
+        let mut extractor = extractor(vec![(0.0, vec![("/World/Mesh", MockPrim::Mesh(quad()))])]);
+        extractor.extract(0.0);
+
+        let matrix = Mat4::from_translation(Vec3::new(1.0, 2.0, 3.0));
+        extractor.set_transform("/World/Mesh", matrix).unwrap();
+        let diff = extractor.extract(0.0);
+        assert_eq!(item_names(&diff), ["MeshTransformMatrixDirtied"]);
+        let SceneDiffItem::MeshTransformMatrixDirtied(_, transform_matrix) = &diff.items[0] else {
+            unreachable!();
+        };
+        assert_eq!(transform_matrix.matrix, matrix);
+
+        // 同じtransformを設定し直しても差分はない
+        extractor.set_transform("/World/Mesh", matrix).unwrap();
+        assert!(extractor.extract(0.0).items.is_empty());
+    }
+
+    #[test]
+    fn removed_prim_is_restored_by_set_active() {
+        let mut extractor = extractor(vec![(0.0, vec![("/World/Mesh", MockPrim::Mesh(quad()))])]);
+        extractor.extract(0.0);
+
+        extractor.remove_prim("/World/Mesh").unwrap();
+        assert_eq!(item_names(&extractor.extract(0.0)), ["MeshDestroyed"]);
+        extractor.set_active("/World/Mesh", true).unwrap();
+        assert_eq!(item_names(&extractor.extract(0.0)), ["MeshCreated"]);
+    }
+
+    #[test]
+    fn population_mask_limits_extracted_prims() {
+        let mut extractor = extractor(vec![(
+            0.0,
+            vec![
+                ("/World/A/Mesh", MockPrim::Mesh(quad())),
+                ("/World/B/Mesh", MockPrim::Mesh(quad())),
+            ],
+        )]);
+        extractor
+            .set_population_mask(Some(&["/World/A".to_string()]))
+            .unwrap();
+        let diff = extractor.extract(0.0);
+        let [SceneDiffItem::MeshCreated(path, ..)] = diff.items.as_slice() else {
+            panic!(
+                "expected a single MeshCreated, got {} items",
+                diff.items.len()
+            );
+        };
+        assert_eq!(path.as_str(), "/World/A/Mesh");
+
+        // maskを外すと残りのprimが追加される
+        extractor.set_population_mask(None).unwrap();
+        let diff = extractor.extract(0.0);
+        let [SceneDiffItem::MeshCreated(path, ..)] = diff.items.as_slice() else {
+            panic!(
+                "expected a single MeshCreated, got {} items",
+                diff.items.len()
+            );
+        };
+        assert_eq!(path.as_str(), "/World/B/Mesh");
+    }
+
+    #[test]
+    fn set_attribute_edits_mock_prims() {
+        let mut extractor = extractor(vec![(
+            0.0,
+            vec![
+                ("/World/Mesh", MockPrim::Mesh(quad())),
+                (
+                    "/World/Light",
+                    MockPrim::SphereLight(MockSphereLight {
+                        transform_matrix: Some(Mat4::IDENTITY.to_cols_array()),
+                        color: Some([1.0; 3]),
+                        intensity: Some(1.0),
+                        ..Default::default()
+                    }),
+                ),
+            ],
+        )]);
+        assert_eq!(
+            item_names(&extractor.extract(0.0)),
+            ["MeshCreated", "SphereLightAddOrUpdate"]
+        );
+
+        let points = QUAD_POINTS
+            .chunks_exact(3)
+            .map(|p| [p[0], p[1], p[2] + 1.0])
+            .collect();
+        extractor
+            .set_attribute(
+                "/World/Mesh",
+                "points",
+                &AttributeValue::Point3fArray(points),
+            )
+            .unwrap();
+        assert_eq!(item_names(&extractor.extract(0.0)), ["MeshPointsDirtied"]);
+
+        extractor
+            .set_attribute(
+                "/World/Mesh",
+                "faceVertexCounts",
+                &AttributeValue::IntArray(vec![3]),
+            )
+            .unwrap();
+        extractor
+            .set_attribute(
+                "/World/Mesh",
+                "faceVertexIndices",
+                &AttributeValue::IntArray(vec![0, 1, 2]),
+            )
+            .unwrap();
+        assert_eq!(item_names(&extractor.extract(0.0)), ["MeshDataDirtied"]);
+
+        extractor
+            .set_attribute(
+                "/World/Light",
+                "inputs:intensity",
+                &AttributeValue::Float(2.0),
+            )
+            .unwrap();
+        let diff = extractor.extract(0.0);
+        assert_eq!(item_names(&diff), ["SphereLightAddOrUpdate"]);
+        let SceneDiffItem::SphereLightAddOrUpdate(_, light) = &diff.items[0] else {
+            unreachable!();
+        };
+        assert_eq!(light.intensity, 2.0);
+
+        // mockのprimが持っていない属性や、存在しないprimは編集できない
+        assert!(matches!(
+            extractor.set_attribute("/World/Mesh", "points", &AttributeValue::Float(1.0)),
+            Err(Error::Edit { .. })
+        ));
+        assert!(matches!(
+            extractor.set_attribute("/World/Missing", "points", &AttributeValue::Float(1.0)),
+            Err(Error::Edit { .. })
+        ));
+    }
+
+    #[test]
+    fn define_prim_creates_and_remove_prim_destroys() {
+        let mut extractor = extractor(vec![(0.0, vec![])]);
+        extractor.define_prim("/World", "Xform").unwrap();
+        extractor.define_prim("/World/Mesh", "Mesh").unwrap();
+        extractor
+            .set_attribute(
+                "/World/Mesh",
+                "points",
+                &AttributeValue::Point3fArray(
+                    QUAD_POINTS
+                        .chunks_exact(3)
+                        .map(|p| [p[0], p[1], p[2]])
+                        .collect(),
+                ),
+            )
+            .unwrap();
+        extractor
+            .set_attribute(
+                "/World/Mesh",
+                "faceVertexCounts",
+                &AttributeValue::IntArray(vec![4]),
+            )
+            .unwrap();
+        extractor
+            .set_attribute(
+                "/World/Mesh",
+                "faceVertexIndices",
+                &AttributeValue::IntArray(vec![0, 1, 2, 3]),
+            )
+            .unwrap();
+        let diff = extractor.extract(0.0);
+        assert_eq!(item_names(&diff), ["MeshCreated"]);
+        assert!(diff.warnings.is_empty());
+
+        extractor.remove_prim("/World/Mesh").unwrap();
+        assert_eq!(item_names(&extractor.extract(0.0)), ["MeshDestroyed"]);
+
+        assert!(matches!(
+            extractor.define_prim("/World/Volume", "Volume"),
+            Err(Error::Edit { .. })
+        ));
+    }
+}
+
+#[cfg(all(test, not(feature = "mock")))]
+mod usda_tests {
+    use super::*;
+
+    const QUAD_USDA: &str = r#"#usda 1.0
+(
+    defaultPrim = "World"
+    metersPerUnit = 0.01
+    upAxis = "Z"
+)
+
+def Xform "World"
+{
+    def Mesh "Mesh"
+    {
+        point3f[] points = [(0, 0, 0), (1, 0, 0), (1, 1, 0), (0, 1, 0)]
+        int[] faceVertexIndices = [0, 1, 2, 3]
+        int[] faceVertexCounts = [4]
+        uniform token subdivisionScheme = "none"
+        double3 xformOp:translate = (0, 0, 1)
+        uniform token[] xformOpOrder = ["xformOp:translate"]
+    }
+}
+"#;
+
+    #[test]
+    fn extract_from_usda_str() {
+        let mut extractor = UsdSceneExtractor::from_usda_str(QUAD_USDA).unwrap();
+        assert_eq!(extractor.stage_metadata().up_axis, UpAxis::Z);
+        assert_eq!(
+            extractor.stage_metadata().default_prim.as_deref(),
+            Some("World")
+        );
+        assert_eq!(extractor.stage_metadata().meters_per_unit, 0.01);
+
+        let (start_time_code, _) = extractor.time_code_range();
+        let diff = extractor.extract(start_time_code);
+        assert!(diff.warnings.is_empty(), "{:?}", diff.warnings);
+        let [SceneDiffItem::MeshCreated(path, transform_matrix, mesh)] = diff.items.as_slice()
+        else {
+            panic!(
+                "expected a single MeshCreated, got {} items",
+                diff.items.len()
+            );
+        };
+        assert_eq!(path.as_str(), "/World/Mesh");
+        assert_eq!(
+            transform_matrix.matrix,
+            Mat4::from_translation(Vec3::new(0.0, 0.0, 1.0))
+        );
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_eq!(mesh.sub_meshes[0].indices.len(), 6);
+
+        // 変更がなければ差分はない
+        assert!(extractor.extract(start_time_code).items.is_empty());
+    }
+
+    #[test]
+    fn extract_from_layer_identifier() {
+        let root = concat!(env!("CARGO_MANIFEST_DIR"), "/../test-usd/test.usda");
+        let mut extractor = UsdSceneExtractor::from_layers(root, None).unwrap();
+        assert_eq!(
+            extractor.stage_metadata().default_prim.as_deref(),
+            Some("SphereA")
+        );
+        let (start_time_code, _) = extractor.time_code_range();
+        assert_eq!(start_time_code, 1.0);
+        let diff = extractor.extract(start_time_code);
+        assert!(diff.items.iter().any(|item| matches!(
+            item,
+            SceneDiffItem::MeshCreated(path, ..) if path.as_str() == "/Plane/mesh_0"
+        )));
+
+        assert!(matches!(
+            UsdSceneExtractor::from_layers("missing.usda", None),
+            Err(Error::OpenStage { .. })
+        ));
+    }
+
+    #[test]
+    fn session_layer_overrides_root_layer() {
+        let session = r#"#usda 1.0
+over "World"
+{
+    over "Mesh"
+    {
+        token visibility = "invisible"
+    }
+}
+"#;
+        let mut extractor = UsdSceneExtractor::from_usda_layers(QUAD_USDA, Some(session)).unwrap();
+        let (start_time_code, _) = extractor.time_code_range();
+        let diff = extractor.extract(start_time_code);
+        assert!(diff.warnings.is_empty(), "{:?}", diff.warnings);
+        assert!(diff.items.is_empty());
     }
 }
@@ -0,0 +1,230 @@
+use glam::Vec3;
+
+/// 法線が与えられていないmeshの、faceの頂点ごとの法線を求める。
+/// face_trianglesはfaceごとにtriangulateした、faceの頂点(face_vertex_indicesの位置)のindex。
+///
+/// faceの法線をfaceの頂点の角度で重み付けして平均する。
+/// 辺を共有するfaceの法線のなす角がcrease_angle(度)未満の場合だけその辺を越えて平均するので、
+/// 角度の大きい辺では頂点の法線が分かれる。
+/// crease_angleが180度以上の場合は、辺を共有しないものも含めて点を共有する全てのfaceで平均する。
+pub(crate) fn generate_normals(
+    points: &[Vec3],
+    face_vertex_indices: &[u32],
+    face_vertex_counts: &[u32],
+    face_triangles: &[Vec<u32>],
+    crease_angle: f32,
+) -> Vec<Vec3> {
+    let position = |corner: usize| points[face_vertex_indices[corner] as usize];
+
+    // faceの三角形の外積の和から、面積で重み付けされたfaceの法線を求める。
+    // 三角形の向きはleft handedを考慮済みなので、そのまま法線を求める
+    let face_normals = face_triangles
+        .iter()
+        .map(|triangles| {
+            triangles
+                .chunks_exact(3)
+                .map(|triangle| {
+                    let [p0, p1, p2] = [0, 1, 2].map(|i| position(triangle[i] as usize));
+                    (p1 - p0).cross(p2 - p0)
+                })
+                .sum::<Vec3>()
+                .normalize_or_zero()
+        })
+        .collect::<Vec<_>>();
+
+    // faceの頂点ごとにそのfaceの番号と、faceの中の前後の頂点を求める
+    let mut corner_faces = Vec::with_capacity(face_vertex_indices.len());
+    let mut neighbors = Vec::with_capacity(face_vertex_indices.len());
+    let mut index_offset = 0;
+    for (face, &count) in face_vertex_counts.iter().enumerate() {
+        let count = count as usize;
+        for i in 0..count {
+            let prev = index_offset + (i + count - 1) % count;
+            let next = index_offset + (i + 1) % count;
+            corner_faces.push(face);
+            neighbors.push((prev, next));
+        }
+        index_offset += count;
+    }
+
+    // 同じグループになった頂点の法線は平均される
+    let groups = if crease_angle >= 180.0 {
+        face_vertex_indices
+            .iter()
+            .map(|&index| index as usize)
+            .collect::<Vec<_>>()
+    } else {
+        smooth_groups(
+            face_vertex_indices,
+            &face_normals,
+            &corner_faces,
+            &neighbors,
+            crease_angle.to_radians().cos(),
+        )
+    };
+
+    // faceの法線をfaceの頂点の角度で重み付けしてグループごとに足し合わせる
+    let group_count = groups.iter().max().map_or(0, |&group| group + 1);
+    let mut group_normals = vec![Vec3::ZERO; group_count];
+    for (corner, &(prev, next)) in neighbors.iter().enumerate() {
+        let face = corner_faces[corner];
+        let p = position(corner);
+        let to_prev = (position(prev) - p).normalize_or_zero();
+        let to_next = (position(next) - p).normalize_or_zero();
+        let angle = to_prev.dot(to_next).clamp(-1.0, 1.0).acos();
+        group_normals[groups[corner]] += face_normals[face] * angle;
+    }
+
+    groups
+        .iter()
+        .enumerate()
+        .map(|(corner, &group)| {
+            let normal = group_normals[group].normalize_or_zero();
+            if normal == Vec3::ZERO {
+                face_normals[corner_faces[corner]]
+            } else {
+                normal
+            }
+        })
+        .collect()
+}
+
+// faceの頂点を、法線のなす角が閾値未満の辺で繋がったグループに分け、グループの番号を返す
+fn smooth_groups(
+    face_vertex_indices: &[u32],
+    face_normals: &[Vec3],
+    corner_faces: &[usize],
+    neighbors: &[(usize, usize)],
+    min_cos: f32,
+) -> Vec<usize> {
+    // faceの頂点から次の頂点への辺を両端の点のindexの組で並べ、
+    // 同じ辺を共有するfaceの頂点同士を探す
+    let mut shared_edges = neighbors
+        .iter()
+        .enumerate()
+        .filter_map(|(a, &(_, b))| {
+            let (point_a, point_b) = (face_vertex_indices[a], face_vertex_indices[b]);
+            match point_a.cmp(&point_b) {
+                std::cmp::Ordering::Less => Some(((point_a, point_b), a, b)),
+                std::cmp::Ordering::Greater => Some(((point_b, point_a), b, a)),
+                // 同じ点が連続する縮退した辺
+                std::cmp::Ordering::Equal => None,
+            }
+        })
+        .collect::<Vec<_>>();
+    shared_edges.sort_unstable_by_key(|&(key, _, _)| key);
+
+    let mut parents = (0..face_vertex_indices.len()).collect::<Vec<_>>();
+    for edges in shared_edges.chunk_by(|(a, _, _), (b, _, _)| a == b) {
+        for (i, &(_, a0, b0)) in edges.iter().enumerate() {
+            let normal = face_normals[corner_faces[a0]];
+            for &(_, a1, b1) in &edges[i + 1..] {
+                let other = face_normals[corner_faces[a1]];
+                if normal != Vec3::ZERO && other != Vec3::ZERO && normal.dot(other) > min_cos {
+                    union(&mut parents, a0, a1);
+                    union(&mut parents, b0, b1);
+                }
+            }
+        }
+    }
+    (0..parents.len())
+        .map(|corner| find(&mut parents, corner))
+        .collect()
+}
+
+fn find(parents: &mut [usize], mut i: usize) -> usize {
+    while parents[i] != i {
+        parents[i] = parents[parents[i]];
+        i = parents[i];
+    }
+    i
+}
+
+fn union(parents: &mut [usize], a: usize, b: usize) {
+    let (a, b) = (find(parents, a), find(parents, b));
+    parents[a.max(b)] = a.min(b);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // faceごとにfanでtriangulateしたface_triangles
+    fn fan_triangles(face_vertex_counts: &[u32]) -> Vec<Vec<u32>> {
+        let mut index_offset = 0;
+        face_vertex_counts
+            .iter()
+            .map(|&count| {
+                let triangles = (2..count)
+                    .flat_map(|i| [0, i - 1, i].map(|j| index_offset + j))
+                    .collect();
+                index_offset += count;
+                triangles
+            })
+            .collect()
+    }
+
+    // 外向きの単位立方体。点のindexはx + 2y + 4z
+    fn cube() -> (Vec<Vec3>, Vec<u32>, Vec<u32>) {
+        let points = (0..8)
+            .map(|i| Vec3::new((i & 1) as f32, ((i >> 1) & 1) as f32, (i >> 2) as f32))
+            .collect();
+        let indices = vec![
+            0, 2, 3, 1, 4, 5, 7, 6, 0, 1, 5, 4, 2, 6, 7, 3, 0, 4, 6, 2, 1, 3, 7, 5,
+        ];
+        (points, indices, vec![4; 6])
+    }
+
+    #[test]
+    fn cube_is_smooth_with_default_crease_angle() {
+        let (points, indices, counts) = cube();
+        let normals = generate_normals(&points, &indices, &counts, &fan_triangles(&counts), 180.0);
+        for (&index, normal) in indices.iter().zip(normals) {
+            let expected = (points[index as usize] * 2.0 - Vec3::ONE).normalize();
+            assert!(normal.abs_diff_eq(expected, 1e-5));
+        }
+    }
+
+    #[test]
+    fn cube_edges_split_below_crease_angle() {
+        let (points, indices, counts) = cube();
+        let normals = generate_normals(&points, &indices, &counts, &fan_triangles(&counts), 60.0);
+        let face_normals = [
+            Vec3::NEG_Z,
+            Vec3::Z,
+            Vec3::NEG_Y,
+            Vec3::Y,
+            Vec3::NEG_X,
+            Vec3::X,
+        ];
+        for (i, normal) in normals.into_iter().enumerate() {
+            assert!(normal.abs_diff_eq(face_normals[i / 4], 1e-5));
+        }
+    }
+
+    #[test]
+    fn normals_are_weighted_by_corner_angle() {
+        // +Zを向く四角形と、-Yを向く三角形が原点からの辺を共有する。
+        // 原点での角度は四角形が90度、三角形が45度
+        let points = [
+            Vec3::ZERO,
+            Vec3::X,
+            Vec3::new(1.0, 1.0, 0.0),
+            Vec3::Y,
+            Vec3::new(1.0, 0.0, 1.0),
+        ];
+        let indices = [0, 1, 2, 3, 0, 1, 4];
+        let counts = [4, 3];
+        let triangles = fan_triangles(&counts);
+
+        let normals = generate_normals(&points, &indices, &counts, &triangles, 180.0);
+        let expected = Vec3::new(0.0, -1.0, 2.0).normalize();
+        assert!(normals[0].abs_diff_eq(expected, 1e-5));
+        assert!(normals[4].abs_diff_eq(expected, 1e-5));
+
+        // 2つのfaceのなす角は90度なので、それ未満のcrease angleでは分かれる
+        let normals = generate_normals(&points, &indices, &counts, &triangles, 89.0);
+        assert!(normals[0].abs_diff_eq(Vec3::Z, 1e-5));
+        assert!(normals[4].abs_diff_eq(Vec3::NEG_Y, 1e-5));
+    }
+}
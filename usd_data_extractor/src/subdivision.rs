@@ -0,0 +1,1096 @@
+use std::collections::HashMap;
+
+use crate::bridge::{
+    BoundaryInterpolation, FaceVaryingLinearInterpolation, MeshDataDiff, PrimvarData, PrimvarType,
+    SubdivisionScheme, SubdivisionTags,
+};
+use crate::{interpolation_element_count, resolve_indexed_primvar, Interpolation};
+
+/// refine levelの上限。1段階の細分割ごとにfaceの数がおよそ4倍になる
+pub(crate) const MAX_REFINE_LEVEL: u32 = 8;
+
+// このsharpness以上のcreaseとcornerは無限にsharpとして扱う
+const INFINITE_SHARPNESS: f32 = 10.0;
+
+/// subdivisionSchemeに従ってmeshをlevel回細分割する。
+/// levelが0の場合やsubdivisionSchemeがnoneの場合は、そのまま返す。
+///
+/// 細分割後のnormalsは形状に合わないので捨て、MeshDataで計算し直す。
+/// primvarはinterpolationを保ったまま細分割後のtopologyに合わせ、
+/// holeIndicesとgeomSubsetのfaceのindexも細分割後のfaceに振り直す。
+/// topologyが壊れている場合は細分割せずに返し、エラーはMeshDataの検証に任せる。
+pub(crate) fn refine(mut data: MeshDataDiff, level: u32) -> Result<MeshDataDiff, String> {
+    // subdivisionSchemeのfallbackはcatmullClark
+    let scheme = data
+        .subdivision_scheme
+        .unwrap_or(SubdivisionScheme::CatmullClark);
+    let (split, smooth) = match scheme {
+        SubdivisionScheme::CatmullClark => (Split::Quads, true),
+        SubdivisionScheme::Loop => (Split::Triangles, true),
+        SubdivisionScheme::Bilinear => (Split::Quads, false),
+        _ => return Ok(data),
+    };
+    let level = level.min(MAX_REFINE_LEVEL);
+    if level == 0 || !is_refinable(&data, split) {
+        return Ok(data);
+    }
+    let mut points = data.points.take().unwrap_or_default();
+    let face_vertex_indices = data.face_vertex_indices.take().unwrap_or_default();
+    let face_vertex_counts = data.face_vertex_counts.take().unwrap_or_default();
+    let point_count = points.len() / 3;
+    let face_count = face_vertex_counts.len();
+
+    let tags = &data.subdivision_tags;
+    let mut topology = Topology {
+        vertex_count: point_count,
+        face_counts: face_vertex_counts,
+        face_indices: face_vertex_indices,
+        edge_sharpness: crease_sharpness(tags, point_count),
+        vertex_sharpness: corner_sharpness(tags, point_count),
+    };
+    // interpolateBoundaryのfallbackはedgeAndCorner
+    let boundary_interpolation = tags
+        .interpolate_boundary
+        .unwrap_or(BoundaryInterpolation::EdgeAndCorner);
+    let rules = Rules {
+        boundary_corners: boundary_interpolation == BoundaryInterpolation::EdgeAndCorner,
+        pin_boundary: false,
+    };
+    // faceVaryingLinearInterpolationのfallbackはcornersPlus1
+    let face_varying_interpolation = tags
+        .face_varying_linear_interpolation
+        .unwrap_or(FaceVaryingLinearInterpolation::CornersPlus1);
+
+    let mut hole_faces = vec![false; face_count];
+    for &index in data.hole_indices.iter().flatten() {
+        let Some(hole_face) = hole_faces.get_mut(index as usize) else {
+            return Err(format!(
+                "holeIndices contains {index} but there are only {face_count} faces"
+            ));
+        };
+        *hole_face = true;
+    }
+    // interpolateBoundaryがnoneの場合、境界の頂点に接するfaceは描画しない
+    if boundary_interpolation == BoundaryInterpolation::None {
+        let adjacency = Adjacency::new(&topology);
+        let mut boundary_vertices = vec![false; point_count];
+        for (edge, &(a, b)) in adjacency.edges.iter().enumerate() {
+            if adjacency.is_boundary(edge) {
+                boundary_vertices[a as usize] = true;
+                boundary_vertices[b as usize] = true;
+            }
+        }
+        for (face, hole_face) in hole_faces.iter_mut().enumerate() {
+            if adjacency
+                .face(face)
+                .iter()
+                .any(|&v| boundary_vertices[v as usize])
+            {
+                *hole_face = true;
+            }
+        }
+    }
+
+    // uvsも2要素のprimvarとして他のprimvarと同じように細分割する
+    let mut uvs = match (data.uvs.take(), data.uvs_interpolation) {
+        (Some(values), Some(interpolation)) => {
+            let primvar = PrimvarData {
+                element_type: PrimvarType::Float2,
+                interpolation,
+                values,
+                indices: data.uvs_indices.take(),
+            };
+            Some(Channel::new(
+                "uvs",
+                primvar,
+                &topology,
+                smooth,
+                face_varying_interpolation,
+            )?)
+        }
+        (values, _) => {
+            data.uvs = values;
+            None
+        }
+    };
+    let mut primvars = Vec::with_capacity(data.primvars.len());
+    for (name, primvar) in std::mem::take(&mut data.primvars) {
+        let channel = Channel::new(
+            &name,
+            primvar,
+            &topology,
+            smooth,
+            face_varying_interpolation,
+        )?;
+        primvars.push((name, channel));
+    }
+
+    // 細分割後のfaceごとの、細分割前のmeshのface
+    let mut root_faces = (0..face_count as u32).collect::<Vec<_>>();
+    for _ in 0..level {
+        let step = subdivide(&topology, split, smooth, rules);
+        points = step.stencils.apply(&points, 3);
+        let channels = uvs
+            .iter_mut()
+            .chain(primvars.iter_mut().map(|(_, channel)| channel));
+        for channel in channels {
+            channel.refine(&step, split);
+        }
+        root_faces = step
+            .face_parents
+            .iter()
+            .map(|&parent| root_faces[parent as usize])
+            .collect();
+        topology = step.child;
+    }
+
+    if let Some(channel) = uvs {
+        let primvar = channel.finish(&root_faces);
+        data.uvs = Some(primvar.values);
+        data.uvs_indices = primvar.indices;
+    }
+    for (name, channel) in primvars {
+        data.primvars.insert(name, channel.finish(&root_faces));
+    }
+
+    let faces_of = |selected: &[bool]| {
+        root_faces
+            .iter()
+            .enumerate()
+            .filter(|&(_, &root)| selected[root as usize])
+            .map(|(face, _)| face as u32)
+            .collect::<Vec<_>>()
+    };
+    let hole_indices = faces_of(&hole_faces);
+    data.hole_indices = (!hole_indices.is_empty()).then_some(hole_indices);
+    for subset in data.geom_subsets.values_mut() {
+        if subset.indices_type != "typeFaceSet" {
+            continue;
+        }
+        let mut in_subset = vec![false; face_count];
+        for &index in &subset.indices {
+            if let Some(selected) = in_subset.get_mut(index as usize) {
+                *selected = true;
+            }
+        }
+        subset.indices = faces_of(&in_subset);
+    }
+
+    data.points = Some(points);
+    data.face_vertex_indices = Some(topology.face_indices);
+    data.face_vertex_counts = Some(topology.face_counts);
+    data.normals = None;
+    data.normals_indices = None;
+    data.normals_interpolation = None;
+    Ok(data)
+}
+
+// 細分割できるtopologyかどうか。
+// Loopは三角形のmeshにしか定義されないので、三角形以外を含む場合は細分割しない
+fn is_refinable(data: &MeshDataDiff, split: Split) -> bool {
+    let (Some(points), Some(face_vertex_indices), Some(face_vertex_counts)) = (
+        &data.points,
+        &data.face_vertex_indices,
+        &data.face_vertex_counts,
+    ) else {
+        return false;
+    };
+    let point_count = points.len() / 3;
+    let face_vertex_count_sum = face_vertex_counts
+        .iter()
+        .map(|&count| count as usize)
+        .sum::<usize>();
+    points.len().is_multiple_of(3)
+        && face_vertex_count_sum == face_vertex_indices.len()
+        && face_vertex_indices
+            .iter()
+            .all(|&index| (index as usize) < point_count)
+        && face_vertex_counts.iter().all(|&count| match split {
+            Split::Quads => count >= 3,
+            Split::Triangles => count == 3,
+        })
+}
+
+// creaseIndicesとcreaseLengthsから辺ごとのsharpnessを求める。
+// creaseSharpnessesはcreaseごと、または辺ごとに指定できる
+fn crease_sharpness(tags: &SubdivisionTags, point_count: usize) -> HashMap<(u32, u32), f32> {
+    let edge_count = tags
+        .crease_lengths
+        .iter()
+        .map(|&length| length.saturating_sub(1) as usize)
+        .sum::<usize>();
+    let per_edge = tags.crease_sharpnesses.len() != tags.crease_lengths.len()
+        && tags.crease_sharpnesses.len() == edge_count;
+
+    let mut edge_sharpness = HashMap::new();
+    let mut offset = 0;
+    let mut edge = 0;
+    for (crease, &length) in tags.crease_lengths.iter().enumerate() {
+        let length = length as usize;
+        let Some(chain) = tags.crease_indices.get(offset..offset + length) else {
+            break;
+        };
+        for pair in chain.windows(2) {
+            let sharpness = tags
+                .crease_sharpnesses
+                .get(if per_edge { edge } else { crease })
+                .copied()
+                .unwrap_or(0.0);
+            if sharpness > 0.0 && pair.iter().all(|&v| (v as usize) < point_count) {
+                edge_sharpness.insert(edge_key(pair[0], pair[1]), sharpness);
+            }
+            edge += 1;
+        }
+        offset += length;
+    }
+    edge_sharpness
+}
+
+// cornerIndicesとcornerSharpnessesから頂点ごとのsharpnessを求める
+fn corner_sharpness(tags: &SubdivisionTags, point_count: usize) -> Vec<f32> {
+    let mut vertex_sharpness = vec![0.0; point_count];
+    for (&index, &sharpness) in tags.corner_indices.iter().zip(&tags.corner_sharpnesses) {
+        if let Some(vertex) = vertex_sharpness.get_mut(index as usize) {
+            *vertex = sharpness;
+        }
+    }
+    vertex_sharpness
+}
+
+fn edge_key(a: u32, b: u32) -> (u32, u32) {
+    (a.min(b), a.max(b))
+}
+
+// 1段階の細分割でfaceをどう分割するか
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Split {
+    // n角形をn個の四角形に分割する。Catmull-ClarkとBilinear
+    Quads,
+    // 三角形を4つの三角形に分割する。Loop
+    Triangles,
+}
+
+// 境界の頂点の扱い
+#[derive(Debug, Clone, Copy, Default)]
+struct Rules {
+    // faceを1つしか持たない境界の頂点をcornerにする
+    boundary_corners: bool,
+    // 全ての境界の頂点をcornerにして、境界を線形に補間する
+    pin_boundary: bool,
+}
+
+// 細分割の各段階のmeshの接続情報
+#[derive(Debug, Clone)]
+struct Topology {
+    vertex_count: usize,
+    face_counts: Vec<u32>,
+    face_indices: Vec<u32>,
+    // 辺の(小さい方の頂点, 大きい方の頂点)ごとのcreaseのsharpness
+    edge_sharpness: HashMap<(u32, u32), f32>,
+    // 頂点ごとのcornerのsharpness
+    vertex_sharpness: Vec<f32>,
+}
+
+// Topologyから求めた辺と頂点とfaceの隣接情報
+struct Adjacency<'a> {
+    topology: &'a Topology,
+    face_offsets: Vec<usize>,
+    edges: Vec<(u32, u32)>,
+    // face_indicesと同じ並びで、faceのi番目の頂点からi+1番目の頂点への辺
+    face_edges: Vec<u32>,
+    edge_faces: Vec<Vec<u32>>,
+    vertex_edges: Vec<Vec<u32>>,
+    vertex_faces: Vec<Vec<u32>>,
+}
+impl<'a> Adjacency<'a> {
+    fn new(topology: &'a Topology) -> Self {
+        let mut face_offsets = Vec::with_capacity(topology.face_counts.len() + 1);
+        face_offsets.push(0);
+        for &count in &topology.face_counts {
+            face_offsets.push(face_offsets[face_offsets.len() - 1] + count as usize);
+        }
+
+        let mut edge_indices = HashMap::new();
+        let mut edges = Vec::new();
+        let mut face_edges = Vec::with_capacity(topology.face_indices.len());
+        let mut edge_faces = Vec::new();
+        let mut vertex_edges = vec![Vec::new(); topology.vertex_count];
+        let mut vertex_faces = vec![Vec::new(); topology.vertex_count];
+        for face in 0..topology.face_counts.len() {
+            let vertices = &topology.face_indices[face_offsets[face]..face_offsets[face + 1]];
+            for (i, &a) in vertices.iter().enumerate() {
+                let b = vertices[(i + 1) % vertices.len()];
+                let key = edge_key(a, b);
+                let edge = *edge_indices.entry(key).or_insert_with(|| {
+                    let edge = edges.len() as u32;
+                    edges.push(key);
+                    edge_faces.push(Vec::new());
+                    vertex_edges[a as usize].push(edge);
+                    vertex_edges[b as usize].push(edge);
+                    edge
+                });
+                edge_faces[edge as usize].push(face as u32);
+                face_edges.push(edge);
+                vertex_faces[a as usize].push(face as u32);
+            }
+        }
+
+        Self {
+            topology,
+            face_offsets,
+            edges,
+            face_edges,
+            edge_faces,
+            vertex_edges,
+            vertex_faces,
+        }
+    }
+
+    fn face(&self, face: usize) -> &'a [u32] {
+        &self.topology.face_indices[self.face_offsets[face]..self.face_offsets[face + 1]]
+    }
+
+    // 2つのfaceに共有されていない辺は境界
+    fn is_boundary(&self, edge: usize) -> bool {
+        self.edge_faces[edge].len() != 2
+    }
+
+    // 境界の辺は無限にsharpとして扱う
+    fn sharpness(&self, edge: usize) -> f32 {
+        if self.is_boundary(edge) {
+            INFINITE_SHARPNESS
+        } else {
+            let key = self.edges[edge];
+            self.topology
+                .edge_sharpness
+                .get(&key)
+                .copied()
+                .unwrap_or(0.0)
+        }
+    }
+
+    fn other_vertex(&self, edge: u32, vertex: u32) -> u32 {
+        let (a, b) = self.edges[edge as usize];
+        if a == vertex {
+            b
+        } else {
+            a
+        }
+    }
+
+    // faceの頂点の平均。Catmull-Clarkのface point
+    fn face_point(&self, face: usize) -> Stencil {
+        let vertices = self.face(face);
+        let weight = 1.0 / vertices.len() as f32;
+        vertices.iter().map(|&v| (v, weight)).collect()
+    }
+}
+
+// 細分割後の頂点を、細分割前の頂点の重み付き和で表したもの
+type Stencil = Vec<(u32, f32)>;
+
+// 2つのstencilをtで線形補間する
+fn blend(from: Stencil, to: Stencil, t: f32) -> Stencil {
+    let scaled = |stencil: Stencil, scale: f32| {
+        stencil
+            .into_iter()
+            .map(move |(index, weight)| (index, weight * scale))
+    };
+    scaled(from, 1.0 - t).chain(scaled(to, t)).collect()
+}
+
+// 細分割後の全ての頂点のstencil
+struct Stencils {
+    offsets: Vec<usize>,
+    weights: Vec<(u32, f32)>,
+}
+impl Stencils {
+    fn with_capacity(vertex_count: usize) -> Self {
+        let mut offsets = Vec::with_capacity(vertex_count + 1);
+        offsets.push(0);
+        Self {
+            offsets,
+            weights: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, stencil: impl IntoIterator<Item = (u32, f32)>) {
+        self.weights.extend(stencil);
+        self.offsets.push(self.weights.len());
+    }
+
+    // 1頂点あたりcomponents個のfloatが並ぶ値にstencilを適用する
+    fn apply(&self, values: &[f32], components: usize) -> Vec<f32> {
+        let mut result = vec![0.0; (self.offsets.len() - 1) * components];
+        for (i, output) in result.chunks_exact_mut(components).enumerate() {
+            for &(index, weight) in &self.weights[self.offsets[i]..self.offsets[i + 1]] {
+                let start = index as usize * components;
+                for (output, value) in output.iter_mut().zip(&values[start..start + components]) {
+                    *output += value * weight;
+                }
+            }
+        }
+        result
+    }
+}
+
+// 1段階の細分割の結果
+struct Step {
+    child: Topology,
+    // 細分割後の頂点をschemeの規則で求めるstencil
+    stencils: Stencils,
+    // 細分割後の頂点を線形補間で求めるstencil。varyingのprimvarに使う
+    linear: Stencils,
+    // 細分割後のfaceごとの、細分割前のface
+    face_parents: Vec<u32>,
+}
+
+// topologyを1段階細分割する。
+// 細分割後の頂点は、元の頂点のvertex point、辺のedge point、faceのface pointの順に並ぶ。
+// smoothがfalseの場合は、Bilinearのように全ての頂点を線形補間で求める
+fn subdivide(topology: &Topology, split: Split, smooth: bool, rules: Rules) -> Step {
+    let adjacency = Adjacency::new(topology);
+    let vertex_count = topology.vertex_count;
+    let edge_count = adjacency.edges.len();
+    let face_count = topology.face_counts.len();
+    let child_vertex_count = match split {
+        Split::Quads => vertex_count + edge_count + face_count,
+        Split::Triangles => vertex_count + edge_count,
+    };
+    let mut stencils = Stencils::with_capacity(child_vertex_count);
+    let mut linear = Stencils::with_capacity(child_vertex_count);
+
+    for vertex in 0..vertex_count as u32 {
+        if smooth {
+            stencils.push(vertex_point(&adjacency, split, rules, vertex));
+        } else {
+            stencils.push([(vertex, 1.0)]);
+        }
+        linear.push([(vertex, 1.0)]);
+    }
+    for (edge, &(a, b)) in adjacency.edges.iter().enumerate() {
+        let midpoint = vec![(a, 0.5), (b, 0.5)];
+        let sharpness = adjacency.sharpness(edge);
+        if smooth && sharpness < 1.0 {
+            stencils.push(blend(
+                smooth_edge_point(&adjacency, split, edge),
+                midpoint.clone(),
+                sharpness,
+            ));
+        } else {
+            stencils.push(midpoint.clone());
+        }
+        linear.push(midpoint);
+    }
+    if split == Split::Quads {
+        for face in 0..face_count {
+            let face_point = adjacency.face_point(face);
+            stencils.push(face_point.clone());
+            linear.push(face_point);
+        }
+    }
+
+    let edge_point = |edge: u32| vertex_count as u32 + edge;
+    let mut face_counts = Vec::new();
+    let mut face_indices = Vec::new();
+    let mut face_parents = Vec::new();
+    for face in 0..face_count {
+        let offset = adjacency.face_offsets[face];
+        let vertices = adjacency.face(face);
+        let edges = &adjacency.face_edges[offset..offset + vertices.len()];
+        match split {
+            Split::Quads => {
+                // 元のfaceの頂点ごとに、頂点と前後の辺の中点とfaceの中心を結ぶ四角形を作る
+                let face_point = (vertex_count + edge_count + face) as u32;
+                let n = vertices.len();
+                for (i, &vertex) in vertices.iter().enumerate() {
+                    face_indices.extend([
+                        vertex,
+                        edge_point(edges[i]),
+                        face_point,
+                        edge_point(edges[(i + n - 1) % n]),
+                    ]);
+                    face_counts.push(4);
+                    face_parents.push(face as u32);
+                }
+            }
+            Split::Triangles => {
+                let [a, b, c] = [vertices[0], vertices[1], vertices[2]];
+                let [ab, bc, ca] = [edges[0], edges[1], edges[2]].map(edge_point);
+                face_indices.extend([a, ab, ca, ab, b, bc, ca, bc, c, ab, bc, ca]);
+                face_counts.extend([3; 4]);
+                face_parents.extend([face as u32; 4]);
+            }
+        }
+    }
+
+    // creaseとcornerのsharpnessは細分割ごとに1ずつ減る。無限にsharpなものはそのまま
+    let decay = |sharpness: f32| {
+        if sharpness >= INFINITE_SHARPNESS {
+            sharpness
+        } else {
+            (sharpness - 1.0).max(0.0)
+        }
+    };
+    let mut edge_sharpness = HashMap::new();
+    for (edge, &(a, b)) in adjacency.edges.iter().enumerate() {
+        let Some(&sharpness) = topology.edge_sharpness.get(&(a, b)) else {
+            continue;
+        };
+        let sharpness = decay(sharpness);
+        if sharpness > 0.0 {
+            let edge_point = edge_point(edge as u32);
+            edge_sharpness.insert(edge_key(a, edge_point), sharpness);
+            edge_sharpness.insert(edge_key(b, edge_point), sharpness);
+        }
+    }
+    let mut vertex_sharpness = topology
+        .vertex_sharpness
+        .iter()
+        .map(|&sharpness| decay(sharpness))
+        .collect::<Vec<_>>();
+    vertex_sharpness.resize(child_vertex_count, 0.0);
+
+    Step {
+        child: Topology {
+            vertex_count: child_vertex_count,
+            face_counts,
+            face_indices,
+            edge_sharpness,
+            vertex_sharpness,
+        },
+        stencils,
+        linear,
+        face_parents,
+    }
+}
+
+// sharpでない辺のedge point
+fn smooth_edge_point(adjacency: &Adjacency, split: Split, edge: usize) -> Stencil {
+    let (a, b) = adjacency.edges[edge];
+    let faces = &adjacency.edge_faces[edge];
+    match split {
+        // 辺の両端とその辺を共有するfaceのface pointの平均
+        Split::Quads => {
+            let mut stencil = vec![(a, 0.25), (b, 0.25)];
+            for &face in faces {
+                stencil.extend(
+                    adjacency
+                        .face_point(face as usize)
+                        .into_iter()
+                        .map(|(index, weight)| (index, weight * 0.25)),
+                );
+            }
+            stencil
+        }
+        // 辺の両端に3/8、辺の向かいの頂点に1/8
+        Split::Triangles => {
+            let mut stencil = vec![(a, 0.375), (b, 0.375)];
+            for &face in faces {
+                let opposite = adjacency
+                    .face(face as usize)
+                    .iter()
+                    .find(|&&v| v != a && v != b);
+                if let Some(&opposite) = opposite {
+                    stencil.push((opposite, 0.125));
+                }
+            }
+            stencil
+        }
+    }
+}
+
+// vertex pointのstencil。接続するsharpな辺の数とcornerのsharpnessで規則を選ぶ。
+// sharpnessが1未満の場合は、sharpな規則とsmoothな規則をsharpnessで補間する
+fn vertex_point(adjacency: &Adjacency, split: Split, rules: Rules, vertex: u32) -> Stencil {
+    let corner = vec![(vertex, 1.0)];
+    let faces = &adjacency.vertex_faces[vertex as usize];
+    let edges = &adjacency.vertex_edges[vertex as usize];
+    if faces.is_empty() {
+        return corner;
+    }
+
+    let sharp_edges = edges
+        .iter()
+        .map(|&edge| {
+            (
+                adjacency.other_vertex(edge, vertex),
+                adjacency.sharpness(edge as usize),
+            )
+        })
+        .filter(|&(_, sharpness)| sharpness > 0.0)
+        .collect::<Vec<_>>();
+    let on_boundary = edges
+        .iter()
+        .any(|&edge| adjacency.is_boundary(edge as usize));
+    let mut vertex_sharpness = adjacency.topology.vertex_sharpness[vertex as usize];
+    if on_boundary && (rules.pin_boundary || (rules.boundary_corners && faces.len() == 1)) {
+        vertex_sharpness = INFINITE_SHARPNESS;
+    }
+
+    // sharpな辺が2本ならcreaseの規則、3本以上ならcornerの規則
+    let edge_rule = match sharp_edges[..] {
+        [] | [_] => None,
+        [(a, sharpness_a), (b, sharpness_b)] => Some((
+            vec![(vertex, 0.75), (a, 0.125), (b, 0.125)],
+            (sharpness_a + sharpness_b) / 2.0,
+        )),
+        _ => {
+            let sharpness = sharp_edges
+                .iter()
+                .map(|&(_, sharpness)| sharpness)
+                .sum::<f32>()
+                / sharp_edges.len() as f32;
+            Some((corner.clone(), sharpness))
+        }
+    };
+    let stencil = match edge_rule {
+        Some((rule, sharpness)) if sharpness >= 1.0 => rule,
+        Some((rule, sharpness)) => blend(
+            smooth_vertex_point(adjacency, split, vertex),
+            rule,
+            sharpness,
+        ),
+        None => smooth_vertex_point(adjacency, split, vertex),
+    };
+    if vertex_sharpness >= 1.0 {
+        corner
+    } else if vertex_sharpness > 0.0 {
+        blend(stencil, corner, vertex_sharpness)
+    } else {
+        stencil
+    }
+}
+
+// sharpな辺やcornerに接続しない頂点のvertex point
+fn smooth_vertex_point(adjacency: &Adjacency, split: Split, vertex: u32) -> Stencil {
+    let edges = &adjacency.vertex_edges[vertex as usize];
+    let valence = edges.len() as f32;
+    match split {
+        // (Q + 2R + (n - 3)P) / n。
+        // Qは周囲のface pointの平均、Rは周囲の辺の中点の平均、nは頂点の次数
+        Split::Quads => {
+            let faces = &adjacency.vertex_faces[vertex as usize];
+            let face_weight = 1.0 / (faces.len() as f32 * valence);
+            let edge_weight = 1.0 / (valence * valence);
+            let mut stencil = vec![(vertex, (valence - 3.0) / valence)];
+            for &face in faces {
+                stencil.extend(
+                    adjacency
+                        .face_point(face as usize)
+                        .into_iter()
+                        .map(|(index, weight)| (index, weight * face_weight)),
+                );
+            }
+            for &edge in edges {
+                let (a, b) = adjacency.edges[edge as usize];
+                stencil.extend([(a, edge_weight), (b, edge_weight)]);
+            }
+            stencil
+        }
+        // 周囲の頂点にβ、自身に1 - nβ
+        Split::Triangles => {
+            let beta = if edges.len() == 3 {
+                3.0 / 16.0
+            } else {
+                3.0 / (8.0 * valence)
+            };
+            let mut stencil = vec![(vertex, 1.0 - valence * beta)];
+            for &edge in edges {
+                stencil.push((adjacency.other_vertex(edge, vertex), beta));
+            }
+            stencil
+        }
+    }
+}
+
+// 細分割するprimvarのデータ
+enum ChannelData {
+    // schemeの規則で補間する頂点ごとの値
+    Vertex(Vec<f32>),
+    // 線形に補間する頂点ごとの値
+    Varying(Vec<f32>),
+    // face-varyingの値と、値のindexを頂点とするTopology
+    FaceVarying {
+        values: Vec<f32>,
+        topology: Topology,
+        smooth: bool,
+        rules: Rules,
+    },
+    // faceごとの値のindex。細分割後のfaceは元のfaceの値を引き継ぐ
+    Uniform {
+        values: Vec<f32>,
+        indices: Vec<u32>,
+    },
+    // constantなど、細分割によらない値
+    Constant {
+        values: Vec<f32>,
+        indices: Option<Vec<u32>>,
+    },
+}
+
+struct Channel {
+    element_type: PrimvarType,
+    interpolation: Interpolation,
+    components: usize,
+    data: ChannelData,
+}
+impl Channel {
+    fn new(
+        name: &str,
+        primvar: PrimvarData,
+        geometry: &Topology,
+        smooth: bool,
+        face_varying_interpolation: FaceVaryingLinearInterpolation,
+    ) -> Result<Self, String> {
+        let PrimvarData {
+            element_type,
+            interpolation,
+            values,
+            indices,
+        } = primvar;
+        let components = element_type.component_count();
+        if !values.len().is_multiple_of(components) {
+            return Err(format!(
+                "{name} length {} is not a multiple of {components}",
+                values.len()
+            ));
+        }
+        // indexed primvarを展開し、要素ごとの値のindexにする
+        let element_count = values.len() / components;
+        let required = interpolation_element_count(
+            interpolation,
+            geometry.vertex_count,
+            geometry.face_counts.len(),
+            geometry.face_indices.len(),
+        );
+        let element_indices = resolve_indexed_primvar(
+            name,
+            &(0..element_count as u32).collect::<Vec<_>>(),
+            indices.as_deref(),
+            interpolation,
+            required,
+        )?;
+        let element_indices = &element_indices[..required];
+        let gather = || {
+            element_indices
+                .iter()
+                .flat_map(|&index| {
+                    let start = index as usize * components;
+                    values[start..start + components].iter().copied()
+                })
+                .collect::<Vec<_>>()
+        };
+        let data = match interpolation {
+            Interpolation::Vertex => ChannelData::Vertex(gather()),
+            Interpolation::Varying => ChannelData::Varying(gather()),
+            Interpolation::FaceVarying => {
+                let (rules, face_varying_smooth) = face_varying_rules(face_varying_interpolation);
+                ChannelData::FaceVarying {
+                    topology: face_varying_topology(
+                        geometry,
+                        element_indices,
+                        element_count,
+                        face_varying_interpolation,
+                    ),
+                    values,
+                    smooth: smooth && face_varying_smooth,
+                    rules,
+                }
+            }
+            Interpolation::Uniform => ChannelData::Uniform {
+                indices: element_indices.to_vec(),
+                values,
+            },
+            _ => ChannelData::Constant { values, indices },
+        };
+        Ok(Self {
+            element_type,
+            interpolation,
+            components,
+            data,
+        })
+    }
+
+    fn refine(&mut self, step: &Step, split: Split) {
+        match &mut self.data {
+            ChannelData::Vertex(values) => *values = step.stencils.apply(values, self.components),
+            ChannelData::Varying(values) => *values = step.linear.apply(values, self.components),
+            ChannelData::FaceVarying {
+                values,
+                topology,
+                smooth,
+                rules,
+            } => {
+                let step = subdivide(topology, split, *smooth, *rules);
+                *values = step.stencils.apply(values, self.components);
+                *topology = step.child;
+            }
+            ChannelData::Uniform { .. } | ChannelData::Constant { .. } => {}
+        }
+    }
+
+    // 細分割後の値とindicesをprimvarに戻す
+    fn finish(self, root_faces: &[u32]) -> PrimvarData {
+        let (values, indices) = match self.data {
+            ChannelData::Vertex(values) | ChannelData::Varying(values) => (values, None),
+            ChannelData::FaceVarying {
+                values, topology, ..
+            } => (values, Some(topology.face_indices)),
+            ChannelData::Uniform { values, indices } => {
+                let indices = root_faces
+                    .iter()
+                    .map(|&face| indices[face as usize])
+                    .collect();
+                (values, Some(indices))
+            }
+            ChannelData::Constant { values, indices } => (values, indices),
+        };
+        PrimvarData {
+            element_type: self.element_type,
+            interpolation: self.interpolation,
+            values,
+            indices,
+        }
+    }
+}
+
+// faceVaryingLinearInterpolationごとの境界の扱いと、smoothに補間するかどうか
+fn face_varying_rules(interpolation: FaceVaryingLinearInterpolation) -> (Rules, bool) {
+    match interpolation {
+        FaceVaryingLinearInterpolation::None => (Rules::default(), true),
+        FaceVaryingLinearInterpolation::CornersOnly
+        | FaceVaryingLinearInterpolation::CornersPlus1
+        | FaceVaryingLinearInterpolation::CornersPlus2 => (
+            Rules {
+                boundary_corners: true,
+                pin_boundary: false,
+            },
+            true,
+        ),
+        FaceVaryingLinearInterpolation::Boundaries => (
+            Rules {
+                boundary_corners: false,
+                pin_boundary: true,
+            },
+            true,
+        ),
+        _ => (Rules::default(), false),
+    }
+}
+
+// face-varyingの値のindexを頂点とするTopologyを作る。
+// 値が分かれているseamは境界の辺になり、geometryのcreaseとcornerは引き継ぐ。
+// cornersPlus1とcornersPlus2では3つ以上の値に分かれる頂点(junction)も固定する。
+// cornersPlus2の凹なcornerの扱いは省略し、cornersPlus1と同じに扱う
+fn face_varying_topology(
+    geometry: &Topology,
+    indices: &[u32],
+    value_count: usize,
+    interpolation: FaceVaryingLinearInterpolation,
+) -> Topology {
+    let mut edge_sharpness = HashMap::new();
+    let mut vertex_sharpness = vec![0.0; value_count];
+    let mut vertex_values = vec![Vec::new(); geometry.vertex_count];
+    let mut offset = 0;
+    for &count in &geometry.face_counts {
+        let count = count as usize;
+        for i in 0..count {
+            let current = offset + i;
+            let next = offset + (i + 1) % count;
+            let vertex = geometry.face_indices[current];
+            let key = edge_key(vertex, geometry.face_indices[next]);
+            if let Some(&sharpness) = geometry.edge_sharpness.get(&key) {
+                edge_sharpness.insert(edge_key(indices[current], indices[next]), sharpness);
+            }
+            let value = indices[current];
+            let sharpness = &mut vertex_sharpness[value as usize];
+            *sharpness = geometry.vertex_sharpness[vertex as usize].max(*sharpness);
+            let values: &mut Vec<u32> = &mut vertex_values[vertex as usize];
+            if !values.contains(&value) {
+                values.push(value);
+            }
+        }
+        offset += count;
+    }
+    let pin_junctions = matches!(
+        interpolation,
+        FaceVaryingLinearInterpolation::CornersPlus1 | FaceVaryingLinearInterpolation::CornersPlus2
+    );
+    if pin_junctions {
+        for values in vertex_values.iter().filter(|values| values.len() >= 3) {
+            for &value in values {
+                vertex_sharpness[value as usize] = INFINITE_SHARPNESS;
+            }
+        }
+    }
+
+    Topology {
+        vertex_count: value_count,
+        face_counts: geometry.face_counts.clone(),
+        face_indices: indices.to_vec(),
+        edge_sharpness,
+        vertex_sharpness,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bridge::SubMeshData;
+
+    // 原点を中心とする一辺2の立方体
+    fn cube() -> MeshDataDiff {
+        MeshDataDiff {
+            points: Some(vec![
+                -1.0, -1.0, -1.0, 1.0, -1.0, -1.0, 1.0, 1.0, -1.0, -1.0, 1.0, -1.0, //
+                -1.0, -1.0, 1.0, 1.0, -1.0, 1.0, 1.0, 1.0, 1.0, -1.0, 1.0, 1.0,
+            ]),
+            face_vertex_indices: Some(vec![
+                0, 3, 2, 1, 4, 5, 6, 7, 0, 1, 5, 4, 1, 2, 6, 5, 2, 3, 7, 6, 3, 0, 4, 7,
+            ]),
+            face_vertex_counts: Some(vec![4; 6]),
+            ..Default::default()
+        }
+    }
+
+    // XY平面上に並んだ2つの四角形
+    fn two_quads() -> MeshDataDiff {
+        MeshDataDiff {
+            points: Some(vec![
+                0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 2.0, 0.0, 0.0, //
+                0.0, 1.0, 0.0, 1.0, 1.0, 0.0, 2.0, 1.0, 0.0,
+            ]),
+            face_vertex_indices: Some(vec![0, 1, 4, 3, 1, 2, 5, 4]),
+            face_vertex_counts: Some(vec![4, 4]),
+            ..Default::default()
+        }
+    }
+
+    fn points(data: &MeshDataDiff) -> Vec<[f32; 3]> {
+        data.points
+            .as_ref()
+            .unwrap()
+            .chunks_exact(3)
+            .map(|p| [p[0], p[1], p[2]])
+            .collect()
+    }
+
+    #[test]
+    fn catmull_clark_cube() {
+        let refined = refine(cube(), 1).unwrap();
+        // 8頂点 + 12辺 + 6face
+        assert_eq!(points(&refined).len(), 26);
+        assert_eq!(refined.face_vertex_counts.as_deref(), Some(&[4; 24][..]));
+
+        let refined = refine(cube(), 2).unwrap();
+        assert_eq!(points(&refined).len(), 98);
+        assert_eq!(refined.face_vertex_counts.as_ref().unwrap().len(), 96);
+        // smoothな立方体は角が丸まって元の立方体の内側に縮む
+        for point in points(&refined) {
+            assert!(point.iter().all(|p| p.abs() < 1.0));
+        }
+    }
+
+    #[test]
+    fn infinitely_sharp_creases_keep_cube_shape() {
+        let mut cube = cube();
+        // 上下の面の周囲を1本ずつのcreaseで、側面の縦の辺を4本のcreaseで囲む
+        cube.subdivision_tags.crease_indices =
+            vec![0, 1, 2, 3, 0, 4, 5, 6, 7, 4, 0, 4, 1, 5, 2, 6, 3, 7];
+        cube.subdivision_tags.crease_lengths = vec![5, 5, 2, 2, 2, 2];
+        cube.subdivision_tags.crease_sharpnesses = vec![INFINITE_SHARPNESS; 6];
+        let refined = refine(cube, 2).unwrap();
+        let points = points(&refined);
+        // 全ての辺がsharpなので、角の頂点は動かず全ての頂点は立方体の表面に残る
+        for corner in &points[..8] {
+            assert!(corner.iter().all(|p| p.abs() == 1.0));
+        }
+        for point in &points {
+            assert!(point.iter().any(|p| (p.abs() - 1.0).abs() < 1e-6));
+        }
+    }
+
+    #[test]
+    fn loop_splits_triangles() {
+        let triangle = MeshDataDiff {
+            points: Some(vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0]),
+            face_vertex_indices: Some(vec![0, 1, 2]),
+            face_vertex_counts: Some(vec![3]),
+            subdivision_scheme: Some(SubdivisionScheme::Loop),
+            ..Default::default()
+        };
+        let refined = refine(triangle, 1).unwrap();
+        let points = points(&refined);
+        assert_eq!(points.len(), 6);
+        assert_eq!(refined.face_vertex_counts, Some(vec![3; 4]));
+        // edgeAndCornerでは1つのfaceにしか接しない角は動かない
+        assert_eq!(&points[..3], [[0.0; 3], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]);
+
+        // Loopは四角形を含むmeshには定義されないのでそのまま返す
+        let mut quads = two_quads();
+        quads.subdivision_scheme = Some(SubdivisionScheme::Loop);
+        let refined = refine(quads, 1).unwrap();
+        assert_eq!(refined.face_vertex_counts, Some(vec![4, 4]));
+    }
+
+    #[test]
+    fn none_scheme_and_zero_level_are_not_refined() {
+        let refined = refine(cube(), 0).unwrap();
+        assert_eq!(points(&refined).len(), 8);
+
+        let mut cube = cube();
+        cube.subdivision_scheme = Some(SubdivisionScheme::None);
+        let refined = refine(cube, 2).unwrap();
+        assert_eq!(points(&refined).len(), 8);
+    }
+
+    #[test]
+    fn faces_and_primvars_follow_refined_faces() {
+        let mut quads = two_quads();
+        quads.hole_indices = Some(vec![1]);
+        quads.geom_subsets.insert(
+            "left".to_string(),
+            SubMeshData {
+                indices_type: "typeFaceSet".to_string(),
+                indices: vec![0],
+                material_path: None,
+            },
+        );
+        quads.primvars.insert(
+            "faceId".to_string(),
+            PrimvarData {
+                element_type: PrimvarType::Float,
+                interpolation: Interpolation::Uniform,
+                values: vec![10.0, 20.0],
+                indices: None,
+            },
+        );
+        // 各faceが別々のUVを持つので、2つのfaceの間はseamになる
+        quads.uvs = Some(vec![
+            0.0, 0.0, 1.0, 0.0, 1.0, 1.0, 0.0, 1.0, //
+            0.0, 0.0, 1.0, 0.0, 1.0, 1.0, 0.0, 1.0,
+        ]);
+        quads.uvs_indices = Some((0..8).collect());
+        quads.uvs_interpolation = Some(Interpolation::FaceVarying);
+        quads.subdivision_tags.face_varying_linear_interpolation =
+            Some(FaceVaryingLinearInterpolation::Boundaries);
+
+        let refined = refine(quads, 1).unwrap();
+        assert_eq!(refined.hole_indices, Some(vec![4, 5, 6, 7]));
+        assert_eq!(refined.geom_subsets["left"].indices, vec![0, 1, 2, 3]);
+        let face_id = &refined.primvars["faceId"];
+        assert_eq!(face_id.indices, Some(vec![0, 0, 0, 0, 1, 1, 1, 1]));
+        assert_eq!(face_id.values, vec![10.0, 20.0]);
+
+        // boundariesでは境界が線形に補間されるので、UVは元の正方形をそのまま分割した格子になる
+        assert!(refined.normals.is_none());
+        let uvs = refined.uvs.unwrap();
+        let uvs_indices = refined.uvs_indices.unwrap();
+        assert_eq!(uvs_indices.len(), 32);
+        for &index in &uvs_indices {
+            let uv = &uvs[index as usize * 2..index as usize * 2 + 2];
+            assert!(uv.iter().all(|&c| c == 0.0 || c == 0.5 || c == 1.0));
+        }
+    }
+}
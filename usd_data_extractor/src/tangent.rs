@@ -0,0 +1,95 @@
+use bevy_mikktspace::Geometry;
+use glam::Vec4;
+
+use crate::Vertex;
+
+/// 三角形の頂点のindexのリストから、MikkTSpaceのtangentを頂点ごとに求める。
+/// wはbitangentの向きの符号で、bitangentはcross(normal, tangent) * wになる。
+/// 三角形に使われない頂点のtangentはゼロのまま。生成できなかった場合はNoneを返す。
+pub(crate) fn generate_tangents(vertices: &[Vertex], triangles: &[u32]) -> Option<Vec<Vec4>> {
+    let mut geometry = TangentGeometry {
+        vertices,
+        triangles,
+        tangents: vec![Vec4::ZERO; vertices.len()],
+    };
+    bevy_mikktspace::generate_tangents(&mut geometry).then_some(geometry.tangents)
+}
+
+// MikkTSpaceに三角形のリストとしてmeshを渡すためのadapter
+struct TangentGeometry<'a> {
+    vertices: &'a [Vertex],
+    triangles: &'a [u32],
+    tangents: Vec<Vec4>,
+}
+impl TangentGeometry<'_> {
+    fn vertex_index(&self, face: usize, vert: usize) -> usize {
+        self.triangles[face * 3 + vert] as usize
+    }
+
+    fn vertex(&self, face: usize, vert: usize) -> &Vertex {
+        &self.vertices[self.vertex_index(face, vert)]
+    }
+}
+impl Geometry for TangentGeometry<'_> {
+    fn num_faces(&self) -> usize {
+        self.triangles.len() / 3
+    }
+
+    fn num_vertices_of_face(&self, _face: usize) -> usize {
+        3
+    }
+
+    fn position(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.vertex(face, vert).position.to_array()
+    }
+
+    fn normal(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.vertex(face, vert).normal.to_array()
+    }
+
+    fn tex_coord(&self, face: usize, vert: usize) -> [f32; 2] {
+        self.vertex(face, vert).uv.to_array()
+    }
+
+    // 同じ頂点を共有する三角形には同じtangentが計算されるので、後から書き込んだ値を使う
+    fn set_tangent_encoded(&mut self, tangent: [f32; 4], face: usize, vert: usize) {
+        let index = self.vertex_index(face, vert);
+        self.tangents[index] = Vec4::from_array(tangent);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::{Vec2, Vec3};
+
+    // XY平面上の正方形で、UVは頂点のx座標とy座標をu_sign倍したもの
+    fn quad(u_sign: f32) -> Vec<Vertex> {
+        [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)]
+            .into_iter()
+            .map(|(x, y)| Vertex {
+                position: Vec3::new(x, y, 0.0),
+                normal: Vec3::Z,
+                uv: Vec2::new(x * u_sign, y),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn tangent_follows_u_direction() {
+        let tangents = generate_tangents(&quad(1.0), &[0, 1, 2, 0, 2, 3]).unwrap();
+        for tangent in tangents {
+            assert!(tangent.truncate().abs_diff_eq(Vec3::X, 1e-5));
+            assert_eq!(tangent.w, 1.0);
+        }
+    }
+
+    #[test]
+    fn mirrored_uvs_flip_the_sign() {
+        let tangents = generate_tangents(&quad(-1.0), &[0, 1, 2, 0, 2, 3]).unwrap();
+        for tangent in tangents {
+            assert!(tangent.truncate().abs_diff_eq(-Vec3::X, 1e-5));
+            assert_eq!(tangent.w, -1.0);
+        }
+    }
+}
@@ -0,0 +1,237 @@
+use glam::{Vec2, Vec3};
+
+/// faceを三角形に分割し、face内での頂点の位置(0..face.len())の組を返す。
+/// 三角形の頂点の並びはfaceの頂点の並びと同じ向きになる。
+///
+/// 三角形と凸な四角形はfanで分割し、それ以外はfaceを最もよく当てはまる平面に投影して
+/// ear clippingで分割する。凹なn-gonや平面でないn-gonでも三角形が重なったり裏返ったりしない。
+pub(crate) fn triangulate_face(points: &[Vec3], face: &[u32]) -> Vec<[usize; 3]> {
+    let count = face.len();
+    let fan = || (2..count).map(|i| [0, i - 1, i]).collect();
+    if count <= 3 {
+        return fan();
+    }
+
+    let positions = face
+        .iter()
+        .map(|&index| points[index as usize])
+        .collect::<Vec<_>>();
+    let normal = newell_normal(&positions);
+    if !normal.is_finite() || normal.length_squared() == 0.0 {
+        // 面積のない縮退したfaceは分割しても見えないので、fanのままにする
+        return fan();
+    }
+    let normal = normal.normalize();
+    if count == 4 && is_convex(&positions, normal) {
+        return fan();
+    }
+
+    // normalを法線とする平面の正規直交基底に投影する。
+    // (u, v, normal)は右手系なので、faceの頂点の並びは投影後に反時計回りになる
+    let u = normal.any_orthonormal_vector();
+    let v = normal.cross(u);
+    let projected = positions
+        .iter()
+        .map(|p| Vec2::new(p.dot(u), p.dot(v)))
+        .collect::<Vec<_>>();
+    ear_clip(&projected)
+}
+
+// Newellの方法でfaceの法線を求める。平面でないfaceでも最もよく当てはまる平面の法線になる
+fn newell_normal(positions: &[Vec3]) -> Vec3 {
+    let mut normal = Vec3::ZERO;
+    for (i, &current) in positions.iter().enumerate() {
+        let next = positions[(i + 1) % positions.len()];
+        normal += (current - next).cross(current + next);
+    }
+    normal
+}
+
+// すべての角がnormalに対して同じ向きに曲がっていれば凸
+fn is_convex(positions: &[Vec3], normal: Vec3) -> bool {
+    let count = positions.len();
+    (0..count).all(|i| {
+        let p0 = positions[i];
+        let p1 = positions[(i + 1) % count];
+        let p2 = positions[(i + 2) % count];
+        (p1 - p0).cross(p2 - p1).dot(normal) > 0.0
+    })
+}
+
+// 2次元に投影したpolygonをear clippingで三角形に分割する
+fn ear_clip(points: &[Vec2]) -> Vec<[usize; 3]> {
+    let count = points.len();
+    let signed_area = (0..count)
+        .map(|i| points[i].perp_dot(points[(i + 1) % count]))
+        .sum::<f32>();
+    let orientation = if signed_area < 0.0 { -1.0 } else { 1.0 };
+
+    let mut remaining = (0..count).collect::<Vec<_>>();
+    let mut triangles = Vec::with_capacity(count - 2);
+    while remaining.len() > 3 {
+        let len = remaining.len();
+        let corner = |i: usize| {
+            (
+                remaining[(i + len - 1) % len],
+                remaining[i],
+                remaining[(i + 1) % len],
+            )
+        };
+        // 自己交差しているpolygonなどでearが見つからない場合は、
+        // 必ず終了するように先頭の頂点を切り落とす
+        let ear = (0..len)
+            .find(|&i| {
+                let (prev, current, next) = corner(i);
+                is_ear(points, &remaining, prev, current, next, orientation)
+            })
+            .unwrap_or(0);
+        let (prev, current, next) = corner(ear);
+        triangles.push([prev, current, next]);
+        remaining.remove(ear);
+    }
+    triangles.push([remaining[0], remaining[1], remaining[2]]);
+    triangles
+}
+
+// prev, current, nextの三角形が凸な角で、他の頂点を内側に含まなければear
+fn is_ear(
+    points: &[Vec2],
+    remaining: &[usize],
+    prev: usize,
+    current: usize,
+    next: usize,
+    orientation: f32,
+) -> bool {
+    let (a, b, c) = (points[prev], points[current], points[next]);
+    if (b - a).perp_dot(c - b) * orientation <= 0.0 {
+        return false;
+    }
+    remaining.iter().all(|&index| {
+        let p = points[index];
+        // 三角形の頂点と同じ位置にある頂点は内側とはみなさない
+        index == prev
+            || index == current
+            || index == next
+            || p == a
+            || p == b
+            || p == c
+            || !contains(a, b, c, p, orientation)
+    })
+}
+
+// pが三角形abcの内側か辺上にあるか
+fn contains(a: Vec2, b: Vec2, c: Vec2, p: Vec2, orientation: f32) -> bool {
+    (b - a).perp_dot(p - a) * orientation >= 0.0
+        && (c - b).perp_dot(p - b) * orientation >= 0.0
+        && (a - c).perp_dot(p - c) * orientation >= 0.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn polygon_area(points: &[Vec2]) -> f32 {
+        let count = points.len();
+        (0..count)
+            .map(|i| points[i].perp_dot(points[(i + 1) % count]))
+            .sum::<f32>()
+            / 2.0
+    }
+
+    // 三角形分割の結果が元のpolygonを重なりも裏返りもなく覆っていることを確認する。
+    // 全ての三角形がpolygonと同じ向きで、面積の合計がpolygonの面積と一致すればよい
+    fn assert_valid_triangulation(points_2d: &[Vec2]) {
+        let points = points_2d
+            .iter()
+            .map(|p| Vec3::new(p.x, p.y, 0.0))
+            .collect::<Vec<_>>();
+        let face = (0..points.len() as u32).collect::<Vec<_>>();
+        let triangles = triangulate_face(&points, &face);
+        assert_eq!(triangles.len(), points.len() - 2);
+
+        let area = polygon_area(points_2d);
+        let mut triangle_area_sum = 0.0;
+        for [a, b, c] in triangles {
+            let triangle_area = polygon_area(&[points_2d[a], points_2d[b], points_2d[c]]);
+            assert!(
+                triangle_area * area.signum() > 0.0,
+                "triangle {a}, {b}, {c} is inverted or degenerate"
+            );
+            triangle_area_sum += triangle_area;
+        }
+        assert!((triangle_area_sum - area).abs() < 1e-4);
+    }
+
+    #[test]
+    fn triangle_and_convex_quad_use_fan() {
+        let points = [Vec3::ZERO, Vec3::X, Vec3::X + Vec3::Y, Vec3::Y];
+        assert_eq!(triangulate_face(&points, &[0, 1, 2]), vec![[0, 1, 2]]);
+        assert_eq!(
+            triangulate_face(&points, &[0, 1, 2, 3]),
+            vec![[0, 1, 2], [0, 2, 3]]
+        );
+    }
+
+    #[test]
+    fn concave_quad() {
+        // fanだと0番目と2番目の頂点を結ぶ対角線がfaceの外に出る矢じり型
+        assert_valid_triangulation(&[
+            Vec2::new(2.0, 0.0),
+            Vec2::new(1.0, 2.0),
+            Vec2::new(0.0, 0.0),
+            Vec2::new(1.0, 0.5),
+        ]);
+    }
+
+    #[test]
+    fn l_shaped_face() {
+        let l_shape = [
+            Vec2::new(1.0, 1.0),
+            Vec2::new(1.0, 2.0),
+            Vec2::new(0.0, 2.0),
+            Vec2::new(0.0, 0.0),
+            Vec2::new(2.0, 0.0),
+            Vec2::new(2.0, 1.0),
+        ];
+        assert_valid_triangulation(&l_shape);
+        // 時計回りでも同じように分割できる
+        let mut reversed = l_shape;
+        reversed.reverse();
+        assert_valid_triangulation(&reversed);
+    }
+
+    #[test]
+    fn star_shaped_face() {
+        // 凹な頂点から始まる5芒星
+        let star = (0..10)
+            .map(|i| {
+                let radius = if i % 2 == 0 { 0.4 } else { 1.0 };
+                let angle = i as f32 * std::f32::consts::PI / 5.0;
+                Vec2::new(angle.cos(), angle.sin()) * radius
+            })
+            .collect::<Vec<_>>();
+        assert_valid_triangulation(&star);
+    }
+
+    #[test]
+    fn non_planar_face_is_projected_to_best_fit_plane() {
+        // XZ平面に近い、少しだけ歪んだL字型
+        let points = [
+            Vec3::new(1.0, 0.05, 1.0),
+            Vec3::new(1.0, -0.05, 2.0),
+            Vec3::new(0.0, 0.05, 2.0),
+            Vec3::new(0.0, -0.05, 0.0),
+            Vec3::new(2.0, 0.05, 0.0),
+            Vec3::new(2.0, -0.05, 1.0),
+        ];
+        let face = [0, 1, 2, 3, 4, 5];
+        let triangles = triangulate_face(&points, &face);
+        assert_eq!(triangles.len(), 4);
+        // 投影した平面上では全ての三角形がfaceと同じ向きになる
+        let normal = newell_normal(&points).normalize();
+        for [a, b, c] in triangles {
+            let triangle_normal = (points[b] - points[a]).cross(points[c] - points[a]);
+            assert!(triangle_normal.dot(normal) > 0.0);
+        }
+    }
+}
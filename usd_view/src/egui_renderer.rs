@@ -1,4 +1,6 @@
 use std::sync::Arc;
+use std::time::Instant;
+use usd_data_extractor::{StageMetadata, UpAxis, VariantSet};
 use winit::window::Window;
 
 pub struct EguiRenderer {
@@ -7,6 +9,11 @@ pub struct EguiRenderer {
     egui_context: egui::Context,
     egui_state: egui_winit::State,
     egui_renderer: egui_wgpu::Renderer,
+
+    // time codeの再生状態
+    playing: bool,
+    looping: bool,
+    last_frame_instant: Option<Instant>,
 }
 impl EguiRenderer {
     pub fn new(
@@ -31,6 +38,10 @@ impl EguiRenderer {
             egui_context,
             egui_state,
             egui_renderer,
+
+            playing: false,
+            looping: true,
+            last_frame_instant: None,
         }
     }
 
@@ -48,14 +59,24 @@ impl EguiRenderer {
         view: &wgpu::TextureView,
         encoder: &mut wgpu::CommandEncoder,
         usd_filename: &mut String,
-        time_code: &mut i64,
-        time_code_range: std::ops::RangeInclusive<i64>,
+        time_code: &mut f64,
+        time_code_range: std::ops::RangeInclusive<f64>,
         load_button_clicked: &mut bool,
+        stage_metadata: Option<StageMetadata>,
         render_settings_paths: Vec<String>,
         render_settings_path: &mut Option<String>,
         render_product_paths: Vec<String>,
         render_product_path: &mut Option<String>,
+        variant_sets: &mut [(String, Vec<VariantSet>)],
     ) {
+        let time_codes_per_second = stage_metadata
+            .as_ref()
+            .map(|metadata| metadata.time_codes_per_second)
+            .unwrap_or(StageMetadata::default().time_codes_per_second);
+        self.advance_time_code(time_code, &time_code_range, time_codes_per_second);
+        let playing = &mut self.playing;
+        let looping = &mut self.looping;
+
         let raw_input = self.egui_state.take_egui_input(window);
 
         let full_output = self.egui_context.run(raw_input, |ui| {
@@ -67,6 +88,16 @@ impl EguiRenderer {
                         *load_button_clicked = true;
                     }
                 });
+                if let Some(metadata) = &stage_metadata {
+                    let up_axis = match metadata.up_axis {
+                        UpAxis::Y => "Y",
+                        UpAxis::Z => "Z",
+                    };
+                    ui.label(format!(
+                        "upAxis: {up_axis}, metersPerUnit: {}, timeCodesPerSecond: {}",
+                        metadata.meters_per_unit, metadata.time_codes_per_second
+                    ));
+                }
                 egui::ComboBox::from_label("Render Settings Paths")
                     .selected_text(
                         render_settings_path
@@ -93,11 +124,47 @@ impl EguiRenderer {
                         ui.separator();
                         ui.selectable_value(render_product_path, None, "Clear Selection");
                     });
+                if !variant_sets.is_empty() {
+                    ui.collapsing("Variants", |ui| {
+                        for (path, sets) in variant_sets.iter_mut() {
+                            ui.label(path.as_str());
+                            for set in sets {
+                                egui::ComboBox::from_id_source((path.as_str(), set.name.as_str()))
+                                    .selected_text(
+                                        set.selection
+                                            .clone()
+                                            .unwrap_or(String::from("Not selected")),
+                                    )
+                                    .show_ui(ui, |ui| {
+                                        for variant in &set.variants {
+                                            ui.selectable_value(
+                                                &mut set.selection,
+                                                Some(variant.to_owned()),
+                                                variant,
+                                            );
+                                        }
+                                    });
+                                ui.label(set.name.as_str());
+                            }
+                        }
+                    });
+                }
                 ui.scope(|ui| {
                     ui.style_mut().spacing.slider_width = 400.0;
                     ui.horizontal(|ui| {
                         ui.label("Time Code: ");
-                        ui.add(egui::Slider::new(time_code, time_code_range));
+                        ui.add(egui::Slider::new(time_code, time_code_range.clone()));
+                    });
+                    ui.horizontal(|ui| {
+                        let label = if *playing { "Pause" } else { "Play" };
+                        if ui.button(label).clicked() {
+                            *playing = !*playing;
+                            // 最後まで再生し終わっていたら先頭から再生し直す
+                            if *playing && *time_code >= *time_code_range.end() {
+                                *time_code = *time_code_range.start();
+                            }
+                        }
+                        ui.checkbox(looping, "Loop");
                     });
                 });
             });
@@ -147,4 +214,35 @@ impl EguiRenderer {
             self.egui_renderer.free_texture(x)
         }
     }
+
+    // 再生中であれば、前のフレームからの経過時間とtimeCodesPerSecondからtime codeを進める。
+    // 経過時間はwall-clockで測るので、extractが間に合わない場合は途中のtime codeが飛ばされる。
+    // 終端に達したらloopが有効なら先頭に戻り、そうでなければ終端で停止する。
+    fn advance_time_code(
+        &mut self,
+        time_code: &mut f64,
+        time_code_range: &std::ops::RangeInclusive<f64>,
+        time_codes_per_second: f64,
+    ) {
+        if !self.playing {
+            self.last_frame_instant = None;
+            return;
+        }
+        let now = Instant::now();
+        let Some(last_frame_instant) = self.last_frame_instant.replace(now) else {
+            return;
+        };
+
+        let (start, end) = (*time_code_range.start(), *time_code_range.end());
+        let elapsed = now.duration_since(last_frame_instant).as_secs_f64();
+        let next = *time_code + elapsed * time_codes_per_second;
+        *time_code = if next <= end {
+            next
+        } else if self.looping && end > start {
+            start + (next - start) % (end - start)
+        } else {
+            self.playing = false;
+            end
+        };
+    }
 }
@@ -5,23 +5,38 @@ use std::sync::Arc;
 use usd_data_extractor::*;
 use wgpu::util::DeviceExt;
 
-use crate::renderer::{RenderDirectionalLight, RenderPointLight, RenderSpotLight};
+use crate::renderer::{
+    InstanceTransform, RenderDirectionalLight, RenderPointLight, RenderSpotLight,
+};
 
 #[derive(Debug)]
 struct RenderSubMeshData {
     index_buffer: wgpu::Buffer,
     indices_count: u32,
+    index_format: wgpu::IndexFormat,
     material_buffer: wgpu::Buffer,
     material_path: Option<String>,
 }
 
+fn index_format(indices: &SubMeshIndices) -> wgpu::IndexFormat {
+    match indices {
+        SubMeshIndices::U16(_) => wgpu::IndexFormat::Uint16,
+        SubMeshIndices::U32(_) => wgpu::IndexFormat::Uint32,
+    }
+}
+
 #[derive(Debug)]
 struct RenderMeshData {
     device: Arc<wgpu::Device>,
     queue: Arc<wgpu::Queue>,
     transform_buffer: wgpu::Buffer,
+    // instanceごとのtransform matrix。instancerのprototypeでないmeshはidentityの一つだけ
+    instance_buffer: wgpu::Buffer,
+    instance_count: u32,
     vertex_buffer: Option<wgpu::Buffer>,
     vertex_count: u32,
+    // pointsだけの更新で頂点位置と法線を書き換えるための、vertex bufferと同じ内容
+    vertices: Vec<Vertex>,
     sub_meshes: Vec<RenderSubMeshData>,
 }
 impl RenderMeshData {
@@ -32,12 +47,22 @@ impl RenderMeshData {
             contents: bytemuck::cast_slice(&data),
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
+        let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Instance Buffer"),
+            contents: bytemuck::cast_slice(&[InstanceTransform {
+                matrix: glam::Mat4::IDENTITY,
+            }]),
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        });
         Self {
             device,
             queue,
             transform_buffer,
+            instance_buffer,
+            instance_count: 1,
             vertex_buffer: None,
             vertex_count: 0,
+            vertices: Vec::new(),
             sub_meshes: Vec::new(),
         }
     }
@@ -51,6 +76,33 @@ impl RenderMeshData {
         );
     }
 
+    fn update_instances(&mut self, transforms: &[glam::Mat4]) {
+        // instanceがない場合は描画しないので、bufferはそのままにしておく
+        if transforms.is_empty() {
+            self.instance_count = 0;
+            return;
+        }
+        let data = transforms
+            .iter()
+            .map(|&matrix| InstanceTransform { matrix })
+            .collect::<Vec<_>>();
+        // instanceの数が変わっていなければデータの更新のみ行い、
+        // 変わっていれば新しいバッファを生成しアップロードする
+        if self.instance_count == transforms.len() as u32 {
+            self.queue
+                .write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&data));
+        } else {
+            self.instance_buffer =
+                self.device
+                    .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                        label: Some("Instance Buffer"),
+                        contents: bytemuck::cast_slice(&data),
+                        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                    });
+        }
+        self.instance_count = transforms.len() as u32;
+    }
+
     fn update_mesh_data(&mut self, mesh: MeshData) {
         // vertex bufferの更新
         if self.vertex_buffer.is_none() {
@@ -94,7 +146,7 @@ impl RenderMeshData {
                     self.device
                         .create_buffer_init(&wgpu::util::BufferInitDescriptor {
                             label: Some("Index Buffer"),
-                            contents: bytemuck::cast_slice(&sub_mesh.indices),
+                            contents: sub_mesh.indices.as_bytes(),
                             usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
                         });
                 let material_buffer =
@@ -107,6 +159,7 @@ impl RenderMeshData {
                 self.sub_meshes.push(RenderSubMeshData {
                     index_buffer,
                     indices_count: sub_mesh.indices.len() as u32,
+                    index_format: index_format(&sub_mesh.indices),
                     material_buffer,
                     material_path: sub_mesh.material.clone(),
                 });
@@ -115,29 +168,33 @@ impl RenderMeshData {
                 let RenderSubMeshData {
                     index_buffer,
                     indices_count,
+                    index_format: current_index_format,
                     material_path,
                     ..
                 } = &mut self.sub_meshes[index];
 
                 // index bufferの更新
-                // sub meshのindicesの要素数が変わっていなければデータの更新のみ行い、
-                // 要素数が変わっていれば新しいバッファを生成しアップロードする
-                if *indices_count == sub_mesh.indices.len() as u32 {
-                    self.queue.write_buffer(
-                        index_buffer,
-                        0,
-                        bytemuck::cast_slice(&sub_mesh.indices),
-                    );
+                // sub meshのindicesの要素数と型が変わっていなければデータの更新のみ行い、
+                // 変わっていれば新しいバッファを生成しアップロードする。
+                // write_bufferは4byte単位でしか書き込めないので、
+                // u16のindicesが奇数個の場合もバッファを生成し直す
+                let bytes = sub_mesh.indices.as_bytes();
+                if *indices_count == sub_mesh.indices.len() as u32
+                    && *current_index_format == index_format(&sub_mesh.indices)
+                    && bytes.len() as u64 % wgpu::COPY_BUFFER_ALIGNMENT == 0
+                {
+                    self.queue.write_buffer(index_buffer, 0, bytes);
                 } else {
                     let buf = self
                         .device
                         .create_buffer_init(&wgpu::util::BufferInitDescriptor {
                             label: Some("Index Buffer"),
-                            contents: bytemuck::cast_slice(&sub_mesh.indices),
+                            contents: sub_mesh.indices.as_bytes(),
                             usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
                         });
                     *index_buffer = buf;
                     *indices_count = sub_mesh.indices.len() as u32;
+                    *current_index_format = index_format(&sub_mesh.indices);
                 }
 
                 // material pathの更新
@@ -148,14 +205,32 @@ impl RenderMeshData {
                 }
             }
         }
+
+        self.vertices = mesh.vertices;
+    }
+
+    fn update_mesh_points(&mut self, points: MeshPoints) {
+        // 頂点数が変わっていなければ、頂点位置と法線を書き換えてvertex buffer全体を更新する
+        let Some(vertex_buffer) = &self.vertex_buffer else {
+            return;
+        };
+        if self.vertices.len() != points.positions.len() {
+            return;
+        }
+        points.write_vertices(&mut self.vertices);
+        self.queue
+            .write_buffer(vertex_buffer, 0, bytemuck::cast_slice(&self.vertices));
     }
 }
 
 pub struct RenderMesh<'a> {
     pub transform_matrix_buffer: &'a wgpu::Buffer,
+    pub instance_buffer: &'a wgpu::Buffer,
+    pub instance_count: u32,
     pub vertex_buffer: &'a wgpu::Buffer,
     pub index_buffer: &'a wgpu::Buffer,
     pub index_count: u32,
+    pub index_format: wgpu::IndexFormat,
     pub material_buffer: &'a wgpu::Buffer,
     pub diffuse_texture: &'a wgpu::TextureView,
     pub diffuse_sampler: &'a wgpu::Sampler,
@@ -530,6 +605,24 @@ impl RenderScene {
         }
     }
 
+    pub fn update_mesh_points(&mut self, name: String, points: MeshPoints) {
+        if let Some(mesh_data) = self.meshes.get_mut(&name) {
+            mesh_data.update_mesh_points(points);
+        }
+    }
+
+    pub fn update_mesh_instances(&mut self, name: String, instances: MeshInstances) {
+        if let Some(mesh_data) = self.meshes.get_mut(&name) {
+            mesh_data.update_instances(&instances.transforms);
+        }
+    }
+
+    pub fn remove_mesh_instances(&mut self, name: String) {
+        if let Some(mesh_data) = self.meshes.get_mut(&name) {
+            mesh_data.update_instances(&[glam::Mat4::IDENTITY]);
+        }
+    }
+
     pub fn insert_sphere_light(&mut self, name: String, light: SphereLight) {
         self.sphere_lights.insert(name, light);
     }
@@ -597,12 +690,13 @@ impl RenderScene {
     pub fn get_meshes<'a>(&'a self) -> Vec<RenderMesh<'a>> {
         self.meshes
             .iter()
-            .filter(|(_, mesh)| mesh.vertex_buffer.is_some())
+            .filter(|(_, mesh)| mesh.vertex_buffer.is_some() && mesh.instance_count > 0)
             .flat_map(|(_, mesh)| {
                 mesh.sub_meshes.iter().map(move |sub_mesh| {
                     let RenderSubMeshData {
                         index_buffer,
                         indices_count: count,
+                        index_format,
                         material_path,
                         material_buffer,
                     } = sub_mesh;
@@ -728,9 +822,12 @@ impl RenderScene {
 
                             return RenderMesh {
                                 transform_matrix_buffer: &mesh.transform_buffer,
+                                instance_buffer: &mesh.instance_buffer,
+                                instance_count: mesh.instance_count,
                                 vertex_buffer: mesh.vertex_buffer.as_ref().unwrap(),
                                 index_buffer,
                                 index_count: *count,
+                                index_format: *index_format,
                                 material_buffer,
                                 diffuse_texture,
                                 diffuse_sampler,
@@ -770,9 +867,12 @@ impl RenderScene {
 
                     RenderMesh {
                         transform_matrix_buffer: &mesh.transform_buffer,
+                        instance_buffer: &mesh.instance_buffer,
+                        instance_count: mesh.instance_count,
                         vertex_buffer: mesh.vertex_buffer.as_ref().unwrap(),
                         index_buffer,
                         index_count: *count,
+                        index_format: *index_format,
                         material_buffer,
                         diffuse_texture: &self.dummy_texture_view,
                         diffuse_sampler: &self.dummy_sampler,
@@ -1,5 +1,5 @@
 use bytemuck::Zeroable;
-use glam::{Mat4, Vec2, Vec3};
+use glam::{Mat4, Vec2, Vec3, Vec4};
 use std::sync::Arc;
 use wgpu::{CommandEncoder, TextureView};
 
@@ -38,6 +38,44 @@ impl Vertex {
     }
 }
 
+// instanceごとのtransform matrix。vertex bufferとしてinstanceごとに読む
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+pub struct InstanceTransform {
+    pub matrix: Mat4,
+}
+impl InstanceTransform {
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        // mat4x4は4つのvec4の頂点属性として渡す
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<InstanceTransform>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 3,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<Vec4>() as wgpu::BufferAddress,
+                    shader_location: 4,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: (std::mem::size_of::<Vec4>() * 2) as wgpu::BufferAddress,
+                    shader_location: 5,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: (std::mem::size_of::<Vec4>() * 3) as wgpu::BufferAddress,
+                    shader_location: 6,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+            ],
+        }
+    }
+}
+
 #[derive(Debug, Default, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
 #[repr(C)]
 pub struct Material {
@@ -363,7 +401,7 @@ impl Renderer {
             vertex: wgpu::VertexState {
                 module: &shader,
                 entry_point: "vs_main",
-                buffers: &[Vertex::desc()],
+                buffers: &[Vertex::desc(), InstanceTransform::desc()],
             },
             fragment: Some(wgpu::FragmentState {
                 module: &shader,
@@ -455,6 +493,7 @@ impl Renderer {
         });
 
         {
+            // シーンはY-upの座標系に変換して読み込んでいるので、上方向は常にY
             let camera = scene.get_camera();
             let camera = Camera {
                 view: Mat4::look_at_rh(camera.eye, camera.eye + camera.dir, Vec3::Y),
@@ -584,8 +623,9 @@ impl Renderer {
             render_pass.set_bind_group(2, &self.transform_matrix_bind_groups[i], &[]);
             render_pass.set_bind_group(3, &self.material_bind_groups[i], &[]);
             render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
-            render_pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
-            render_pass.draw_indexed(0..mesh.index_count, 0, 0..1);
+            render_pass.set_vertex_buffer(1, mesh.instance_buffer.slice(..));
+            render_pass.set_index_buffer(mesh.index_buffer.slice(..), mesh.index_format);
+            render_pass.draw_indexed(0..mesh.index_count, 0, 0..mesh.instance_count);
         }
     }
 }
@@ -11,8 +11,8 @@ use crate::render_scene::RenderScene;
 
 #[derive(Debug)]
 pub struct TimeCodeRange {
-    pub start: i64,
-    pub end: i64,
+    pub start: f64,
+    pub end: f64,
 }
 
 #[derive(Debug)]
@@ -27,13 +27,20 @@ struct SyncItems {
     scene: RenderScene,
     render_settings: UsdRenderSettings,
     time_code_range: Option<TimeCodeRange>,
+    stage_metadata: Option<StageMetadata>,
+    variant_sets: Vec<(String, Vec<VariantSet>)>,
 }
 
 enum UsdSceneExtractorMessage {
     LoadUsd(String),
-    SetTimeCode(i64),
+    SetTimeCode(f64),
     SetActiveRenderSettings(Option<String>),
     SetActiveRenderProduct(Option<String>),
+    SetVariantSelection {
+        path: String,
+        variant_set: String,
+        variant: String,
+    },
     Stop,
 }
 
@@ -42,6 +49,7 @@ struct UsdSceneExtractorTask {
     queue: Arc<wgpu::Queue>,
 
     usd_data_extractor: Option<UsdSceneExtractor>,
+    time_code: f64,
 
     sync_items: Arc<Mutex<SyncItems>>,
 }
@@ -60,52 +68,53 @@ impl UsdSceneExtractorTask {
                 device,
                 queue,
                 usd_data_extractor: None,
+                time_code: 0.0,
                 sync_items,
             };
 
+            let mut pending_message = None;
             loop {
-                let mut filename = None;
-                let mut time_code = None;
-                let mut active_render_settings_path = None;
-                let mut active_render_product_path = None;
-                while let Ok(message) = receiver.recv() {
-                    match message {
-                        UsdSceneExtractorMessage::LoadUsd(file) => {
-                            filename = Some(file);
-                            break;
-                        }
-                        UsdSceneExtractorMessage::SetTimeCode(tc) => {
-                            time_code = Some(tc);
-                            break;
-                        }
-                        UsdSceneExtractorMessage::SetActiveRenderSettings(path) => {
-                            active_render_settings_path = Some(path);
-                            break;
-                        }
-                        UsdSceneExtractorMessage::SetActiveRenderProduct(path) => {
-                            active_render_product_path = Some(path);
-                            break;
-                        }
-                        UsdSceneExtractorMessage::Stop => {
-                            return;
+                let message = match pending_message.take() {
+                    Some(message) => message,
+                    None => match receiver.recv() {
+                        Ok(message) => message,
+                        Err(_) => return,
+                    },
+                };
+                match message {
+                    UsdSceneExtractorMessage::LoadUsd(filename) => {
+                        task.load_usd(&filename);
+                    }
+                    UsdSceneExtractorMessage::SetTimeCode(mut time_code) => {
+                        // 再生中にextractが間に合わずSetTimeCodeが溜まった場合は、
+                        // 最新のtime_codeだけを処理して途中のフレームを落とす。
+                        while let Ok(message) = receiver.try_recv() {
+                            match message {
+                                UsdSceneExtractorMessage::SetTimeCode(tc) => time_code = tc,
+                                message => {
+                                    pending_message = Some(message);
+                                    break;
+                                }
+                            }
                         }
+                        task.set_time_code(time_code);
+                    }
+                    UsdSceneExtractorMessage::SetActiveRenderSettings(path) => {
+                        task.set_active_render_settings_path(path);
+                    }
+                    UsdSceneExtractorMessage::SetActiveRenderProduct(path) => {
+                        task.set_active_render_product_path(path);
+                    }
+                    UsdSceneExtractorMessage::SetVariantSelection {
+                        path,
+                        variant_set,
+                        variant,
+                    } => {
+                        task.set_variant_selection(&path, &variant_set, &variant);
+                    }
+                    UsdSceneExtractorMessage::Stop => {
+                        return;
                     }
-                }
-
-                if let Some(filename) = filename {
-                    task.load_usd(&filename);
-                }
-
-                if let Some(time_code) = time_code {
-                    task.set_time_code(time_code);
-                }
-
-                if let Some(path) = active_render_settings_path {
-                    task.set_active_render_settings_path(path);
-                }
-
-                if let Some(path) = active_render_product_path {
-                    task.set_active_render_product_path(path);
                 }
             }
         })
@@ -115,8 +124,18 @@ impl UsdSceneExtractorTask {
     // 新しくUsdDataExtractorを作成し、syncしているシーン情報などを初期化する。
     fn load_usd(&mut self, filename: &str) {
         let mut sync_items = self.sync_items.lock().unwrap();
+        // Z-upやセンチメートル単位のシーンも同じように表示できるように、
+        // Y-upでメートル単位の座標系に変換して読み込む。
+        // normal mappingはしないのでtangentは生成せず、GPUに送る頂点を減らすためにweldする。
+        // GPUでのskinningには対応していないので、skinningやblend shapeはCPUで適用する
         self.usd_data_extractor = UsdSceneExtractor::new(filename)
-            .inspect_err(|_| eprintln!("Failed to open USD file: {filename}"))
+            .map(|e| {
+                e.with_y_up_meters(true)
+                    .with_tangents(false)
+                    .with_vertex_welding(true)
+                    .with_cpu_skinning(true)
+            })
+            .inspect_err(|e| eprintln!("Failed to open USD file: {filename}: {e}"))
             .ok();
         let (start, end) = self
             .usd_data_extractor
@@ -129,22 +148,29 @@ impl UsdSceneExtractorTask {
             active_settings_path: None,
             active_product_path: None,
         };
-        sync_items.time_code_range = Some(TimeCodeRange {
-            start: start as i64,
-            end: end as i64,
-        });
+        sync_items.time_code_range = Some(TimeCodeRange { start, end });
+        sync_items.stage_metadata = self
+            .usd_data_extractor
+            .as_ref()
+            .map(|e| e.stage_metadata().clone());
+        sync_items.variant_sets = self.collect_variant_sets();
     }
 
     // 裏でusd読み込みのために走っているスレッドでtime_codeが変更された際に呼び出されるメソッド。
     // UsdDataExtractorからtime_codeに対応するデータを取得し、
     // UsdSceneExtractorのメンバ変数に反映する。
-    fn set_time_code(&mut self, time_code: i64) {
+    fn set_time_code(&mut self, time_code: f64) {
         let Some(usd_data_extractor) = &mut self.usd_data_extractor else {
             return;
         };
 
+        self.time_code = time_code;
+
         let mut sync_items = self.sync_items.lock().unwrap();
-        let diff = usd_data_extractor.extract(time_code as f64);
+        let diff = usd_data_extractor.extract(time_code);
+        for warning in &diff.warnings {
+            eprintln!("Skipped invalid prim: {warning}");
+        }
         for item in diff.items {
             match item {
                 SceneDiffItem::MeshCreated(path, transform_matrix, mesh_data) => {
@@ -163,6 +189,17 @@ impl UsdSceneExtractorTask {
                 SceneDiffItem::MeshDataDirtied(path, mesh_data) => {
                     sync_items.scene.update_mesh_data(path.into(), mesh_data);
                 }
+                SceneDiffItem::MeshPointsDirtied(path, points) => {
+                    sync_items.scene.update_mesh_points(path.into(), points);
+                }
+                SceneDiffItem::MeshInstancesDirtied(path, instances) => {
+                    sync_items
+                        .scene
+                        .update_mesh_instances(path.into(), instances);
+                }
+                SceneDiffItem::MeshInstancesDestroyed(path) => {
+                    sync_items.scene.remove_mesh_instances(path.into());
+                }
                 SceneDiffItem::SphereLightAddOrUpdate(path, light) => {
                     sync_items.scene.insert_sphere_light(path.into(), light);
                 }
@@ -197,10 +234,53 @@ impl UsdSceneExtractorTask {
                 SceneDiffItem::MaterialDestroyed(path) => {
                     sync_items.scene.remove_material(path.into());
                 }
+                // CPUでskinningしたmeshの頂点はMeshPointsDirtiedで届くので、
+                // Skeletonやblend shapeのweightの情報は使わない
+                SceneDiffItem::SkeletonAddOrUpdate(..)
+                | SceneDiffItem::SkeletonDestroyed(_)
+                | SceneDiffItem::SkeletonPoseDirtied(..)
+                | SceneDiffItem::MeshBlendShapeWeightsDirtied(..) => {}
             }
         }
     }
 
+    // 裏でusd読み込みのために走っているスレッドでSetVariantSelectionが呼ばれた際に呼び出されるメソッド。
+    // variantの選択を変更し、現在のtime_codeで再度extractしてvariantの切り替えによる差分をシーンに反映する。
+    // variantの切り替えで新しいvariant setが現れることがあるので、variant setの一覧も更新する。
+    fn set_variant_selection(&mut self, path: &str, variant_set: &str, variant: &str) {
+        let Some(usd_data_extractor) = &mut self.usd_data_extractor else {
+            return;
+        };
+        if let Err(e) = usd_data_extractor.set_variant_selection(path, variant_set, variant) {
+            eprintln!("Failed to set variant selection: {e}");
+            return;
+        }
+
+        self.set_time_code(self.time_code);
+
+        let variant_sets = self.collect_variant_sets();
+        self.sync_items.lock().unwrap().variant_sets = variant_sets;
+    }
+
+    // stage上のvariant setを持つprimのパスと、そのvariant setの一覧を集める。
+    fn collect_variant_sets(&self) -> Vec<(String, Vec<VariantSet>)> {
+        let Some(usd_data_extractor) = &self.usd_data_extractor else {
+            return vec![];
+        };
+        let mut paths = usd_data_extractor.variant_set_prim_paths();
+        paths.sort();
+        paths
+            .into_iter()
+            .filter_map(|path| {
+                let variant_sets = usd_data_extractor
+                    .variant_sets(&path)
+                    .inspect_err(|e| eprintln!("Failed to get variant sets: {e}"))
+                    .ok()?;
+                Some((path, variant_sets))
+            })
+            .collect()
+    }
+
     // 裏でusd読み込みのために走っているスレッドでSetActiveRenderSettingsが呼ばれた際に呼び出されるメソッド。
     // 渡されたpathがステージに存在しているかを確認している。
     // 存在する場合はRenderSettingsをactiveに設定する。
@@ -291,6 +371,8 @@ impl SceneLoader {
             scene,
             render_settings,
             time_code_range: None,
+            stage_metadata: None,
+            variant_sets: vec![],
         }));
         let (message_sender, message_receiver) = channel();
 
@@ -310,19 +392,24 @@ impl SceneLoader {
             .unwrap();
     }
 
-    pub fn set_time_code(&self, time_code: i64) {
+    pub fn set_time_code(&self, time_code: f64) {
         self.message_sender
             .send(UsdSceneExtractorMessage::SetTimeCode(time_code))
             .unwrap();
     }
 
-    pub fn get_time_code_range(&self) -> (i64, i64) {
+    pub fn get_time_code_range(&self) -> (f64, f64) {
         let sync_item = self.sync_item.lock().unwrap();
         sync_item
             .time_code_range
             .as_ref()
-            .map(|range| (range.start as i64, range.end as i64))
-            .unwrap_or((0, 0))
+            .map(|range| (range.start, range.end))
+            .unwrap_or((0.0, 0.0))
+    }
+
+    pub fn get_stage_metadata(&self) -> Option<StageMetadata> {
+        let sync_item = self.sync_item.lock().unwrap();
+        sync_item.stage_metadata.clone()
     }
 
     pub fn read_scene(&self, f: impl FnOnce(&RenderScene)) {
@@ -384,6 +471,21 @@ impl SceneLoader {
         let sync_item = self.sync_item.lock().unwrap();
         sync_item.render_settings.active_product_path.clone()
     }
+
+    pub fn get_variant_sets(&self) -> Vec<(String, Vec<VariantSet>)> {
+        let sync_item = self.sync_item.lock().unwrap();
+        sync_item.variant_sets.clone()
+    }
+
+    pub fn set_variant_selection(&self, path: &str, variant_set: &str, variant: &str) {
+        self.message_sender
+            .send(UsdSceneExtractorMessage::SetVariantSelection {
+                path: path.to_string(),
+                variant_set: variant_set.to_string(),
+                variant: variant.to_string(),
+            })
+            .unwrap();
+    }
 }
 impl Drop for SceneLoader {
     fn drop(&mut self) {
@@ -84,6 +84,13 @@ struct VertexInput {
     @location(2) uv: vec2<f32>,
 };
 
+struct InstanceInput {
+    @location(3) transform_0: vec4<f32>,
+    @location(4) transform_1: vec4<f32>,
+    @location(5) transform_2: vec4<f32>,
+    @location(6) transform_3: vec4<f32>,
+};
+
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) view_position: vec3<f32>,
@@ -110,8 +117,8 @@ fn inverse(m: mat3x3<f32>) -> mat3x3<f32> {
     ) * inv_det;
 }
 
-fn model_to_view_normal(n: vec3<f32>) -> vec3<f32> {
-    let m4x4 = camera.view * model.model;
+fn model_to_view_normal(instance: mat4x4<f32>, n: vec3<f32>) -> vec3<f32> {
+    let m4x4 = camera.view * instance * model.model;
     let m = mat3x3<f32>(m4x4[0].xyz, m4x4[1].xyz, m4x4[2].xyz);
     return normalize(transpose(inverse(m)) * n);
 }
@@ -127,12 +134,19 @@ fn world_to_view_vector(v: vec3<f32>) -> vec3<f32> {
 @vertex
 fn vs_main(
     vin: VertexInput,
+    instance_in: InstanceInput,
 ) -> VertexOutput {
     var out: VertexOutput;
-    let pos = camera.view * model.model * vec4<f32>(vin.position, 1.0);
+    let instance = mat4x4<f32>(
+        instance_in.transform_0,
+        instance_in.transform_1,
+        instance_in.transform_2,
+        instance_in.transform_3,
+    );
+    let pos = camera.view * instance * model.model * vec4<f32>(vin.position, 1.0);
     out.clip_position = camera.projection * pos;
     out.view_position = pos.xyz / pos.w;
-    out.view_normal = model_to_view_normal(vin.normal);
+    out.view_normal = model_to_view_normal(instance, vin.normal);
     out.uv = vin.uv;
     return out;
 }
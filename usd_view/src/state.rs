@@ -17,7 +17,7 @@ pub struct State<'a> {
     scene_loader: SceneLoader,
 
     usd_filename: String,
-    usd_time_code: i64,
+    usd_time_code: f64,
 }
 
 impl<'a> State<'a> {
@@ -97,7 +97,7 @@ impl<'a> State<'a> {
             scene_loader,
 
             usd_filename: String::new(),
-            usd_time_code: 1,
+            usd_time_code: 1.0,
         }
     }
 
@@ -145,12 +145,15 @@ impl<'a> State<'a> {
         let time_code_range = (time_code_range.0)..=(time_code_range.1);
         let prev_time_code = self.usd_time_code;
         let mut load_button_clicked = false;
+        let stage_metadata = self.scene_loader.get_stage_metadata();
         let render_settings_paths = self.scene_loader.get_render_settings_paths();
         let mut active_render_settings_path = self.scene_loader.get_active_render_settings_path();
         let prev_active_render_settings_path = active_render_settings_path.clone();
         let render_product_paths = self.scene_loader.get_render_product_paths();
         let mut active_render_product_path = self.scene_loader.get_active_render_product_path();
         let prev_active_render_product_path = active_render_product_path.clone();
+        let mut variant_sets = self.scene_loader.get_variant_sets();
+        let prev_variant_sets = variant_sets.clone();
         self.egui_renderer.render(
             window,
             &view,
@@ -159,13 +162,15 @@ impl<'a> State<'a> {
             &mut self.usd_time_code,
             time_code_range,
             &mut load_button_clicked,
+            stage_metadata,
             render_settings_paths,
             &mut active_render_settings_path,
             render_product_paths,
             &mut active_render_product_path,
+            &mut variant_sets,
         );
         if load_button_clicked {
-            self.usd_time_code = 1;
+            self.usd_time_code = 1.0;
             self.scene_loader.load_usd(&self.usd_filename);
             self.scene_loader.set_time_code(self.usd_time_code);
         }
@@ -180,6 +185,16 @@ impl<'a> State<'a> {
             let path = active_render_product_path.as_deref();
             self.scene_loader.set_active_render_product_path(path);
         }
+        for ((path, sets), (_, prev_sets)) in variant_sets.iter().zip(&prev_variant_sets) {
+            for (set, prev_set) in sets.iter().zip(prev_sets) {
+                if set.selection != prev_set.selection {
+                    if let Some(variant) = &set.selection {
+                        self.scene_loader
+                            .set_variant_selection(path, &set.name, variant);
+                    }
+                }
+            }
+        }
 
         // submit will accept anything that implements IntoIter
         self.queue.submit(std::iter::once(encoder.finish()));